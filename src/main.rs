@@ -1,8 +1,29 @@
-use std::io;
+use std::{env, io, process};
+use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::time::SystemTime;
-use crate::renderer::render;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+use crate::renderer::{benchmark, film_window, frame_size, render_image, scene, Image, RenderSettings};
+use crate::renderer::adaptive::AdaptiveSampling;
+use crate::renderer::animation::{frame_shutter, Sequence};
+use crate::renderer::aov::{self, Aov, AovFormat, Aovs, Surface, AOVS, AOV_FORMATS};
+use crate::renderer::camera::Shutter;
+use crate::renderer::checkpoint;
+use crate::renderer::denoise::Denoiser;
+use crate::renderer::color_space::{Display, OutputTransform, COLOR_SPACES, DISPLAYS};
+use crate::renderer::filter::FILTER_KINDS;
+use crate::renderer::framebuffer::{Framebuffer, Window};
+use crate::renderer::hit::Hittable;
+use crate::renderer::png;
+use crate::renderer::progress::{ProgressBar, StatisticsCollector};
+use crate::renderer::progressive::{render_progressive, Progressive};
+use crate::renderer::sampler::SAMPLER_KINDS;
+use crate::renderer::stats::Statistics;
+use crate::renderer::tiles::TILE_ORDERS;
+use crate::renderer::tone_mapping::{ToneMapper, ToneMapping, TONE_MAPPERS};
+use crate::renderer::vector3d::Point;
+use crate::renderer::voxel_grid::VoxelGrid;
 
 mod renderer;
 
@@ -13,7 +34,7 @@ fn get_input(message: &'static str) -> bool {
         .read_line(&mut input)
         .expect("Failed to read line");
     let parsed = input.trim();
-    return match parsed {
+    match parsed {
         "y" | "" => true,
         "n" => false,
         _ => {
@@ -23,24 +44,536 @@ fn get_input(message: &'static str) -> bool {
     }
 }
 
+struct Options {
+    scene: String,
+    atmosphere: Option<f64>,
+    volume: Option<String>,
+    benchmark: Option<u32>,
+    progressive: Option<Progressive>,
+    checkpoint: Option<String>,
+    resume: bool,
+    patch_into: Option<String>,
+    stats_json: bool,
+    // override the scene's own tone mapping
+    exposure: Option<f64>,
+    tone_mapper: Option<ToneMapper>,
+    white: Option<f64>,
+    display: Display,
+    aovs: Vec<Aov>,
+    aov_format: AovFormat,
+    denoise: bool,
+    // the scene's own unless --shutter overrides it, in frames from the frame's start
+    shutter: Shutter,
+    // first and last frame of an image sequence
+    frames: Option<(u32, u32)>,
+    fps: f64,
+    settings: RenderSettings,
+}
+
+fn value_of<T: std::str::FromStr>(flag: &str, value: Option<String>, expected: &str) -> T {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(parsed)) => parsed,
+        _ => {
+            eprintln!("{} expects {}", flag, expected);
+            process::exit(2);
+        }
+    }
+}
+
+fn progressive_or_default(options: &Options) -> Progressive {
+    options.progressive.unwrap_or(Progressive::new(1))
+}
+
+fn parse_options() -> Options {
+    let mut args = env::args().skip(1);
+    // overrides the scene's own
+    let mut shutter = None;
+    let mut options = Options {
+        scene: String::from("spheres"),
+        atmosphere: None,
+        volume: None,
+        benchmark: None,
+        progressive: None,
+        checkpoint: None,
+        resume: false,
+        patch_into: None,
+        stats_json: false,
+        exposure: None,
+        tone_mapper: None,
+        white: None,
+        display: Display::Srgb,
+        aovs: Vec::new(),
+        aov_format: AovFormat::Exr,
+        denoise: false,
+        shutter: Shutter::default(),
+        frames: None,
+        fps: 24.0,
+        settings: RenderSettings::default(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scene" => {
+                let expected = format!("one of {:?}", scene::SCENE_NAMES);
+                options.scene = value_of(&arg, args.next(), &expected);
+            }
+            "--atmosphere" => options.atmosphere = Some(value_of(&arg, args.next(), "a density")),
+            "--volume" => options.volume = Some(value_of(&arg, args.next(), "an NRRD file")),
+            "--width" => options.settings.width = value_of(&arg, args.next(), "a width in pixels"),
+            "--height" => options.settings.height = Some(value_of(&arg, args.next(), "a height in pixels")),
+            "--pixel-aspect" => options.settings.pixel_aspect = value_of(&arg, args.next(), "a pixel aspect ratio"),
+            "--overscan" => options.settings.overscan = value_of(&arg, args.next(), "a margin in pixels"),
+            "--seed" => options.settings.seed = value_of(&arg, args.next(), "an unsigned integer"),
+            "--threads" => options.settings.threads = value_of(&arg, args.next(), "a thread count"),
+            "--samples" => options.settings.samples_per_pixel = value_of(&arg, args.next(), "a sample count"),
+            "--depth" => options.settings.depth = value_of(&arg, args.next(), "a bounce count"),
+            "--diffuse-depth" => options.settings.lobe_depths.diffuse = Some(value_of(&arg, args.next(), "a bounce count")),
+            "--glossy-depth" => options.settings.lobe_depths.glossy = Some(value_of(&arg, args.next(), "a bounce count")),
+            "--transmission-depth" => options.settings.lobe_depths.transmission = Some(value_of(&arg, args.next(), "a bounce count")),
+            "--volume-depth" => options.settings.lobe_depths.volume = Some(value_of(&arg, args.next(), "a bounce count")),
+            "--roulette-depth" => options.settings.roulette = Some(value_of(&arg, args.next(), "a bounce count")),
+            "--no-roulette" => options.settings.roulette = None,
+            "--sampler" => {
+                let expected = format!("one of {:?}", SAMPLER_KINDS.map(|kind| kind.to_string()));
+                options.settings.sampler = value_of(&arg, args.next(), &expected);
+            }
+            "--filter" => {
+                let expected = format!("one of {:?}", FILTER_KINDS.map(|kind| kind.to_string()));
+                options.settings.filter = value_of(&arg, args.next(), &expected);
+            }
+            "--filter-radius" => options.settings.filter_radius = Some(value_of(&arg, args.next(), "a radius in pixels")),
+            "--blue-noise" => options.settings.blue_noise = true,
+            "--adaptive" => {
+                let tolerance = value_of(&arg, args.next(), "a noise tolerance");
+                let min_samples = options.settings.adaptive.map_or(4, |adaptive| adaptive.min_samples);
+                options.settings.adaptive = Some(AdaptiveSampling { tolerance, min_samples });
+            }
+            "--min-samples" => {
+                let min_samples = value_of(&arg, args.next(), "a sample count");
+                let tolerance = options.settings.adaptive.map_or(0.01, |adaptive| adaptive.tolerance);
+                options.settings.adaptive = Some(AdaptiveSampling { tolerance, min_samples });
+            }
+            "--progressive" => {
+                let pass_samples = value_of(&arg, args.next(), "the samples per pass");
+                options.progressive = Some(Progressive { pass_samples, ..progressive_or_default(&options) });
+            }
+            "--snapshot-interval" => {
+                let seconds = value_of(&arg, args.next(), "a number of seconds");
+                let snapshot_interval = Some(Duration::from_secs_f64(seconds));
+                options.progressive = Some(Progressive { snapshot_interval, ..progressive_or_default(&options) });
+            }
+            "--time-budget" => {
+                let seconds = value_of(&arg, args.next(), "a number of seconds");
+                let time_budget = Some(Duration::from_secs_f64(seconds));
+                options.progressive = Some(Progressive { time_budget, ..progressive_or_default(&options) });
+            }
+            "--noise-target" => {
+                let noise_target = Some(value_of(&arg, args.next(), "a noise level"));
+                options.progressive = Some(Progressive { noise_target, ..progressive_or_default(&options) });
+            }
+            "--checkpoint" => options.checkpoint = Some(value_of(&arg, args.next(), "a checkpoint file")),
+            "--resume" => {
+                options.checkpoint = Some(value_of(&arg, args.next(), "a checkpoint file"));
+                options.resume = true;
+            }
+            "--tile-size" => options.settings.tile_size = value_of(&arg, args.next(), "a tile size in pixels"),
+            "--tile-order" => {
+                let expected = format!("one of {:?}", TILE_ORDERS.map(|order| order.to_string()));
+                options.settings.tile_order = value_of(&arg, args.next(), &expected);
+            }
+            "--crop" => {
+                let expected = "the crop window's x, y, width and height in pixels";
+                options.settings.crop = Some(Window {
+                    x: value_of(&arg, args.next(), expected),
+                    y: value_of(&arg, args.next(), expected),
+                    width: value_of(&arg, args.next(), expected),
+                    height: value_of(&arg, args.next(), expected),
+                });
+            }
+            "--patch-into" => options.patch_into = Some(value_of(&arg, args.next(), "a full-frame checkpoint file")),
+            "--stats-json" => options.stats_json = true,
+            "--exposure" => options.exposure = Some(value_of(&arg, args.next(), "an exposure compensation in stops")),
+            "--tone-map" => {
+                let expected = format!("one of {:?}", TONE_MAPPERS.map(|mapper| mapper.to_string()));
+                options.tone_mapper = Some(value_of(&arg, args.next(), &expected));
+            }
+            "--white-point" => options.white = Some(value_of(&arg, args.next(), "a luminance")),
+            "--working-space" => {
+                let expected = format!("one of {:?}", COLOR_SPACES.map(|space| space.to_string()));
+                options.settings.working_space = value_of(&arg, args.next(), &expected);
+            }
+            "--spectral" => options.settings.spectral = true,
+            "--shutter" => {
+                let expected = "the times the shutter opens and closes, in frames";
+                shutter = Some(Shutter { open: value_of(&arg, args.next(), expected), close: value_of(&arg, args.next(), expected) });
+            }
+            "--clamp" => options.settings.fireflies.max_sample = Some(value_of(&arg, args.next(), "a luminance")),
+            "--clamp-indirect" => options.settings.fireflies.max_indirect = Some(value_of(&arg, args.next(), "a luminance")),
+            "--regularize" => options.settings.fireflies.regularize = Some(value_of(&arg, args.next(), "a roughness")),
+            "--display" => {
+                let expected = format!("one of {:?}", DISPLAYS.map(|display| display.to_string()));
+                options.display = value_of(&arg, args.next(), &expected);
+            }
+            "--aovs" => {
+                let expected = format!("'all' or a comma-separated list of {:?}", AOVS.map(|aov| aov.to_string()));
+                options.aovs = value_of::<Aovs>(&arg, args.next(), &expected).0;
+            }
+            "--aov-format" => {
+                let expected = format!("one of {:?}", AOV_FORMATS.map(|format| format.to_string()));
+                options.aov_format = value_of(&arg, args.next(), &expected);
+            }
+            "--denoise" => options.denoise = true,
+            "--frames" => {
+                let expected = "the first and last frame to render";
+                options.frames = Some((value_of(&arg, args.next(), expected), value_of(&arg, args.next(), expected)));
+            }
+            "--fps" => options.fps = value_of(&arg, args.next(), "a frame rate"),
+            "--benchmark-samplers" => {
+                options.benchmark = Some(value_of(&arg, args.next(), "the reference sample count"));
+            }
+            _ => {
+                eprintln!("Unknown argument '{}'", arg);
+                process::exit(2);
+            }
+        }
+    }
+    options.settings.light_paths = options.aovs.iter().any(|aov| aov.needs_light_paths());
+    options.shutter = shutter.unwrap_or(scene::shutter(&options.scene));
+    options
+}
+
+// how rendered images are written
+struct Output {
+    write_samples: bool,
+    transform: OutputTransform,
+}
+
+fn write_png(filename: &str, image: &Image, output: &Output) {
+    let path = format!("{}.png", filename);
+    let written = png::encode(image.width, image.height, &image.to_rgb8(&output.transform)).and_then(|bytes| fs::write(&path, bytes));
+    if let Err(error) = written {
+        eprintln!("Failed to write '{}': {}", path, error);
+    }
+}
+
+fn write_image(filename: &str, image: &Image, output: &Output) {
+    if output.write_samples {
+        let mut samples_file = File::create(format!("{}_spp.ppm", filename)).expect("Failed to create file");
+        samples_file.write_all(image.samples_to_ppm().as_bytes()).expect("Failed to write to file");
+    }
+    let mut rendered_file = File::create(format!("{}.ppm", filename)).expect("Failed to create file");
+    rendered_file.write_all(image.to_ppm(&output.transform).as_bytes()).expect("Failed to write to file");
+}
+
+// the denoiser and the AOVs share the first-hit feature buffers, so they are rendered once for both
+fn render_features(options: &Options, settings: &RenderSettings, world: &dyn Hittable) -> Vec<Surface> {
+    if options.denoise || !options.aovs.is_empty() {
+        aov::render_surfaces(settings, world)
+    } else {
+        Vec::new()
+    }
+}
+
+fn denoise(image: Image, features: &[Surface]) -> Image {
+    Image { pixels: Denoiser::default().apply(&image, features), ..image }
+}
+
+fn write_aovs(filename: &str, options: &Options, image: &Image, features: &[Surface]) {
+    match aov::write(filename, options.aov_format, &options.aovs, image, features) {
+        Ok(paths) => println!("AOVs written to {}", paths.join(", ")),
+        Err(error) => eprintln!("Failed to write AOVs: {}", error),
+    }
+}
+
+// the summary goes to stderr when the image itself was printed, so it doesn't end up in the image
+fn report_statistics(filename: &str, statistics: &Statistics, write_json: bool, image_printed: bool) {
+    if image_printed {
+        eprint!("{}", statistics.summary());
+    } else {
+        print!("{}", statistics.summary());
+    }
+    if write_json {
+        let path = format!("{}.stats.json", filename);
+        if let Err(error) = fs::write(&path, statistics.to_json()) {
+            eprintln!("Failed to write statistics '{}': {}", path, error);
+        }
+    }
+}
+
+// renders the frames one after another to <filename>.<frame>.png, with their AOVs next to them
+fn render_sequence(options: &Options, sequence: &Sequence, (first, last): (u32, u32), filename: &str, output: &Output, scene_time: Duration, bvh_time: Duration) {
+    let progress_bar = ProgressBar::default();
+    let collector = StatisticsCollector::new(&progress_bar);
+    let (mut frame_bvh_time, mut render_time, mut output_time) = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+    for frame in first..=last {
+        let shutter = frame_shutter(frame, options.fps, options.shutter);
+        let settings = sequence.settings(&options.settings, shutter);
+        let bvh_start = Instant::now();
+        let world = sequence.frame(shutter);
+        frame_bvh_time += bvh_start.elapsed();
+        println!("Rendering frame {} of {}-{}...", frame, first, last);
+        let render_start = Instant::now();
+        let mut image = render_image(&settings, &world, &collector);
+        let features = render_features(options, &settings, &world);
+        if options.denoise {
+            image = denoise(image, &features);
+        }
+        render_time += render_start.elapsed();
+        let output_start = Instant::now();
+        let name = format!("{}.{:04}", filename, frame);
+        write_png(&name, &image, output);
+        if !options.aovs.is_empty() {
+            write_aovs(&name, options, &image, &features);
+        }
+        output_time += output_start.elapsed();
+    }
+    let mut statistics = collector.into_statistics();
+    statistics.add_phase("scene build", scene_time);
+    statistics.add_phase("bvh build", bvh_time);
+    statistics.add_phase("frame bvh builds", frame_bvh_time);
+    statistics.add_phase("render", render_time);
+    statistics.add_phase("output", output_time);
+    println!("Frames written to {}.{:04}.png to {}.{:04}.png", filename, first, filename, last);
+    report_statistics(filename, &statistics, options.stats_json, false);
+}
+
+// copies a crop into the full-frame checkpoint it was cut from and rewrites that checkpoint's image
+fn patch_checkpoint(path: &str, fingerprint: u64, settings: &RenderSettings, region: &Framebuffer, output: &Output) {
+    let full_settings = RenderSettings { crop: None, ..*settings };
+    let patched = checkpoint::load(path, fingerprint, &full_settings).and_then(|mut full| {
+        full.patch(region).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        checkpoint::save(path, fingerprint, &full_settings, &full)?;
+        Ok(full)
+    });
+    match patched {
+        Ok(full) => {
+            let filename = Path::new(path).with_extension("").to_string_lossy().to_string();
+            println!("Patched into {}.ppm", filename);
+            write_image(&filename, &full.to_image(), output);
+        }
+        Err(error) => {
+            eprintln!("Cannot patch into '{}': {}", path, error);
+            process::exit(1);
+        }
+    }
+}
+
+// everything the world is built from, for the checkpoint fingerprint
+fn scene_description(options: &Options) -> Vec<u8> {
+    let mut description = format!("{} {:?} {:?}", options.scene, options.atmosphere, options.volume).into_bytes();
+    if let Some(path) = &options.volume {
+        description.extend(fs::read(path).unwrap_or_default());
+    }
+    description
+}
+
 fn main() {
+    let mut options = parse_options();
+    let (width, height) = frame_size(&options.settings);
+    if width == 0 || height == 0 || options.settings.pixel_aspect.is_nan() || options.settings.pixel_aspect <= 0.0 {
+        eprintln!("The frame must be at least one pixel wide and high, with a positive pixel aspect");
+        process::exit(2);
+    }
+    if options.settings.filter_radius.is_some_and(|radius| radius.is_nan() || radius <= 0.0) {
+        eprintln!("--filter-radius must be positive");
+        process::exit(2);
+    }
+    let full_frame = film_window(&RenderSettings { crop: None, ..options.settings });
+    if !full_frame.contains(&film_window(&options.settings)) {
+        eprintln!("--crop must lie within the {}x{} film", full_frame.width, full_frame.height);
+        process::exit(2);
+    }
+    if options.settings.depth < 1 {
+        eprintln!("--depth takes at least 1 bounce");
+        process::exit(2);
+    }
+    let fireflies = options.settings.fireflies;
+    if fireflies.max_sample.into_iter().chain(fireflies.max_indirect).any(|max| max <= 0.0) {
+        eprintln!("--clamp and --clamp-indirect take a positive luminance");
+        process::exit(2);
+    }
+    if fireflies.regularize.is_some_and(|roughness| !(roughness > 0.0 && roughness <= 1.0)) {
+        eprintln!("--regularize takes a roughness between 0 and 1");
+        process::exit(2);
+    }
+    let shutter = options.shutter;
+    if !(shutter.open.is_finite() && shutter.close.is_finite() && shutter.open <= shutter.close) {
+        eprintln!("--shutter must open no later than it closes");
+        process::exit(2);
+    }
+    if !(options.fps.is_finite() && options.fps > 0.0) {
+        eprintln!("--fps must be positive");
+        process::exit(2);
+    }
+    if options.frames.is_some_and(|(first, last)| first == 0 || first > last) {
+        eprintln!("--frames takes a first frame of at least 1 and no later than the last");
+        process::exit(2);
+    }
+    let single_image = options.progressive.is_some() || options.checkpoint.is_some() || options.patch_into.is_some() || options.benchmark.is_some();
+    if options.frames.is_some() && single_image {
+        eprintln!("--frames cannot be combined with progressive, checkpointed or benchmark renders");
+        process::exit(2);
+    }
+    if !options.aovs.is_empty() && (options.checkpoint.is_some() || options.patch_into.is_some()) {
+        eprintln!("--aovs cannot be combined with checkpoints, which do not keep them");
+        process::exit(2);
+    }
+    let scene_start = Instant::now();
+    let mut animation = match scene::by_name(&options.scene, options.settings.seed) {
+        Ok(animation) => animation,
+        Err(error) => {
+            eprintln!("Failed to build scene: {}", error);
+            process::exit(1);
+        }
+    };
+    if let Some(path) = &options.volume {
+        match VoxelGrid::from_nrrd(path) {
+            Ok(grid) => scene::add_volume(
+                &mut animation.still,
+                grid,
+                Point { x: -1.0, y: -1.0, z: -4.0 },
+                Point { x: 1.0, y: 1.0, z: -2.0 },
+                10.0,
+            ),
+            Err(error) => {
+                eprintln!("Failed to load volume '{}': {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(density) = options.atmosphere {
+        scene::add_atmosphere(&mut animation.still, density);
+    }
+    let scene_time = scene_start.elapsed();
+    let unix_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut filename = format!("render_{}", unix_time);
+    let scene_tone_mapping = match scene::tone_mapping(&options.scene) {
+        Ok(tone_mapping) => tone_mapping,
+        Err(error) => {
+            eprintln!("Failed to read the scene's view settings: {}", error);
+            process::exit(1);
+        }
+    };
+    let output = Output {
+        write_samples: options.settings.adaptive.is_some(),
+        transform: OutputTransform {
+            working: options.settings.working_space,
+            display: options.display,
+            tone_mapping: ToneMapping {
+                exposure: options.exposure.unwrap_or(scene_tone_mapping.exposure),
+                mapper: options.tone_mapper.unwrap_or(scene_tone_mapping.mapper),
+                white: options.white.unwrap_or(scene_tone_mapping.white),
+            },
+        },
+    };
+    if let Some(path) = &options.checkpoint {
+        // checkpointed renders write their images next to the checkpoint
+        filename = Path::new(path).with_extension("").to_string_lossy().to_string();
+    }
+    let bvh_start = Instant::now();
+    let sequence = animation.into_sequence();
+    if let Some(frames) = options.frames {
+        render_sequence(&options, &sequence, frames, &filename, &output, scene_time, bvh_start.elapsed());
+        return;
+    }
+    // a single image is the first frame
+    let shutter = frame_shutter(1, options.fps, options.shutter);
+    let world = sequence.frame(shutter);
+    options.settings = sequence.settings(&options.settings, shutter);
+    let bvh_time = bvh_start.elapsed();
+    if let Some(reference_samples) = options.benchmark {
+        println!("Comparing samplers at {} spp against a {} spp reference...", options.settings.samples_per_pixel, reference_samples);
+        for (sampler, error) in benchmark::convergence(&options.settings, &world, reference_samples) {
+            println!("{:<12} RMSE {:.6}", sampler.to_string(), error);
+        }
+        return;
+    }
+    if options.progressive.is_some() || options.checkpoint.is_some() || options.patch_into.is_some() {
+        let progressive = progressive_or_default(&options);
+        let fingerprint = checkpoint::fingerprint(&scene_description(&options), &options.settings);
+        let resume = if options.resume {
+            let path = options.checkpoint.as_ref().unwrap();
+            match checkpoint::load(path, fingerprint, &options.settings) {
+                Ok(framebuffer) => Some(framebuffer),
+                Err(error) => {
+                    eprintln!("Cannot resume from '{}': {}", path, error);
+                    process::exit(1);
+                }
+            }
+        } else {
+            None
+        };
+        let save = |framebuffer: &Framebuffer| {
+            write_image(&filename, &framebuffer.to_image(), &output);
+            if let Some(path) = &options.checkpoint {
+                if let Err(error) = checkpoint::save(path, fingerprint, &options.settings, framebuffer) {
+                    eprintln!("Failed to write checkpoint '{}': {}", path, error);
+                }
+            }
+        };
+        println!("Rendering progressively, snapshots go to {}.ppm...", filename);
+        let progress_bar = ProgressBar::default();
+        let collector = StatisticsCollector::new(&progress_bar);
+        let render_start = Instant::now();
+        let framebuffer = render_progressive(&options.settings, &world, &progressive, resume, &collector, save);
+        let render_time = render_start.elapsed();
+        let output_start = Instant::now();
+        save(&framebuffer);
+        let features = render_features(&options, &options.settings, &world);
+        let mut image = framebuffer.to_image();
+        if options.denoise {
+            // the checkpoint keeps the noisy image, which later passes refine
+            image = denoise(image, &features);
+            write_image(&filename, &image, &output);
+        }
+        if !options.aovs.is_empty() {
+            write_aovs(&filename, &options, &image, &features);
+        }
+        if let Some(path) = &options.patch_into {
+            patch_checkpoint(path, fingerprint, &options.settings, &framebuffer, &output);
+        }
+        let mut statistics = collector.into_statistics();
+        statistics.add_phase("scene build", scene_time);
+        statistics.add_phase("bvh build", bvh_time);
+        statistics.add_phase("render", render_time);
+        statistics.add_phase("output", output_start.elapsed());
+        report_statistics(&filename, &statistics, options.stats_json, false);
+        println!("Done!");
+        return;
+    }
     println!("Rendering...");
-    let rendered = render(720);
+    let progress_bar = ProgressBar::default();
+    let collector = StatisticsCollector::new(&progress_bar);
+    let render_start = Instant::now();
+    let mut image = render_image(&options.settings, &world, &collector);
+    let render_time = render_start.elapsed();
+    let denoise_start = Instant::now();
+    let features = render_features(&options, &options.settings, &world);
+    if options.denoise {
+        image = denoise(image, &features);
+    }
+    let denoise_time = denoise_start.elapsed();
+    let mut statistics = collector.into_statistics();
+    statistics.add_phase("scene build", scene_time);
+    statistics.add_phase("bvh build", bvh_time);
+    statistics.add_phase("render", render_time);
+    if options.denoise {
+        statistics.add_phase("denoise", denoise_time);
+    }
     if get_input("Write to file? (y/n)\tdefault: 'y'") {
         println!("Writing to file...");
-        let mut filename = String::new();
-        filename.push_str("render_");
-        let unix_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        filename.push_str(&unix_time.to_string());
-        filename.push_str(".ppm");
-        let mut rendered_file = File::create(filename).expect("Failed to create file");
-        rendered_file.write_all(rendered.as_bytes()).expect("Failed to write to file");
+        let output_start = Instant::now();
+        write_image(&filename, &image, &output);
+        if !options.aovs.is_empty() {
+            write_aovs(&filename, &options, &image, &features);
+        }
+        statistics.add_phase("output", output_start.elapsed());
+        report_statistics(&filename, &statistics, options.stats_json, false);
         println!("Done!");
     } else {
-        println!("{}", rendered);
+        println!("{}", image.to_ppm(&output.transform));
+        report_statistics(&filename, &statistics, options.stats_json, true);
     }
-
 }
@@ -1,88 +1,527 @@
 pub mod vector3d;
+pub mod aabb;
+pub mod bvh;
 pub mod ray;
 pub mod hit;
 pub mod sphere;
+pub mod motion;
+pub mod animation;
+pub mod polyhedron;
+pub mod constant_medium;
+pub mod heterogeneous_medium;
+pub mod voxel_grid;
 pub mod world;
 pub mod util;
 pub mod camera;
 pub mod color;
 pub mod material;
+pub mod texture;
+pub mod scene;
+pub mod sampler;
+pub mod benchmark;
+pub mod adaptive;
+pub mod framebuffer;
+pub mod filter;
+pub mod tone_mapping;
+pub mod color_space;
+pub mod spectrum;
+pub mod aov;
+pub mod exr;
+pub mod png;
+pub mod json;
+pub mod denoise;
+pub mod firefly;
+pub mod progressive;
+pub mod checkpoint;
+pub mod tiles;
+pub mod progress;
+pub mod stats;
 
-use std::rc::Rc;
-use rand::{Rng, random};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-use crate::renderer::camera::Camera;
-use crate::renderer::color::{ray_color, write_color};
-use crate::renderer::world::HittableList;
-use crate::renderer::sphere::Sphere;
-use crate::renderer::vector3d::{Color, Point};
-use crate::renderer::material::{Material, metal::Metal};
-use crate::renderer::material::lambert::Lambert;
+use crate::renderer::adaptive::AdaptiveSampling;
+use crate::renderer::camera::{Camera, CameraMotion, Shutter};
+use crate::renderer::aov::{LightPaths, PathLayer};
+use crate::renderer::color::{ray_color, to_bytes, write_color, LobeDepths, Rgb, Spectral};
+use crate::renderer::color_space::{ColorSpace, Conversion, OutputTransform};
+use crate::renderer::filter::{margin, FilterKind};
+use crate::renderer::firefly::FireflySuppression;
+use crate::renderer::framebuffer::{Framebuffer, Pixel, SplatTile, Window};
+use crate::renderer::hit::Hittable;
+use crate::renderer::progress::Progress;
+use crate::renderer::sampler::SamplerKind;
+use crate::renderer::ray::Ray;
+use crate::renderer::spectrum::Wavelengths;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::tiles::{schedule, TileOrder, TILE_SIZE};
+use crate::renderer::vector3d::Color;
+
+pub struct RenderSettings {
+    pub width: u32,
+    // 16:9 to the width when unset
+    pub height: Option<u32>,
+    // width over height of a single pixel, e.g. 2 for footage that will be desqueezed horizontally
+    pub pixel_aspect: f64,
+    // pixels rendered on every side beyond the frame the camera is framed for
+    pub overscan: u32,
+    pub samples_per_pixel: u32,
+    // bounces any path may take
+    pub depth: i32,
+    pub lobe_depths: LobeDepths,
+    // paths that bounced more often than this are ended by Russian roulette as their throughput
+    // drops, and the survivors weighted up to make up for them
+    pub roulette: Option<u32>,
+    pub seed: u64,
+    pub threads: usize,
+    pub sampler: SamplerKind,
+    pub blue_noise: bool,
+    // with adaptive sampling, samples_per_pixel is the upper bound
+    pub adaptive: Option<AdaptiveSampling>,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    // renders only this part of the film, with the same samples the full frame would get there
+    pub crop: Option<Window>,
+    pub filter: FilterKind,
+    // the filter's default when unset
+    pub filter_radius: Option<f64>,
+    // the RGB space light is transported in
+    pub working_space: ColorSpace,
+    // paths carry wavelengths instead of RGB, which is slower and noisier in color but models
+    // spectral effects
+    pub spectral: bool,
+    // splits every sample's radiance into the light-path AOVs too
+    pub light_paths: bool,
+    pub fireflies: FireflySuppression,
+    // in seconds
+    pub shutter: Shutter,
+    pub camera: CameraMotion,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 720,
+            height: None,
+            pixel_aspect: 1.0,
+            overscan: 0,
+            samples_per_pixel: 50,
+            depth: 25,
+            lobe_depths: LobeDepths::default(),
+            roulette: Some(3),
+            seed: 0,
+            threads: thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1),
+            sampler: SamplerKind::Sobol,
+            blue_noise: false,
+            adaptive: None,
+            tile_size: TILE_SIZE,
+            tile_order: TileOrder::Hilbert,
+            crop: None,
+            filter: FilterKind::Box,
+            filter_radius: None,
+            working_space: ColorSpace::Srgb,
+            spectral: false,
+            light_paths: false,
+            fireflies: FireflySuppression::default(),
+            shutter: Shutter::default(),
+            camera: CameraMotion::default(),
+        }
+    }
+}
 
 fn create_header(render: &mut String, width: u32, height: u32) {
-    println!("Width: {}px, Height: {}px", width, height);
     render.push_str("P3\n");
     render.push_str(&width.to_string());
-    render.push_str(" ");
+    render.push(' ');
     render.push_str(&height.to_string());
     render.push_str("\n255\n");
 }
 
-pub fn render(width: u32) -> String {
+fn render_tile(
+    tile: &Window,
+    pixels: &mut [Pixel],
+    splats: &mut SplatTile,
+    samples: u32,
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: &dyn Hittable,
+) {
+    let filter = settings.filter.create(settings.filter_radius);
+    let to_working = Conversion::new(ColorSpace::Srgb, settings.working_space);
+    let mut sampler = settings.sampler.create(settings.seed, settings.samples_per_pixel, settings.blue_noise);
+    let coordinates = (tile.y..tile.y + tile.height).flat_map(|y| (tile.x..tile.x + tile.width).map(move |x| (x, y)));
+    for ((x, y), pixel) in coordinates.zip(pixels) {
+        for _ in 0..samples {
+            if settings.adaptive.is_some_and(|adaptive| adaptive.is_converged(&pixel.estimate)) {
+                break;
+            }
+            // sample indices continue where earlier passes stopped
+            sampler.start_pixel_sample(x, y, pixel.samples());
+            let (jitter_u, jitter_v) = sampler.next_2d();
+            let time = camera.shutter.sample_time(sampler.as_mut());
+            let ray = film_ray(settings, camera, x, y, (jitter_u, jitter_v), time);
+            count(Counter::CameraRays);
+            let mut light_paths = settings.light_paths.then(LightPaths::default);
+            let color = if settings.spectral {
+                let mut transport = Spectral { wavelengths: Wavelengths::sample(sampler.next_1d()), to_working };
+                ray_color(&ray, world, settings, &mut transport, sampler.as_mut(), light_paths.as_mut())
+            } else {
+                ray_color(&ray, world, settings, &mut Rgb { to_working }, sampler.as_mut(), light_paths.as_mut())
+            };
+            pixel.add(color);
+            let (film_x, film_y) = (x as f64 + jitter_u, y as f64 + jitter_v);
+            splats.add(film_x, film_y, color, filter.as_ref());
+            if let Some(light_paths) = &light_paths {
+                splats.add_light_paths(film_x, film_y, light_paths, filter.as_ref());
+            }
+        }
+    }
+}
 
-    // Image
-    let aspect_ratio = 16.0 / 9.0;
-    let height = (width as f64 / aspect_ratio) as u32;
-    let samples_per_pixel = 50;
-    let depth = 25;
+// the camera ray through film pixel (x, y) at the given offset within it, cast at the given time
+pub fn film_ray(settings: &RenderSettings, camera: &Camera, x: u32, y: u32, (jitter_u, jitter_v): (f64, f64), time: f64) -> Ray {
+    let (width, height) = frame_size(settings);
+    let overscan = settings.overscan as f64;
+    // film rows run top to bottom, the camera's v bottom to top; overscan pixels fall outside 0..1
+    let u = (x as f64 - overscan + jitter_u) / width as f64;
+    let v = (height as f64 + overscan - y as f64 - jitter_v) / height as f64;
+    camera.get_ray(u, v, time)
+}
 
-    // World
-    let mut world = HittableList::new();
+// linear radiance in the working space averaged per pixel, rows from top to bottom, and the samples each pixel took
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+    pub samples: Vec<u32>,
+    // of each pixel's mean luminance, as far as its own samples tell
+    pub variances: Vec<f64>,
+    // the light-path AOVs that received any light
+    pub layers: Vec<(PathLayer, Vec<Color>)>,
+}
+
+impl Image {
+    pub fn to_ppm(&self, transform: &OutputTransform) -> String {
+        let mut render = String::new();
+        create_header(&mut render, self.width, self.height);
+        for pixel in &self.pixels {
+            render.push_str(&write_color(&transform.apply(*pixel)));
+        }
+        render
+    }
 
+    // 8 bits per channel, as PNG wants them
+    pub fn to_rgb8(&self, transform: &OutputTransform) -> Vec<u8> {
+        self.pixels.iter().flat_map(|pixel| to_bytes(&transform.apply(*pixel))).collect()
+    }
 
-    for x in 1..10 {
-        for z in 1..5 {
-            let mut rng = rand::thread_rng();
-            let choose_mat: Rc<dyn Material> = match rng.gen_range(1..4) {
-                1 => Rc::new(Metal { albedo: Color { x: 0.05, y: 0.05, z: 0.05 } }),
-                2 => Rc::new(Metal { albedo: Color { x: 0.7, y: 0.7, z: 0.7 } }),
-                _ => Rc::new(Metal { albedo: Color { x: 0.0, y: 0.0, z: 0.33 } }),
-            };
-            let coord_x = -3.0 + (0.6 * x as f64);
-            let coord_z = -1.2 - (0.5 * z as f64);
-            world.add(Box::new(Sphere {
-                center: Point { x: coord_x, y: -0.75, z: coord_z },
-                radius: 0.25,
-                material: choose_mat,
-            }));
+    // debug view of the samples each pixel took, black for none and white for the busiest pixel
+    pub fn samples_to_ppm(&self) -> String {
+        let most = self.samples.iter().copied().max().unwrap_or(0).max(1);
+        let mut render = String::new();
+        create_header(&mut render, self.width, self.height);
+        for &samples in &self.samples {
+            let level = (255.0 * samples as f64 / most as f64).round() as u32;
+            render.push_str(&format!("{} {} {}\n", level, level, level));
+        }
+        render
+    }
+}
+
+// the frame the camera is framed for
+pub fn frame_size(settings: &RenderSettings) -> (u32, u32) {
+    let height = settings.height.unwrap_or((settings.width as f64 * 9.0 / 16.0) as u32);
+    (settings.width, height)
+}
+
+// the frame plus overscan
+pub fn film_size(settings: &RenderSettings) -> (u32, u32) {
+    let (width, height) = frame_size(settings);
+    (width + 2 * settings.overscan, height + 2 * settings.overscan)
+}
+
+pub fn camera(settings: &RenderSettings) -> Camera {
+    let (width, height) = frame_size(settings);
+    Camera::new(width as f64 * settings.pixel_aspect / height as f64, settings.shutter, settings.camera)
+}
+
+pub fn film_window(settings: &RenderSettings) -> Window {
+    let (width, height) = film_size(settings);
+    settings.crop.unwrap_or(Window { x: 0, y: 0, width, height })
+}
+
+// the film window plus the pixels whose samples the filter spreads into it
+pub fn sample_window(settings: &RenderSettings) -> Window {
+    let (width, height) = film_size(settings);
+    let margin = margin(settings.filter.create(settings.filter_radius).as_ref());
+    film_window(settings).expand(margin, &Window { x: 0, y: 0, width, height })
+}
+
+pub fn new_framebuffer(settings: &RenderSettings) -> Framebuffer {
+    Framebuffer::new(film_window(settings), sample_window(settings))
+}
+
+// adds up to `samples` samples to every pixel of the framebuffer that hasn't converged yet
+pub fn render_pass(
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: &dyn Hittable,
+    framebuffer: &mut Framebuffer,
+    samples: u32,
+    progress: &dyn Progress,
+) {
+    let tiles = schedule(framebuffer.sampled, settings.tile_size, settings.tile_order);
+    let margin = margin(settings.filter.create(settings.filter_radius).as_ref());
+    progress.start(tiles.len());
+
+    // workers pull tiles in schedule order; every sample draws from its own stream and splats add up
+    // in fixed point, so the result does not depend on which thread renders which tile
+    let next_tile = AtomicUsize::new(0);
+    let framebuffer = Mutex::new(framebuffer);
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.max(1) {
+            scope.spawn(|| {
+                while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                    let (mut pixels, mut splats) = {
+                        let framebuffer = framebuffer.lock().unwrap();
+                        (framebuffer.read(tile), SplatTile::new(tile.expand(margin, &framebuffer.sampled)))
+                    };
+                    stats::take();
+                    render_tile(tile, &mut pixels, &mut splats, samples, settings, camera, world);
+                    let mut framebuffer = framebuffer.lock().unwrap();
+                    framebuffer.write(tile, &pixels);
+                    framebuffer.splat(&splats);
+                    progress.tile_finished(&stats::take());
+                }
+            });
+        }
+    });
+    progress.finish();
+}
+
+pub fn render_image(settings: &RenderSettings, world: &dyn Hittable, progress: &dyn Progress) -> Image {
+    let camera = camera(settings);
+    let mut framebuffer = new_framebuffer(settings);
+    render_pass(settings, &camera, world, &mut framebuffer, settings.samples_per_pixel, progress);
+    framebuffer.to_image()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::renderer::filter::FILTER_KINDS;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::progress::Silent;
+    use crate::renderer::sampler::SAMPLER_KINDS;
+    use crate::renderer::scene::spheres;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::vector3d::Point;
+    use crate::renderer::world::HittableList;
+
+    fn render(settings: &RenderSettings, world: &dyn Hittable) -> String {
+        render_image(settings, world, &Silent).to_ppm(&OutputTransform::default())
+    }
+
+    fn small(seed: u64, threads: usize) -> RenderSettings {
+        RenderSettings {
+            width: 32,
+            samples_per_pixel: 4,
+            depth: 8,
+            seed,
+            threads,
+            ..RenderSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_render_is_independent_of_thread_count() {
+        let world = spheres(3);
+        let single = render(&small(3, 1), &world);
+        assert_eq!(single, render(&small(3, 4), &world));
+        assert_eq!(single, render(&small(3, 7), &world));
+    }
+
+    #[test]
+    fn test_seed_changes_the_image() {
+        let first = render(&small(1, 2), &spheres(1));
+        assert_eq!(first, render(&small(1, 2), &spheres(1)));
+        assert_ne!(first, render(&small(2, 2), &spheres(1)));
+    }
+
+    #[test]
+    fn test_adaptive_sampling_spends_samples_on_noise() {
+        let settings = RenderSettings {
+            samples_per_pixel: 64,
+            adaptive: Some(AdaptiveSampling { tolerance: 0.02, min_samples: 8 }),
+            ..small(5, 1)
+        };
+        let image = render_image(&settings, &spheres(5), &Silent);
+        assert!(image.samples.iter().all(|&samples| (8..=64).contains(&samples)));
+        // the top rows only see the smooth sky gradient, the bottom rows the noisy sphere field
+        let rows = |range: std::ops::Range<u32>| -> f64 {
+            let pixels = &image.samples[(range.start * image.width) as usize..(range.end * image.width) as usize];
+            pixels.iter().sum::<u32>() as f64 / pixels.len() as f64
+        };
+        assert!(rows(0..2) < 16.0, "{}", rows(0..2));
+        assert!(rows(image.height - 4..image.height) > 2.0 * rows(0..2));
+    }
+
+    #[test]
+    fn test_tile_order_does_not_change_the_image() {
+        let world = spheres(3);
+        let reference = render(&RenderSettings { tile_order: TileOrder::Scanline, ..small(3, 2) }, &world);
+        for tile_order in [TileOrder::Spiral, TileOrder::Hilbert] {
+            for tile_size in [1, 7, 32] {
+                let settings = RenderSettings { tile_order, tile_size, ..small(3, 2) };
+                assert_eq!(reference, render(&settings, &world));
+            }
+        }
+    }
+
+    #[test]
+    fn test_crop_matches_the_full_frame() {
+        let world = spheres(3);
+        let full = render_image(&small(3, 2), &world, &Silent);
+        let crop = Window { x: 5, y: 3, width: 11, height: 6 };
+        let cropped = render_image(&RenderSettings { crop: Some(crop), ..small(3, 2) }, &world, &Silent);
+        assert_eq!((cropped.width, cropped.height), (11, 6));
+        for y in 0..crop.height {
+            for x in 0..crop.width {
+                let expected = full.pixels[((crop.y + y) * full.width + crop.x + x) as usize];
+                let actual = cropped.pixels[(y * crop.width + x) as usize];
+                assert_eq!((actual.x, actual.y, actual.z), (expected.x, expected.y, expected.z));
+            }
+        }
+    }
+
+    // a ball straight ahead of the camera; at depth 1 it renders black against the sky
+    fn ball() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere {
+            center: Point { x: 0.0, y: 0.0, z: -3.0 },
+            radius: 1.0,
+            material: Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } }),
+        }));
+        world
+    }
+
+    // the ball's bounding box in pixels as (left, top, width, height)
+    fn ball_extent(settings: &RenderSettings) -> (u32, u32, u32, u32) {
+        let settings = RenderSettings { samples_per_pixel: 16, depth: 1, ..*settings };
+        let image = render_image(&settings, &ball(), &Silent);
+        let dark: Vec<(u32, u32)> = (0..image.height)
+            .flat_map(|y| (0..image.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| image.pixels[(y * image.width + x) as usize].luminance() < 0.25)
+            .collect();
+        let left = dark.iter().map(|&(x, _)| x).min().unwrap();
+        let right = dark.iter().map(|&(x, _)| x).max().unwrap();
+        let top = dark.iter().map(|&(_, y)| y).min().unwrap();
+        let bottom = dark.iter().map(|&(_, y)| y).max().unwrap();
+        (left, top, right - left + 1, bottom - top + 1)
+    }
+
+    #[test]
+    fn test_spheres_render_round_at_any_aspect_ratio() {
+        for (width, height, pixel_aspect) in [(96, 96, 1.0), (128, 96, 1.0), (229, 96, 1.0), (48, 96, 2.0)] {
+            let settings = RenderSettings { width, height: Some(height), pixel_aspect, ..small(0, 1) };
+            let (left, top, ball_width, ball_height) = ball_extent(&settings);
+            // both extents may be a pixel off at the edges
+            let difference = ball_width as f64 * pixel_aspect - ball_height as f64;
+            assert!(difference.abs() <= 2.0 * pixel_aspect, "{}x{}: {}x{} pixels", width, height, ball_width, ball_height);
+            // centered, and the same size whatever the width
+            assert_eq!(left + left + ball_width, width);
+            assert_eq!(top + top + ball_height, height);
+            assert!((34..=36).contains(&ball_height), "{}", ball_height);
         }
     }
-    world.add(Box::new(Sphere {
-        center: Point { x: 0.0, y: -101.0, z: 0.0 },
-        radius: 100.0,
-        material: Rc::new(Lambert { albedo: Color { x: 0.0, y: 0.7, z: 0.0 } }),
-    }));
-
-
-    // Camera
-    let camera = Camera::new();
-
-    // Render
-    let mut render = String::new();
-    create_header(&mut render, width, height);
-
-    for y in (0..height).rev() {
-        for x in 0..width {
-            println!("Pixel {} of {}, {} scanlines remain", x, width, y);
-            let mut pixel_color = Color { x: 0.0, y: 0.0, z: 0.0 };
-            for _ in 0..samples_per_pixel {
-                let u = (x as f64 + random::<f64>()) / (width - 1) as f64;
-                let v = (y as f64 + random::<f64>()) / (height - 1) as f64;
-                let ray = camera.get_ray(u, v);
-                pixel_color = pixel_color + ray_color(&ray, &world, depth);
+
+    #[test]
+    fn test_overscan_extends_the_frame_without_reframing() {
+        let frame = RenderSettings { width: 128, height: Some(96), ..small(0, 1) };
+        let (left, top, width, height) = ball_extent(&frame);
+        let overscanned = ball_extent(&RenderSettings { overscan: 10, ..frame });
+        assert_eq!(film_size(&RenderSettings { overscan: 10, ..frame }), (148, 116));
+        assert_eq!(overscanned, (left + 10, top + 10, width, height));
+    }
+
+    #[test]
+    fn test_filtered_render_is_independent_of_tiling() {
+        let world = spheres(3);
+        for filter in FILTER_KINDS {
+            let settings = RenderSettings { filter, ..small(3, 1) };
+            let reference = render(&settings, &world);
+            assert_eq!(reference, render(&RenderSettings { threads: 4, tile_size: 5, ..settings }, &world), "{}", filter);
+            let tiled = RenderSettings { tile_order: TileOrder::Spiral, tile_size: 3, ..settings };
+            assert_eq!(reference, render(&tiled, &world), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn test_filtered_crop_matches_the_full_frame() {
+        let world = spheres(3);
+        let settings = RenderSettings { filter: FilterKind::Mitchell, ..small(3, 2) };
+        let full = render_image(&settings, &world, &Silent);
+        // touches the left edge of the film, where there is no margin to sample
+        let crop = Window { x: 0, y: 4, width: 9, height: 7 };
+        let cropped = render_image(&RenderSettings { crop: Some(crop), ..settings }, &world, &Silent);
+        for y in 0..crop.height {
+            for x in 0..crop.width {
+                let expected = full.pixels[((crop.y + y) * full.width + crop.x + x) as usize];
+                let actual = cropped.pixels[(y * crop.width + x) as usize];
+                assert_eq!((actual.x, actual.y, actual.z), (expected.x, expected.y, expected.z));
             }
-            render.push_str(&write_color(&pixel_color, samples_per_pixel));
         }
     }
-    return render;
-}
\ No newline at end of file
+
+    #[test]
+    fn test_wider_filters_blur_edges() {
+        // the ball's silhouette against the sky: every pixel the box sees as sky stays sky, but a
+        // wide filter pulls some of the ball's darkness into the ring around it
+        let frame = RenderSettings { width: 64, height: Some(64), samples_per_pixel: 16, depth: 1, ..small(0, 1) };
+        let darkness = |filter| {
+            let image = render_image(&RenderSettings { filter, ..frame }, &ball(), &Silent);
+            image.pixels.iter().filter(|pixel| (0.05..0.45).contains(&pixel.luminance())).count()
+        };
+        assert!(darkness(FilterKind::Gaussian) > darkness(FilterKind::Box));
+    }
+
+    #[test]
+    fn test_every_sampler_is_deterministic() {
+        let world = spheres(3);
+        for sampler in SAMPLER_KINDS {
+            for blue_noise in [false, true] {
+                let settings = RenderSettings { sampler, blue_noise, ..small(3, 1) };
+                let single = render(&settings, &world);
+                assert_eq!(single, render(&RenderSettings { threads: 3, ..settings }, &world));
+            }
+        }
+    }
+
+    #[test]
+    fn test_spectral_render_matches_rgb_on_average() {
+        let settings = RenderSettings { width: 24, samples_per_pixel: 16, depth: 4, ..small(4, 1) };
+        let spectral = RenderSettings { spectral: true, ..settings };
+        let image = render_image(&spectral, &ball(), &Silent);
+        assert_eq!(render(&spectral, &ball()), render(&RenderSettings { threads: 3, ..spectral }, &ball()));
+        let mean = |pixels: &[Color]| pixels.iter().fold(Color::default(), |sum, pixel| sum + *pixel) / pixels.len() as f64;
+        let (rgb, spectral) = (mean(&render_image(&settings, &ball(), &Silent).pixels), mean(&image.pixels));
+        for (rgb, spectral) in [(rgb.x, spectral.x), (rgb.y, spectral.y), (rgb.z, spectral.z)] {
+            assert!((rgb - spectral).abs() < 0.02 * rgb, "{} vs {}", rgb, spectral);
+        }
+    }
+
+    #[test]
+    fn test_working_space_does_not_change_grey_scenes() {
+        // grey surfaces only scale the sky, so light transported in ACEScg comes back to the same sRGB
+        let settings = RenderSettings { width: 24, depth: 4, ..small(2, 1) };
+        let srgb = render_image(&settings, &ball(), &Silent);
+        let acescg = render_image(&RenderSettings { working_space: ColorSpace::AcesCg, ..settings }, &ball(), &Silent);
+        let back = Conversion::new(ColorSpace::AcesCg, ColorSpace::Srgb);
+        for (expected, pixel) in srgb.pixels.iter().zip(&acescg.pixels) {
+            let pixel = back.apply(*pixel);
+            assert!((pixel - *expected).length() < 1e-6, "{:?} vs {:?}", pixel, expected);
+        }
+    }
+}
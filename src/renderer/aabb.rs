@@ -0,0 +1,126 @@
+use crate::renderer::ray::Ray;
+use crate::renderer::vector3d::Point;
+
+pub(crate) fn axis(point: Point, index: usize) -> f64 {
+    match index {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+// axis-aligned bounding box
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Aabb {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Self {
+            min: Point { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY },
+            max: Point { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY },
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point {
+                x: self.min.x.min(other.min.x),
+                y: self.min.y.min(other.min.y),
+                z: self.min.z.min(other.min.z),
+            },
+            max: Point {
+                x: self.max.x.max(other.max.x),
+                y: self.max.y.max(other.max.y),
+                z: self.max.z.max(other.max.z),
+            },
+        }
+    }
+
+    pub fn include(&self, point: Point) -> Aabb {
+        self.union(&Aabb { min: point, max: point })
+    }
+
+    pub fn centroid(&self) -> Point {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn min_on(&self, index: usize) -> f64 {
+        axis(self.min, index)
+    }
+
+    pub fn extent_on(&self, index: usize) -> f64 {
+        axis(self.max, index) - axis(self.min, index)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.extent_on(0), self.extent_on(1), self.extent_on(2));
+        if x < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    // slab test; inverse_direction is 1 / ray.direction
+    pub fn hit(&self, ray: &Ray, inverse_direction: Point, t_min: f64, t_max: f64) -> bool {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for index in 0..3 {
+            let origin = axis(ray.origin, index);
+            let inverse = axis(inverse_direction, index);
+            let mut near = (self.min_on(index) - origin) * inverse;
+            let mut far = (axis(self.max, index) - origin) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from a zero direction on the slab's plane leaves the range untouched
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::vector3d::Vector3D;
+
+    fn unit() -> Aabb {
+        Aabb { min: Point::default(), max: Point { x: 1.0, y: 1.0, z: 1.0 } }
+    }
+
+    fn inverse(direction: Vector3D) -> Point {
+        Point { x: 1.0 / direction.x, y: 1.0 / direction.y, z: 1.0 / direction.z }
+    }
+
+    #[test]
+    fn test_slab_hits() {
+        let direction = Vector3D { x: 1.0, y: 0.0, z: 0.0 };
+        let through = Ray { origin: Point { x: -1.0, y: 0.5, z: 0.5 }, direction, time: 0.0 };
+        assert!(unit().hit(&through, inverse(direction), 0.0, f64::INFINITY));
+        assert!(!unit().hit(&through, inverse(direction), 0.0, 0.5));
+        let beside = Ray { origin: Point { x: -1.0, y: 1.5, z: 0.5 }, direction, time: 0.0 };
+        assert!(!unit().hit(&beside, inverse(direction), 0.0, f64::INFINITY));
+        let behind = Ray { origin: Point { x: 2.0, y: 0.5, z: 0.5 }, direction, time: 0.0 };
+        assert!(!unit().hit(&behind, inverse(direction), 0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_union_and_area() {
+        let moved = Aabb { min: Point { x: 2.0, y: 0.0, z: 0.0 }, max: Point { x: 3.0, y: 1.0, z: 1.0 } };
+        let both = unit().union(&moved);
+        assert_eq!(both.extent_on(0), 3.0);
+        assert_eq!(both.surface_area(), 2.0 * (3.0 + 1.0 + 3.0));
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+    }
+}
@@ -0,0 +1,98 @@
+// keep sampling a pixel only while the 95% confidence interval of its luminance is wider than the
+// tolerance; the tolerance is measured after the output's square-root gamma, so dark and bright
+// pixels of the same visible noise stop together
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveSampling {
+    pub tolerance: f64,
+    pub min_samples: u32,
+}
+
+const CONFIDENCE_Z: f64 = 1.96;
+
+// Welford's running mean and variance
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RunningVariance {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningVariance {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn from_state(count: u32, mean: f64, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    pub fn state(&self) -> (u32, f64, f64) {
+        (self.count, self.mean, self.m2)
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    pub fn confidence_interval(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        CONFIDENCE_Z * (self.variance() / self.count as f64).sqrt()
+    }
+
+    // the confidence interval after the output's square-root gamma, d sqrt(L) = dL / (2 sqrt(L))
+    pub fn display_error(&self) -> f64 {
+        let interval = self.confidence_interval();
+        if interval == 0.0 {
+            return 0.0;
+        }
+        interval / (2.0 * self.mean.max(0.0).sqrt())
+    }
+}
+
+impl AdaptiveSampling {
+    pub fn is_converged(&self, estimate: &RunningVariance) -> bool {
+        estimate.count() >= self.min_samples && estimate.display_error() <= self.tolerance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_variance_matches_two_pass() {
+        let values = [0.5, 2.0, 0.25, 7.0, 1.0, 1.0, 3.5];
+        let mut estimate = RunningVariance::default();
+        values.iter().for_each(|&value| estimate.push(value));
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+        assert!((estimate.mean - mean).abs() < 1e-12);
+        assert!((estimate.variance() - variance).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_convergence_respects_min_samples() {
+        let adaptive = AdaptiveSampling { tolerance: 0.01, min_samples: 8 };
+        let mut estimate = RunningVariance::default();
+        for _ in 0..7 {
+            estimate.push(0.3);
+            assert!(!adaptive.is_converged(&estimate));
+        }
+        estimate.push(0.3);
+        assert!(adaptive.is_converged(&estimate));
+        estimate.push(30.0);
+        assert!(!adaptive.is_converged(&estimate));
+    }
+}
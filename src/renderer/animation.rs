@@ -0,0 +1,154 @@
+use crate::renderer::aabb::Aabb;
+use crate::renderer::bvh::Bvh;
+use crate::renderer::camera::{CameraMotion, CameraPose, Shutter};
+use crate::renderer::hit::{Hittable, HitRecord};
+use crate::renderer::motion::Keyframes;
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::world::HittableList;
+use crate::renderer::RenderSettings;
+
+// builds the objects whose materials or lights are keyed as they are at the given time
+type Builder = Box<dyn Fn(f64) -> HittableList>;
+
+// a scene keyed in seconds; still scenes are animations in which nothing moves
+pub struct Animation {
+    // never change, so every frame shares their hierarchy
+    pub still: HittableList,
+    // called once a frame as its shutter opens. Transforms and moving spheres may keep their whole
+    // tracks, which blurs them over the shutter
+    pub animated: Option<Builder>,
+    pub camera: Keyframes<CameraPose>,
+}
+
+impl Animation {
+    pub fn still(world: HittableList) -> Self {
+        Self { still: world, animated: None, camera: Keyframes::constant(CameraPose::default()) }
+    }
+
+    pub fn into_sequence(self) -> Sequence {
+        Sequence { still: Bvh::new(self.still.into_objects()), animated: self.animated, camera: self.camera }
+    }
+}
+
+// frames count from 1, frame n being exposed from (n - 1) / fps seconds on for the shutter's
+// interval, which is given in frames
+pub fn frame_shutter(frame: u32, fps: f64, shutter: Shutter) -> Shutter {
+    let start = frame as f64 - 1.0;
+    Shutter { open: (start + shutter.open) / fps, close: (start + shutter.close) / fps }
+}
+
+// an animation ready to render frames of
+pub struct Sequence {
+    still: Bvh,
+    animated: Option<Builder>,
+    camera: Keyframes<CameraPose>,
+}
+
+impl Sequence {
+    // the hierarchy of the animated objects is rebuilt for every frame, bounding them only over its shutter
+    pub fn frame(&self, shutter: Shutter) -> FrameWorld<'_> {
+        let animated = self.animated.as_ref().map(|build| Bvh::during(build(shutter.open).into_objects(), shutter.open, shutter.close));
+        FrameWorld { still: &self.still, animated }
+    }
+
+    pub fn settings(&self, settings: &RenderSettings, shutter: Shutter) -> RenderSettings {
+        let camera = CameraMotion { open: self.camera.at(shutter.open), close: self.camera.at(shutter.close) };
+        RenderSettings { shutter, camera, ..*settings }
+    }
+}
+
+// what one frame's rays are traced against. The animated objects are numbered after the still ones,
+// and their materials after the still ones' even where they share them
+pub struct FrameWorld<'a> {
+    still: &'a Bvh,
+    animated: Option<Bvh>,
+}
+
+impl Hittable for FrameWorld<'_> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let still = self.still.hit(ray, t_min, t_max, sampler);
+        let Some(animated) = &self.animated else {
+            return still;
+        };
+        let closest = still.as_ref().map_or(t_max, |record| record.t);
+        match animated.hit(ray, t_min, closest, sampler) {
+            Some(mut record) => {
+                record.object_id += self.still.object_count();
+                if record.material_id > 0 {
+                    record.material_id += self.still.material_count();
+                }
+                Some(record)
+            }
+            None => still,
+        }
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let still = self.still.transmittance(ray, t_min, t_max, sampler);
+        match &self.animated {
+            Some(animated) if still > 0.0 => still * animated.transmittance(ray, t_min, t_max, sampler),
+            _ => still,
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match &self.animated {
+            Some(animated) => Some(self.still.bounding_box()?.union(&animated.bounding_box()?)),
+            None => self.still.bounding_box(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::sampler::SamplerKind;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::vector3d::{Point, Vector3D};
+
+    fn ball(x: f64, z: f64) -> Box<Sphere> {
+        Box::new(Sphere { center: Point { x, y: 0.0, z }, radius: 0.5, material: Arc::new(Lambert::default()) })
+    }
+
+    #[test]
+    fn test_frames_are_exposed_one_after_another() {
+        let shutter = Shutter { open: 0.0, close: 0.5 };
+        assert_eq!(frame_shutter(1, 24.0, shutter), Shutter { open: 0.0, close: 0.5 / 24.0 });
+        assert_eq!(frame_shutter(25, 24.0, shutter), Shutter { open: 1.0, close: 1.0 + 0.5 / 24.0 });
+    }
+
+    #[test]
+    fn test_only_the_animated_objects_are_rebuilt_for_each_frame() {
+        let mut still = HittableList::new();
+        still.add(ball(0.0, -3.0));
+        let builds = Rc::new(Cell::new(0));
+        let counter = builds.clone();
+        let animation = Animation {
+            still,
+            // a ball sliding along x at a unit a second
+            animated: Some(Box::new(move |time| {
+                counter.set(counter.get() + 1);
+                let mut world = HittableList::new();
+                world.add(ball(2.0 + time, -3.0));
+                world
+            })),
+            camera: Keyframes::constant(CameraPose::default()),
+        };
+        let sequence = animation.into_sequence();
+        let mut sampler = SamplerKind::Independent.create(0, 1, false);
+        let towards = |x: f64| Ray { origin: Point::default(), direction: Vector3D { x, y: 0.0, z: -3.0 }, time: 0.0 };
+        for frame in [1, 2] {
+            let world = sequence.frame(frame_shutter(frame, 1.0, Shutter::default()));
+            let still = world.hit(&towards(0.0), 0.001, f64::INFINITY, sampler.as_mut()).unwrap();
+            assert_eq!(still.object_id, 1);
+            let moved = world.hit(&towards(frame as f64 + 1.0), 0.001, f64::INFINITY, sampler.as_mut()).unwrap();
+            assert_eq!((moved.object_id, moved.material_id), (2, 2));
+        }
+        assert_eq!(builds.get(), 2);
+    }
+}
@@ -0,0 +1,524 @@
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::{io, thread};
+use crate::renderer::camera::Camera;
+use crate::renderer::exr::{self, Channel, Samples};
+use crate::renderer::hit::Hittable;
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Color, Point, Vector3D};
+use crate::renderer::{camera, film_ray, film_window, Image, RenderSettings};
+
+// arbitrary output variables: what the camera rays hit first, and the beauty split by light path
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aov {
+    Depth,
+    Normal,
+    CameraNormal,
+    Position,
+    Albedo,
+    Uv,
+    ObjectId,
+    MaterialId,
+    Emission,
+    DirectDiffuse,
+    DirectSpecular,
+    IndirectDiffuse,
+    IndirectSpecular,
+    // one layer per light
+    Lights,
+}
+
+pub const AOVS: [Aov; 14] = [
+    Aov::Depth,
+    Aov::Normal,
+    Aov::CameraNormal,
+    Aov::Position,
+    Aov::Albedo,
+    Aov::Uv,
+    Aov::ObjectId,
+    Aov::MaterialId,
+    Aov::Emission,
+    Aov::DirectDiffuse,
+    Aov::DirectSpecular,
+    Aov::IndirectDiffuse,
+    Aov::IndirectSpecular,
+    Aov::Lights,
+];
+
+impl Aov {
+    // the light-path AOVs are gathered while rendering the beauty
+    pub fn needs_light_paths(self) -> bool {
+        matches!(
+            self,
+            Aov::Emission | Aov::DirectDiffuse | Aov::DirectSpecular | Aov::IndirectDiffuse | Aov::IndirectSpecular | Aov::Lights
+        )
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::CameraNormal => "camera-normal",
+            Aov::Position => "position",
+            Aov::Albedo => "albedo",
+            Aov::Uv => "uv",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::Emission => "emission",
+            Aov::DirectDiffuse => "direct-diffuse",
+            Aov::DirectSpecular => "direct-specular",
+            Aov::IndirectDiffuse => "indirect-diffuse",
+            Aov::IndirectSpecular => "indirect-specular",
+            Aov::Lights => "lights",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        AOVS.iter()
+            .find(|aov| aov.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown AOV '{}'", name))
+    }
+}
+
+// a comma-separated list of AOVs, or "all"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aovs(pub Vec<Aov>);
+
+impl FromStr for Aovs {
+    type Err = String;
+
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        if list == "all" {
+            return Ok(Aovs(AOVS.to_vec()));
+        }
+        let mut aovs = Vec::new();
+        for aov in list.split(',').map(str::parse) {
+            let aov = aov?;
+            if !aovs.contains(&aov) {
+                aovs.push(aov);
+            }
+        }
+        Ok(Aovs(aovs))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AovFormat {
+    // one file with the beauty and every AOV as layers
+    Exr,
+    // one portable float map per AOV
+    Pfm,
+}
+
+pub const AOV_FORMATS: [AovFormat; 2] = [AovFormat::Exr, AovFormat::Pfm];
+
+impl fmt::Display for AovFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AovFormat::Exr => "exr",
+            AovFormat::Pfm => "pfm",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for AovFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        AOV_FORMATS
+            .iter()
+            .find(|format| format.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown AOV format '{}'", name))
+    }
+}
+
+// where light came from: the sky or an emitting object, by its object ID
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Light {
+    Sky,
+    Object(u32),
+}
+
+// one part of the beauty, split by how its light reached the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathLayer {
+    // seen directly
+    Emission,
+    // one scattering event away from the camera
+    DirectDiffuse,
+    DirectSpecular,
+    IndirectDiffuse,
+    IndirectSpecular,
+    // everything one light contributes
+    Light(Light),
+}
+
+impl fmt::Display for PathLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathLayer::Emission => write!(f, "emission"),
+            PathLayer::DirectDiffuse => write!(f, "direct-diffuse"),
+            PathLayer::DirectSpecular => write!(f, "direct-specular"),
+            PathLayer::IndirectDiffuse => write!(f, "indirect-diffuse"),
+            PathLayer::IndirectSpecular => write!(f, "indirect-specular"),
+            PathLayer::Light(Light::Sky) => write!(f, "light-sky"),
+            PathLayer::Light(Light::Object(id)) => write!(f, "light-object-{}", id),
+        }
+    }
+}
+
+// one camera sample's radiance by layer; the lobe layers and the light layers each add up to it
+#[derive(Clone, Debug, Default)]
+pub struct LightPaths {
+    pub layers: Vec<(PathLayer, Color)>,
+}
+
+impl LightPaths {
+    // bounce 1 is what the camera ray hit, bounce 2 is one scattering event further and so on; the
+    // first scattering event's diffuse share splits everything found after it
+    pub fn add(&mut self, light: Light, bounce: u32, diffuse_share: f64, color: Color) {
+        if color.x == 0.0 && color.y == 0.0 && color.z == 0.0 {
+            return;
+        }
+        let (diffuse, specular) = match bounce {
+            1 => {
+                self.accumulate(PathLayer::Emission, color);
+                self.accumulate(PathLayer::Light(light), color);
+                return;
+            }
+            2 => (PathLayer::DirectDiffuse, PathLayer::DirectSpecular),
+            _ => (PathLayer::IndirectDiffuse, PathLayer::IndirectSpecular),
+        };
+        self.accumulate(diffuse, diffuse_share * color);
+        self.accumulate(specular, (1.0 - diffuse_share) * color);
+        self.accumulate(PathLayer::Light(light), color);
+    }
+
+    fn accumulate(&mut self, layer: PathLayer, color: Color) {
+        match self.layers.iter_mut().find(|(known, _)| *known == layer) {
+            Some((_, sum)) => *sum = *sum + color,
+            None => self.layers.push((layer, color)),
+        }
+    }
+}
+
+// what a camera ray hit first; misses are infinitely deep with everything else zero
+#[derive(Copy, Clone, Debug)]
+pub struct Surface {
+    pub depth: f64,
+    pub normal: Vector3D,
+    pub camera_normal: Vector3D,
+    pub position: Point,
+    pub albedo: Color,
+    pub u: f64,
+    pub v: f64,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl Default for Surface {
+    fn default() -> Self {
+        Self {
+            depth: f64::INFINITY,
+            normal: Vector3D::default(),
+            camera_normal: Vector3D::default(),
+            position: Point::default(),
+            albedo: Color::default(),
+            u: 0.0,
+            v: 0.0,
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+}
+
+fn first_hit(ray: &Ray, camera: &Camera, world: &dyn Hittable, sampler: &mut dyn Sampler) -> Surface {
+    // the same dimensions the beauty's camera ray intersected the world with, so media agree
+    sampler.start_bounce(1);
+    match world.hit(ray, 0.001, f64::INFINITY, sampler) {
+        Some(hit_record) => Surface {
+            depth: camera.depth(hit_record.point),
+            normal: hit_record.shading.normal,
+            camera_normal: camera.to_camera(hit_record.shading.normal),
+            position: hit_record.point,
+            albedo: hit_record.material.albedo(&hit_record),
+            u: hit_record.u,
+            v: hit_record.v,
+            object_id: hit_record.object_id,
+            material_id: hit_record.material_id,
+        },
+        None => Surface::default(),
+    }
+}
+
+// albedo and normals average up to this many of a pixel's samples, which antialiases their edges
+const FEATURE_SAMPLES: u32 = 16;
+
+// first hits over the film window, rows from top to bottom, from the camera rays of the beauty's
+// first samples. Every pixel reports the surface its first sample hit, except for albedo and
+// normals, which average its first few samples
+pub fn render_surfaces(settings: &RenderSettings, world: &dyn Hittable) -> Vec<Surface> {
+    let camera = camera(settings);
+    let window = film_window(settings);
+    let samples = settings.samples_per_pixel.clamp(1, FEATURE_SAMPLES);
+    let next_row = AtomicU32::new(0);
+    let rows = Mutex::new(vec![Vec::new(); window.height as usize]);
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.max(1) {
+            scope.spawn(|| {
+                let mut sampler = settings.sampler.create(settings.seed, settings.samples_per_pixel, settings.blue_noise);
+                loop {
+                    let row = next_row.fetch_add(1, Ordering::Relaxed);
+                    if row >= window.height {
+                        break;
+                    }
+                    let y = window.y + row;
+                    let mut pixels = Vec::with_capacity(window.width as usize);
+                    for x in window.x..window.x + window.width {
+                        let mut first = None;
+                        let (mut albedo, mut normal, mut camera_normal) = (Color::default(), Vector3D::default(), Vector3D::default());
+                        for sample in 0..samples {
+                            sampler.start_pixel_sample(x, y, sample);
+                            let jitter = sampler.next_2d();
+                            let ray = film_ray(settings, &camera, x, y, jitter, camera.shutter.sample_time(sampler.as_mut()));
+                            let surface = first_hit(&ray, &camera, world, sampler.as_mut());
+                            albedo = albedo + surface.albedo;
+                            normal = normal + surface.normal;
+                            camera_normal = camera_normal + surface.camera_normal;
+                            first.get_or_insert(surface);
+                        }
+                        let scale = 1.0 / samples as f64;
+                        pixels.push(Surface {
+                            albedo: albedo * scale,
+                            normal: normal * scale,
+                            camera_normal: camera_normal * scale,
+                            ..first.unwrap_or_default()
+                        });
+                    }
+                    rows.lock().unwrap()[row as usize] = pixels;
+                }
+            });
+        }
+    });
+    rows.into_inner().unwrap().concat()
+}
+
+// an AOV as named channels
+struct Layer {
+    name: String,
+    channels: Vec<(&'static str, Samples)>,
+}
+
+fn floats(values: impl Iterator<Item = f64>) -> Samples {
+    Samples::Float(values.map(|value| value as f32).collect())
+}
+
+fn vectors(values: &[Vector3D], names: [&'static str; 3]) -> Vec<(&'static str, Samples)> {
+    vec![
+        (names[0], floats(values.iter().map(|value| value.x))),
+        (names[1], floats(values.iter().map(|value| value.y))),
+        (names[2], floats(values.iter().map(|value| value.z))),
+    ]
+}
+
+fn layers(aovs: &[Aov], image: &Image, surfaces: &[Surface]) -> Vec<Layer> {
+    let surface = |value: fn(&Surface) -> Vector3D| surfaces.iter().map(value).collect::<Vec<_>>();
+    let path_layer = |layer: PathLayer| {
+        let pixels = match image.layers.iter().find(|(known, _)| *known == layer) {
+            Some((_, pixels)) => pixels.clone(),
+            // nothing reached the camera that way
+            None => vec![Color::default(); image.pixels.len()],
+        };
+        Layer { name: layer.to_string(), channels: vectors(&pixels, ["R", "G", "B"]) }
+    };
+    let mut layers = Vec::new();
+    for &aov in aovs {
+        let name = aov.to_string();
+        match aov {
+            Aov::Depth => layers.push(Layer { name, channels: vec![("Z", floats(surfaces.iter().map(|pixel| pixel.depth)))] }),
+            Aov::Normal => layers.push(Layer { name, channels: vectors(&surface(|pixel| pixel.normal), ["X", "Y", "Z"]) }),
+            Aov::CameraNormal => layers.push(Layer { name, channels: vectors(&surface(|pixel| pixel.camera_normal), ["X", "Y", "Z"]) }),
+            Aov::Position => layers.push(Layer { name, channels: vectors(&surface(|pixel| pixel.position), ["X", "Y", "Z"]) }),
+            Aov::Albedo => layers.push(Layer { name, channels: vectors(&surface(|pixel| pixel.albedo), ["R", "G", "B"]) }),
+            Aov::Uv => layers.push(Layer {
+                name,
+                channels: vec![
+                    ("U", floats(surfaces.iter().map(|pixel| pixel.u))),
+                    ("V", floats(surfaces.iter().map(|pixel| pixel.v))),
+                ],
+            }),
+            Aov::ObjectId => layers.push(Layer {
+                name,
+                channels: vec![("id", Samples::Uint(surfaces.iter().map(|pixel| pixel.object_id).collect()))],
+            }),
+            Aov::MaterialId => layers.push(Layer {
+                name,
+                channels: vec![("id", Samples::Uint(surfaces.iter().map(|pixel| pixel.material_id).collect()))],
+            }),
+            Aov::Emission => layers.push(path_layer(PathLayer::Emission)),
+            Aov::DirectDiffuse => layers.push(path_layer(PathLayer::DirectDiffuse)),
+            Aov::DirectSpecular => layers.push(path_layer(PathLayer::DirectSpecular)),
+            Aov::IndirectDiffuse => layers.push(path_layer(PathLayer::IndirectDiffuse)),
+            Aov::IndirectSpecular => layers.push(path_layer(PathLayer::IndirectSpecular)),
+            Aov::Lights => {
+                for &(layer, _) in image.layers.iter().filter(|(layer, _)| matches!(layer, PathLayer::Light(_))) {
+                    layers.push(path_layer(layer));
+                }
+            }
+        }
+    }
+    layers
+}
+
+fn sample(samples: &Samples, index: usize) -> f32 {
+    match samples {
+        Samples::Float(samples) => samples[index],
+        Samples::Uint(samples) => samples[index] as f32,
+    }
+}
+
+// a portable float map: greyscale for one channel, otherwise RGB with missing channels left at zero
+fn encode_pfm(width: u32, height: u32, channels: &[(&str, Samples)]) -> Vec<u8> {
+    let (magic, colors) = if channels.len() == 1 { ("Pf", 1) } else { ("PF", 3) };
+    // a negative scale marks little-endian samples
+    let mut bytes = format!("{}\n{} {}\n-1.0\n", magic, width, height).into_bytes();
+    // rows run from bottom to top
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            for channel in 0..colors {
+                let value = channels.get(channel).map_or(0.0, |(_, samples)| sample(samples, index));
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+// writes the AOVs beside `filename` and returns the files written; EXR files hold the linear
+// beauty as well
+pub fn write(filename: &str, format: AovFormat, aovs: &[Aov], image: &Image, surfaces: &[Surface]) -> io::Result<Vec<String>> {
+    let layers = layers(aovs, image, surfaces);
+    match format {
+        AovFormat::Exr => {
+            let mut channels: Vec<Channel> = vectors(&image.pixels, ["R", "G", "B"])
+                .into_iter()
+                .map(|(name, samples)| Channel { name: name.to_string(), samples })
+                .collect();
+            for layer in layers {
+                for (channel, samples) in layer.channels {
+                    channels.push(Channel { name: format!("{}.{}", layer.name, channel), samples });
+                }
+            }
+            let path = format!("{}.exr", filename);
+            fs::write(&path, exr::encode(image.width, image.height, channels)?)?;
+            Ok(vec![path])
+        }
+        AovFormat::Pfm => {
+            let mut paths = Vec::new();
+            for layer in layers {
+                let path = format!("{}.{}.pfm", filename, layer.name);
+                fs::write(&path, encode_pfm(image.width, image.height, &layer.channels))?;
+                paths.push(path);
+            }
+            Ok(paths)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::material::metal::Metal;
+    use crate::renderer::progress::Silent;
+    use crate::renderer::render_image;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::world::HittableList;
+
+    // a matte ball in front of a mirror ball, both on a matte ground sharing the first ball's material
+    fn balls() -> HittableList {
+        let matte: Arc<dyn crate::renderer::material::Material> = Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } });
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere { center: Point { x: 0.0, y: 0.0, z: -3.0 }, radius: 1.0, material: matte.clone() }));
+        world.add(Box::new(Sphere {
+            center: Point { x: 2.0, y: 0.0, z: -4.0 },
+            radius: 1.0,
+            material: Arc::new(Metal { albedo: Color { x: 0.9, y: 0.9, z: 0.9 }, roughness: 0.0 }),
+        }));
+        world.add(Box::new(Sphere { center: Point { x: 0.0, y: -101.0, z: -3.0 }, radius: 100.0, material: matte }));
+        world
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings { width: 48, height: Some(27), samples_per_pixel: 8, depth: 6, threads: 2, ..RenderSettings::default() }
+    }
+
+    #[test]
+    fn test_light_paths_add_up_to_the_beauty() {
+        let split = RenderSettings { light_paths: true, ..settings() };
+        let image = render_image(&split, &balls(), &Silent);
+        let sum = |index: usize, filter: fn(&PathLayer) -> bool| {
+            image.layers.iter().filter(|(layer, _)| filter(layer)).fold(Color::default(), |sum, (_, pixels)| sum + pixels[index])
+        };
+        for (index, beauty) in image.pixels.iter().enumerate() {
+            let lobes = sum(index, |layer| !matches!(layer, PathLayer::Light(_)));
+            let lights = sum(index, |layer| matches!(layer, PathLayer::Light(_)));
+            assert!((lobes - *beauty).length() < 1e-6, "{:?} vs {:?}", lobes, beauty);
+            assert!((lights - *beauty).length() < 1e-6, "{:?} vs {:?}", lights, beauty);
+        }
+        // only the sky shines here, and only the mirror ball reflects it specularly
+        assert_eq!(image.layers.iter().filter(|(layer, _)| matches!(layer, PathLayer::Light(_))).count(), 1);
+        let specular = &image.layers.iter().find(|(layer, _)| *layer == PathLayer::DirectSpecular).unwrap().1;
+        let at = |x: u32, y: u32| (y * image.width + x) as usize;
+        assert!(specular[at(24, 13)].length() == 0.0);
+        assert!(specular[at(31, 13)].length() > 0.1);
+        // the beauty itself does not change
+        let plain = render_image(&settings(), &balls(), &Silent);
+        assert_eq!(plain.pixels.iter().map(|pixel| pixel.x).collect::<Vec<_>>(), image.pixels.iter().map(|pixel| pixel.x).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_surfaces_report_the_first_hit() {
+        let surfaces = render_surfaces(&settings(), &balls());
+        assert_eq!(surfaces.len(), 48 * 27);
+        let center = surfaces[13 * 48 + 24];
+        assert!((center.depth - 2.0).abs() < 0.01, "{}", center.depth);
+        assert!((center.normal.z - 1.0).abs() < 0.01 && (center.camera_normal.z - 1.0).abs() < 0.01);
+        assert_eq!((center.object_id, center.material_id), (1, 1));
+        assert_eq!(center.albedo.x, 0.5);
+        let mirror = surfaces[13 * 48 + 31];
+        assert_eq!((mirror.object_id, mirror.material_id), (2, 2));
+        // the ground shares the matte ball's material
+        let ground = surfaces[26 * 48 + 2];
+        assert_eq!((ground.object_id, ground.material_id), (3, 1));
+        let sky = surfaces[0];
+        assert_eq!((sky.object_id, sky.depth), (0, f64::INFINITY));
+        // the same whatever the threads
+        let single = render_surfaces(&RenderSettings { threads: 1, ..settings() }, &balls());
+        assert!(single.iter().zip(&surfaces).all(|(a, b)| a.depth == b.depth && a.albedo.x == b.albedo.x));
+    }
+
+    #[test]
+    fn test_aov_lists() {
+        assert_eq!("all".parse::<Aovs>().unwrap().0.len(), AOVS.len());
+        assert_eq!("depth,albedo,depth".parse::<Aovs>(), Ok(Aovs(vec![Aov::Depth, Aov::Albedo])));
+        assert!("depth,glow".parse::<Aovs>().is_err());
+        assert!(AOVS.iter().all(|aov| aov.to_string().parse::<Aov>() == Ok(*aov)));
+    }
+}
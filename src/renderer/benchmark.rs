@@ -0,0 +1,59 @@
+use crate::renderer::hit::Hittable;
+use crate::renderer::progress::Silent;
+use crate::renderer::sampler::{SamplerKind, SAMPLER_KINDS};
+use crate::renderer::{render_image, Image, RenderSettings};
+
+pub fn rmse(image: &Image, reference: &Image) -> f64 {
+    let squared_error: f64 = image
+        .pixels
+        .iter()
+        .zip(&reference.pixels)
+        .map(|(pixel, expected)| (*pixel - *expected).length_squared())
+        .sum();
+    (squared_error / (3 * image.pixels.len()) as f64).sqrt()
+}
+
+// renders the world with every sampler at the settings' spp and measures the error against an
+// independent-sampled reference with reference_samples spp (and a different seed, so that no sampler
+// shares its random numbers with the reference)
+pub fn convergence(settings: &RenderSettings, world: &dyn Hittable, reference_samples: u32) -> Vec<(SamplerKind, f64)> {
+    let reference = render_image(
+        &RenderSettings {
+            samples_per_pixel: reference_samples,
+            seed: settings.seed ^ 0x5eed,
+            sampler: SamplerKind::Independent,
+            blue_noise: false,
+            ..*settings
+        },
+        world,
+        &Silent,
+    );
+    SAMPLER_KINDS
+        .iter()
+        .map(|&sampler| {
+            let image = render_image(&RenderSettings { sampler, ..*settings }, world, &Silent);
+            (sampler, rmse(&image, &reference))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::scene::materials;
+
+    #[test]
+    fn test_low_discrepancy_converges_faster() {
+        let settings = RenderSettings {
+            width: 24,
+            samples_per_pixel: 16,
+            depth: 6,
+            ..RenderSettings::default()
+        };
+        let errors = convergence(&settings, &materials(), 512);
+        let independent = errors[0].1;
+        for &(sampler, error) in &errors[1..] {
+            assert!(error < independent, "{}: {} vs {}", sampler, error, independent);
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::renderer::aabb::{axis, Aabb};
+use crate::renderer::hit::{HitRecord, Hittable};
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::vector3d::Point;
+use crate::renderer::world::MaterialIds;
+
+const BINS: usize = 16;
+const MAX_LEAF_SIZE: usize = 2;
+
+enum Node {
+    Leaf { bounds: Aabb, first: usize, count: usize },
+    // the first child directly follows its parent
+    Interior { bounds: Aabb, second: usize, axis: usize },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// bounding volume hierarchy built with the binned surface area heuristic; objects without
+// bounds (none of the current shapes) are tested against every ray
+pub(crate) struct Bvh {
+    nodes: Vec<Node>,
+    objects: Vec<(Box<dyn Hittable>, Ids)>,
+    unbounded: Vec<(Box<dyn Hittable>, Ids)>,
+    // the distinct materials its objects use
+    materials: u32,
+}
+
+// the object's position in the list the hierarchy was built from and its material's, as hit
+// records report them
+#[derive(Copy, Clone, Debug)]
+struct Ids {
+    object: u32,
+    material: u32,
+}
+
+struct Primitive {
+    bounds: Aabb,
+    centroid: Point,
+    object: Box<dyn Hittable>,
+    ids: Ids,
+}
+
+impl Bvh {
+    pub fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+        Self::during(objects, f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    // a hierarchy only valid for rays between the two times, which bounds moving objects more tightly
+    pub fn during(objects: Vec<Box<dyn Hittable>>, start: f64, end: f64) -> Self {
+        let mut primitives = Vec::new();
+        let mut unbounded = Vec::new();
+        let mut materials = MaterialIds::default();
+        for (index, object) in objects.into_iter().enumerate() {
+            let ids = Ids { object: index as u32 + 1, material: materials.id(object.as_ref()) };
+            match object.bounds_during(start, end) {
+                Some(bounds) => primitives.push(Primitive { bounds, centroid: bounds.centroid(), object, ids }),
+                None => unbounded.push((object, ids)),
+            }
+        }
+        let mut nodes = Vec::new();
+        if !primitives.is_empty() {
+            let count = primitives.len();
+            build(&mut nodes, &mut primitives, 0, count);
+        }
+        Self {
+            nodes,
+            objects: primitives.into_iter().map(|primitive| (primitive.object, primitive.ids)).collect(),
+            unbounded,
+            materials: materials.count(),
+        }
+    }
+
+    pub fn object_count(&self) -> u32 {
+        (self.objects.len() + self.unbounded.len()) as u32
+    }
+
+    pub fn material_count(&self) -> u32 {
+        self.materials
+    }
+}
+
+// cheapest split of the primitives at a bin boundary along one axis, as (cost, bin)
+fn best_split(primitives: &[Primitive], centroids: &Aabb, axis_index: usize) -> Option<(f64, usize)> {
+    let extent = centroids.extent_on(axis_index);
+    if extent <= 0.0 {
+        return None;
+    }
+    let bin_of = |primitive: &Primitive| {
+        let offset = (axis(primitive.centroid, axis_index) - centroids.min_on(axis_index)) / extent;
+        ((offset * BINS as f64) as usize).min(BINS - 1)
+    };
+    let mut bins = [(Aabb::empty(), 0usize); BINS];
+    for primitive in primitives {
+        let bin = &mut bins[bin_of(primitive)];
+        bin.0 = bin.0.union(&primitive.bounds);
+        bin.1 += 1;
+    }
+    // sweep from the right to know every suffix's bounds and count
+    let mut right = vec![(Aabb::empty(), 0usize); BINS];
+    let mut accumulated = (Aabb::empty(), 0);
+    for bin in (1..BINS).rev() {
+        accumulated = (accumulated.0.union(&bins[bin].0), accumulated.1 + bins[bin].1);
+        right[bin] = accumulated;
+    }
+    let mut left = (Aabb::empty(), 0);
+    let mut best: Option<(f64, usize)> = None;
+    for bin in 1..BINS {
+        left = (left.0.union(&bins[bin - 1].0), left.1 + bins[bin - 1].1);
+        let (right_bounds, right_count) = right[bin];
+        if left.1 == 0 || right_count == 0 {
+            continue;
+        }
+        let cost = left.0.surface_area() * left.1 as f64 + right_bounds.surface_area() * right_count as f64;
+        if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+            best = Some((cost, bin));
+        }
+    }
+    best
+}
+
+fn build(nodes: &mut Vec<Node>, primitives: &mut [Primitive], first: usize, count: usize) -> usize {
+    let index = nodes.len();
+    let slice = &mut primitives[first..first + count];
+    let bounds = slice.iter().fold(Aabb::empty(), |bounds, primitive| bounds.union(&primitive.bounds));
+    if count <= MAX_LEAF_SIZE {
+        nodes.push(Node::Leaf { bounds, first, count });
+        return index;
+    }
+    let centroids = slice.iter().fold(Aabb::empty(), |bounds, primitive| bounds.include(primitive.centroid));
+    let split = (0..3)
+        .filter_map(|axis_index| best_split(slice, &centroids, axis_index).map(|(cost, bin)| (cost, bin, axis_index)))
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    let Some((cost, bin, axis_index)) = split else {
+        // every centroid coincides
+        nodes.push(Node::Leaf { bounds, first, count });
+        return index;
+    };
+    // a leaf costs one test per primitive, an interior node a traversal step plus the children's tests
+    if count <= 8 && cost / bounds.surface_area() >= count as f64 - 1.0 {
+        nodes.push(Node::Leaf { bounds, first, count });
+        return index;
+    }
+
+    let extent = centroids.extent_on(axis_index);
+    let threshold = centroids.min_on(axis_index) + extent * bin as f64 / BINS as f64;
+    slice.sort_by(|a, b| axis(a.centroid, axis_index).total_cmp(&axis(b.centroid, axis_index)));
+    let middle = slice
+        .iter()
+        .position(|primitive| axis(primitive.centroid, axis_index) >= threshold)
+        .unwrap_or(count / 2)
+        .clamp(1, count - 1);
+
+    nodes.push(Node::Interior { bounds, second: 0, axis: axis_index });
+    build(nodes, primitives, first, middle);
+    let second = build(nodes, primitives, first + middle, count - middle);
+    if let Node::Interior { second: slot, .. } = &mut nodes[index] {
+        *slot = second;
+    }
+    index
+}
+
+fn identify((mut record, ids): (HitRecord, Ids)) -> HitRecord {
+    record.object_id = ids.object;
+    record.material_id = ids.material;
+    record
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut closest_so_far = t_max;
+        for (object, ids) in &self.unbounded {
+            if let Some(record) = object.hit(ray, t_min, closest_so_far, sampler) {
+                closest_so_far = record.t;
+                closest = Some((record, *ids));
+            }
+        }
+        if self.nodes.is_empty() {
+            return closest.map(identify);
+        }
+
+        let inverse_direction = Point {
+            x: 1.0 / ray.direction.x,
+            y: 1.0 / ray.direction.y,
+            z: 1.0 / ray.direction.z,
+        };
+        let negative = [inverse_direction.x < 0.0, inverse_direction.y < 0.0, inverse_direction.z < 0.0];
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            count(Counter::BvhNodeVisits);
+            let node = &self.nodes[index];
+            if !node.bounds().hit(ray, inverse_direction, t_min, closest_so_far) {
+                continue;
+            }
+            match *node {
+                Node::Leaf { first, count, .. } => {
+                    for (object, ids) in &self.objects[first..first + count] {
+                        if let Some(record) = object.hit(ray, t_min, closest_so_far, sampler) {
+                            closest_so_far = record.t;
+                            closest = Some((record, *ids));
+                        }
+                    }
+                }
+                Node::Interior { second, axis, .. } => {
+                    // visit the child nearer along the split axis first
+                    if negative[axis] {
+                        stack.push(index + 1);
+                        stack.push(second);
+                    } else {
+                        stack.push(second);
+                        stack.push(index + 1);
+                    }
+                }
+            }
+        }
+        closest.map(identify)
+    }
+
+    // every object along the ray takes its share, so no subtree can be skipped for a nearer hit
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let mut transmittance = 1.0;
+        for (object, _) in &self.unbounded {
+            transmittance *= object.transmittance(ray, t_min, t_max, sampler);
+        }
+        let inverse_direction = Point {
+            x: 1.0 / ray.direction.x,
+            y: 1.0 / ray.direction.y,
+            z: 1.0 / ray.direction.z,
+        };
+        let mut stack = if self.nodes.is_empty() { vec![] } else { vec![0] };
+        while let Some(index) = stack.pop() {
+            if transmittance == 0.0 {
+                break;
+            }
+            count(Counter::BvhNodeVisits);
+            let node = &self.nodes[index];
+            if !node.bounds().hit(ray, inverse_direction, t_min, t_max) {
+                continue;
+            }
+            match *node {
+                Node::Leaf { first, count, .. } => {
+                    for (object, _) in &self.objects[first..first + count] {
+                        transmittance *= object.transmittance(ray, t_min, t_max, sampler);
+                    }
+                }
+                Node::Interior { second, .. } => {
+                    stack.push(second);
+                    stack.push(index + 1);
+                }
+            }
+        }
+        transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.nodes.first().map(|node| *node.bounds())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+    use crate::renderer::scene::spheres;
+    use crate::renderer::stats;
+    use crate::renderer::vector3d::{unit_vector, random_unit_vector, Vector3D};
+
+    #[test]
+    fn test_bvh_finds_the_same_hits_as_a_list() {
+        let list = spheres(4);
+        let bvh = Bvh::new(spheres(4).into_objects());
+        let mut sampler = Independent::new(0, 0);
+        stats::take();
+        for _ in 0..2000 {
+            let origin = Point { x: 13.0, y: 2.0, z: 3.0 } + random_unit_vector(&mut sampler);
+            let target = Point { x: 0.0, y: 0.0, z: 0.0 } + 6.0 * random_unit_vector(&mut sampler);
+            let ray = Ray { origin, direction: unit_vector(target - origin), time: 0.0 };
+            // and tell the objects and materials apart the same way
+            let identified = |record: HitRecord| (record.t, record.object_id, record.material_id);
+            let expected = list.hit(&ray, 0.001, f64::INFINITY, &mut sampler).map(identified);
+            let actual = bvh.hit(&ray, 0.001, f64::INFINITY, &mut sampler).map(identified);
+            assert_eq!(expected, actual);
+        }
+        // the list tests every sphere for every ray, the hierarchy only a few
+        let counted = stats::take();
+        let list_tests = 2000 * bvh.objects.len() as u64;
+        let bvh_tests = counted.counter(Counter::SphereTests) - list_tests;
+        assert!(bvh_tests < list_tests / 10, "{}", bvh_tests);
+        assert!(counted.counter(Counter::BvhNodeVisits) > 0);
+    }
+
+    #[test]
+    fn test_empty_hierarchy_misses() {
+        let bvh = Bvh::new(Vec::new());
+        let ray = Ray { origin: Point::default(), direction: Vector3D { x: 0.0, y: 0.0, z: -1.0 }, time: 0.0 };
+        assert!(bvh.hit(&ray, 0.001, f64::INFINITY, &mut Independent::new(0, 0)).is_none());
+        assert!(bvh.bounding_box().is_none());
+    }
+}
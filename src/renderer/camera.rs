@@ -1,47 +1,153 @@
-use crate::renderer::ray::Ray;
-use crate::renderer::vector3d::{Point, Vector3D};
+use crate::renderer::motion::Interpolate;
+use crate::renderer::ray::{cross, dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Point, unit_vector, Vector3D};
 
-pub struct Camera {
-    pub aspect_ratio: f64,
-    pub viewport_height: f64,
-    pub viewport_width: f64,
-    pub focal_length: f64,
+// the times the shutter opens and closes, in the seconds moving objects are keyed in; camera rays
+// are spread evenly over the interval, so anything moving in it blurs
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+}
+
+impl Shutter {
+    pub fn is_instant(&self) -> bool {
+        self.close <= self.open
+    }
+
+    // an instant shutter takes no sample, so still renders keep their sample sequences
+    pub fn sample_time(&self, sampler: &mut dyn Sampler) -> f64 {
+        if self.is_instant() {
+            self.open
+        } else {
+            self.open + sampler.next_1d() * (self.close - self.open)
+        }
+    }
+}
+
+// where the camera is, what it looks at and how much of the scene it takes in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraPose {
+    pub look_from: Point,
+    pub look_at: Point,
+    pub up: Vector3D,
+    // in degrees, from the bottom of the frame to the top
+    pub vertical_fov: f64,
+}
 
+impl Default for CameraPose {
+    fn default() -> Self {
+        Self {
+            look_from: Point { x: 0.0, y: 0.0, z: 0.0 },
+            look_at: Point { x: 0.0, y: 0.0, z: -1.0 },
+            up: Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+            vertical_fov: 90.0,
+        }
+    }
+}
+
+impl Interpolate for CameraPose {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        Self {
+            look_from: self.look_from.lerp(other.look_from, t),
+            look_at: self.look_at.lerp(other.look_at, t),
+            up: self.up.lerp(other.up, t),
+            vertical_fov: self.vertical_fov.lerp(other.vertical_fov, t),
+        }
+    }
+}
+
+// the camera's poses when the shutter opens and when it closes, between which it moves evenly
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct CameraMotion {
+    pub open: CameraPose,
+    pub close: CameraPose,
+}
+
+// the image plane a pose puts one unit in front of the camera
+#[derive(Copy, Clone)]
+struct Viewport {
     origin: Point,
     lower_left_corner: Point,
     horizontal: Vector3D,
     vertical: Vector3D,
+}
 
+impl Viewport {
+    fn new(pose: &CameraPose, aspect_ratio: f64) -> Self {
+        let viewport_height = 2.0 * (pose.vertical_fov.to_radians() / 2.0).tan();
+        let viewport_width = aspect_ratio * viewport_height;
+        let backward = unit_vector(pose.look_from - pose.look_at);
+        let right = unit_vector(cross(pose.up, backward));
+        let up = cross(backward, right);
+        let origin = pose.look_from;
+        let horizontal = viewport_width * right;
+        let vertical = viewport_height * up;
+        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - backward;
+        Self { origin, lower_left_corner, horizontal, vertical }
+    }
+}
+
+#[allow(dead_code)]
+pub struct Camera {
+    pub aspect_ratio: f64,
+    pub viewport_height: f64,
+    pub viewport_width: f64,
+    pub focal_length: f64,
+    pub shutter: Shutter,
+
+    motion: CameraMotion,
+    // as the shutter opens, and for the whole exposure when the camera stands still
+    viewport: Viewport,
+    still: bool,
 }
 
 impl Camera {
-    pub fn new() -> Self {
-        let aspect_ratio = 16.0 / 9.0;
-        let viewport_height = 2.0;
-        let viewport_width = aspect_ratio * viewport_height;
-        let focal_length = 1.0;
-        let origin = Point { x: 0.0, y: 0.0, z: 0.0 };
-        let horizontal = Vector3D { x: viewport_width, y: 0.0, z: 0.0 };
-        let vertical = Vector3D { x: 0.0, y: viewport_height, z: 0.0 };
-        let lower_left_corner = origin - (horizontal / 2.0) - (vertical / 2.0) - Vector3D { x: 0.0, y: 0.0, z: focal_length };
+    // aspect_ratio is the width over the height of the frame as displayed, not in pixels
+    pub fn new(aspect_ratio: f64, shutter: Shutter, motion: CameraMotion) -> Self {
+        let viewport = Viewport::new(&motion.open, aspect_ratio);
+        let viewport_height = viewport.vertical.length();
 
         Self {
             aspect_ratio,
             viewport_height,
-            viewport_width,
-            focal_length,
-            origin,
-            horizontal,
-            vertical,
-            lower_left_corner,
+            viewport_width: aspect_ratio * viewport_height,
+            focal_length: 1.0,
+            shutter,
+            motion,
+            viewport,
+            still: motion.open == motion.close || shutter.is_instant(),
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    // u and v run from 0 to 1 across the frame and beyond it for overscan
+    pub fn get_ray(&self, u: f64, v: f64, time: f64) -> Ray {
+        let viewport = if self.still {
+            self.viewport
+        } else {
+            let fraction = (time - self.shutter.open) / (self.shutter.close - self.shutter.open);
+            Viewport::new(&self.motion.open.lerp(self.motion.close, fraction), self.aspect_ratio)
+        };
         Ray {
-            origin: self.origin,
-            direction: self.lower_left_corner + (u * self.horizontal) + (v * self.vertical) - self.origin,
+            origin: viewport.origin,
+            direction: viewport.lower_left_corner + (u * viewport.horizontal) + (v * viewport.vertical) - viewport.origin,
+            time,
         }
     }
 
-}
\ No newline at end of file
+    // distance in front of the camera along its view axis, as the shutter opens
+    pub fn depth(&self, point: Point) -> f64 {
+        -self.to_camera(point - self.viewport.origin).z
+    }
+
+    // camera space has x to the right, y up and the camera looking down -z
+    pub fn to_camera(&self, direction: Vector3D) -> Vector3D {
+        let viewport = &self.viewport;
+        let right = unit_vector(viewport.horizontal);
+        let up = unit_vector(viewport.vertical);
+        let backward = unit_vector(viewport.origin - (viewport.lower_left_corner + viewport.horizontal / 2.0 + viewport.vertical / 2.0));
+        Vector3D { x: dot(direction, right), y: dot(direction, up), z: dot(direction, backward) }
+    }
+
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use crate::renderer::adaptive::RunningVariance;
+use crate::renderer::framebuffer::{Framebuffer, Pixel, Splat, Window};
+use crate::renderer::{film_window, new_framebuffer, RenderSettings};
+use crate::renderer::vector3d::Color;
+
+const MAGIC: &[u8; 8] = b"RTCHKPT2";
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// FNV-1a
+fn hash_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// identifies everything that decides what a sample returns: the scene, the film and the sampler.
+// The sample budget, adaptive tolerance, tiling and thread count may change between runs, and the crop
+// window is checked separately so that crops can be patched into full-frame checkpoints
+pub fn fingerprint(scene: &[u8], settings: &RenderSettings) -> u64 {
+    let description = format!(
+        "{} {:?} {} {} {} {:?} {} {:?} {:?} {} {} {} {} {} {:?} {:?} {:?}",
+        settings.width,
+        settings.height,
+        settings.pixel_aspect,
+        settings.overscan,
+        settings.filter,
+        settings.filter_radius,
+        settings.depth,
+        settings.lobe_depths,
+        settings.roulette,
+        settings.seed,
+        settings.sampler,
+        settings.blue_noise,
+        settings.working_space,
+        settings.spectral,
+        settings.fireflies,
+        settings.shutter,
+        settings.camera
+    );
+    hash_bytes(hash_bytes(0xcbf29ce484222325, scene), description.as_bytes())
+}
+
+// little-endian: magic, fingerprint, seed, sampler name, blue noise, the window (x, y, width, height), then per pixel
+// the radiance sum, the Welford state of its luminance and the fixed-point filtered sums, over the
+// sampled window the filter settings give. The sampler needs nothing else to resume: a pixel's next
+// sample index is its sample count
+pub fn save<P: AsRef<Path>>(path: P, fingerprint: u64, settings: &RenderSettings, framebuffer: &Framebuffer) -> Result<()> {
+    let mut bytes = Vec::with_capacity(64 + framebuffer.pixels.len() * 116);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&fingerprint.to_le_bytes());
+    bytes.extend_from_slice(&settings.seed.to_le_bytes());
+    let sampler = settings.sampler.to_string();
+    bytes.push(sampler.len() as u8);
+    bytes.extend_from_slice(sampler.as_bytes());
+    bytes.push(settings.blue_noise as u8);
+    let window = framebuffer.window;
+    for value in [window.x, window.y, window.width, window.height] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for (pixel, splat) in framebuffer.pixels.iter().zip(&framebuffer.splats) {
+        for value in [pixel.sum.x, pixel.sum.y, pixel.sum.z] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let (count, mean, m2) = pixel.estimate.state();
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(&mean.to_le_bytes());
+        bytes.extend_from_slice(&m2.to_le_bytes());
+        for sum in splat.sums {
+            bytes.extend_from_slice(&sum.to_le_bytes());
+        }
+    }
+
+    // write next to the target and rename, so that a kill mid-write keeps the previous checkpoint
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes)?;
+    fs::rename(partial, path)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        let end = self.position + count;
+        if end > self.bytes.len() {
+            return Err(invalid("truncated checkpoint".to_string()));
+        }
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn i128(&mut self) -> Result<i128> {
+        Ok(i128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P, fingerprint: u64, settings: &RenderSettings) -> Result<Framebuffer> {
+    let bytes = fs::read(path)?;
+    let mut reader = Reader { bytes: &bytes, position: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(invalid("not a checkpoint file".to_string()));
+    }
+    if reader.u64()? != fingerprint {
+        return Err(invalid("checkpoint was rendered from a different scene or with different settings".to_string()));
+    }
+    let seed = reader.u64()?;
+    let length = reader.u8()? as usize;
+    let sampler = String::from_utf8_lossy(reader.take(length)?).to_string();
+    let blue_noise = reader.u8()? != 0;
+    if seed != settings.seed || sampler != settings.sampler.to_string() || blue_noise != settings.blue_noise {
+        return Err(invalid(format!("checkpoint was sampled with {} (seed {}), not {} (seed {})", sampler, seed, settings.sampler, settings.seed)));
+    }
+
+    let window = Window { x: reader.u32()?, y: reader.u32()?, width: reader.u32()?, height: reader.u32()? };
+    if window != film_window(settings) {
+        return Err(invalid(format!("checkpoint covers {:?}, not {:?}", window, film_window(settings))));
+    }
+    let mut framebuffer = new_framebuffer(settings);
+    for (pixel, splat) in framebuffer.pixels.iter_mut().zip(framebuffer.splats.iter_mut()) {
+        let sum = Color { x: reader.f64()?, y: reader.f64()?, z: reader.f64()? };
+        let estimate = RunningVariance::from_state(reader.u32()?, reader.f64()?, reader.f64()?);
+        *pixel = Pixel { sum, estimate };
+        *splat = Splat { sums: [reader.i128()?, reader.i128()?, reader.i128()?, reader.i128()?] };
+    }
+    if reader.position != bytes.len() {
+        return Err(invalid("trailing data after checkpoint".to_string()));
+    }
+    Ok(framebuffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use crate::renderer::progress::Silent;
+    use crate::renderer::progressive::{render_progressive, Progressive};
+    use crate::renderer::render_image;
+    use crate::renderer::scene::spheres;
+    use crate::renderer::color_space::OutputTransform;
+
+    fn small() -> RenderSettings {
+        RenderSettings {
+            width: 24,
+            samples_per_pixel: 8,
+            depth: 6,
+            ..RenderSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_resumed_render_matches_uninterrupted_render() {
+        let world = spheres(0);
+        let path = env::temp_dir().join(format!("raytracer_checkpoint_{}.chk", std::process::id()));
+        let fingerprint = fingerprint(b"spheres", &small());
+
+        // stop after the first pass of 3 spp, as if the process had been killed
+        let interrupted = RenderSettings { samples_per_pixel: 3, ..small() };
+        let framebuffer = render_progressive(&interrupted, &world, &Progressive::new(3), None, &Silent, |_| {});
+        save(&path, fingerprint, &small(), &framebuffer).unwrap();
+
+        let resumed = load(&path, fingerprint, &small()).unwrap();
+        let finished = render_progressive(&small(), &world, &Progressive::new(3), Some(resumed), &Silent, |_| {});
+        fs::remove_file(&path).unwrap();
+        let transform = OutputTransform::default();
+        assert_eq!(finished.to_image().to_ppm(&transform), render_image(&small(), &world, &Silent).to_ppm(&transform));
+    }
+
+    #[test]
+    fn test_mismatched_checkpoint_is_refused() {
+        let path = env::temp_dir().join(format!("raytracer_mismatch_{}.chk", std::process::id()));
+        save(&path, fingerprint(b"spheres", &small()), &small(), &new_framebuffer(&small())).unwrap();
+        assert!(load(&path, fingerprint(b"materials", &small()), &small()).is_err());
+        let wider = RenderSettings { width: 48, ..small() };
+        assert!(load(&path, fingerprint(b"spheres", &wider), &wider).is_err());
+        let cropped = RenderSettings { crop: Some(Window { x: 1, y: 1, width: 4, height: 4 }), ..small() };
+        assert!(load(&path, fingerprint(b"spheres", &cropped), &cropped).is_err());
+        assert!(load(&path, fingerprint(b"spheres", &small()), &small()).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,40 +1,448 @@
-use crate::renderer::hit::Hittable;
+use std::f64::consts::PI;
+use std::ops;
+use crate::renderer::aov::{Light, LightPaths};
+use crate::renderer::color_space::Conversion;
+use crate::renderer::firefly::{clamp_factor, FireflySuppression};
+use crate::renderer::material::Lobe;
+use crate::renderer::hit::{HitRecord, Hittable};
+use crate::renderer::RenderSettings;
 use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{xyz_to_srgb, RgbSpectrum, Spectrum, Wavelengths, SPECTRUM_SAMPLES};
+use crate::renderer::stats::{count, record_path_length, Counter};
 use crate::renderer::util::clamp;
-use crate::renderer::vector3d::{Color, unit_vector};
+use crate::renderer::vector3d::{Color, random_unit_vector, unit_vector, Vector3D};
 
-pub fn write_color(pixel_color: &Color, samples: i32) -> String {
-    let mut r = pixel_color.x;
-    let mut g = pixel_color.y;
-    let mut b = pixel_color.z;
-
-    let scale = 1.0 / samples as f64;
-    r = (r * scale).sqrt();
-    g = (g * scale).sqrt();
-    b = (b * scale).sqrt();
+// quantizes a display-encoded color to 8 bits per channel
+pub fn to_bytes(encoded: &Color) -> [u8; 3] {
+    [encoded.x, encoded.y, encoded.z].map(|channel| (256.0 * clamp(channel, 0.0, 0.999)) as u8)
+}
 
-    let ir = (256.0 * clamp(r, 0.0, 0.999)) as u32;
-    let ig = (256.0 * clamp(g, 0.0, 0.999)) as u32;
-    let ib = (256.0 * clamp(b, 0.0, 0.999)) as u32;
+pub fn write_color(encoded: &Color) -> String {
+    let [ir, ig, ib] = to_bytes(encoded);
 
     format!("{} {} {}\n", ir, ig, ib)
 }
 
-pub fn ray_color(ray: &Ray, world: &dyn Hittable, depth: i32) -> Color {
-    if depth <= 0 {
-        return Color { x: 0.0, y: 0.0, z: 0.0 };
+// what paths carry light in: RGB in the working space or radiance at a set of wavelengths
+pub trait Transport {
+    type Radiance: Copy
+        + Default
+        + ops::Add<Output = Self::Radiance>
+        + ops::Mul<Output = Self::Radiance>
+        + ops::Mul<f64, Output = Self::Radiance>;
+    const ONE: Self::Radiance;
+
+    fn emitted(&self, hit_record: &HitRecord) -> Self::Radiance;
+    fn scatter(&mut self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Self::Radiance, Ray)>;
+    fn sky(&self, direction: Vector3D) -> Self::Radiance;
+    // the largest factor radiance is carried on by, which Russian roulette keeps paths alive by
+    fn max_component(radiance: Self::Radiance) -> f64;
+    // the working-space color of radiance the path found, once the path is complete
+    fn resolve(&self, radiance: Self::Radiance) -> Color;
+}
+
+// materials and the sky give linear sRGB colors, which `to_working` converts to the working space
+pub struct Rgb {
+    pub to_working: Conversion,
+}
+
+impl Transport for Rgb {
+    type Radiance = Color;
+    const ONE: Color = Color { x: 1.0, y: 1.0, z: 1.0 };
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.to_working.apply(hit_record.material.emitted(hit_record))
+    }
+
+    fn scatter(&mut self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let (attenuation, scattered) = hit_record.material.scatter(ray, hit_record, sampler)?;
+        Some((self.to_working.apply(attenuation), scattered))
+    }
+
+    fn sky(&self, direction: Vector3D) -> Color {
+        self.to_working.apply(sky(direction))
+    }
+
+    fn max_component(radiance: Color) -> f64 {
+        radiance.x.max(radiance.y).max(radiance.z)
+    }
+
+    fn resolve(&self, radiance: Color) -> Color {
+        radiance
+    }
+}
+
+// the materials' and the sky's spectra, upsampled from their linear sRGB colors
+pub struct Spectral {
+    pub wavelengths: Wavelengths,
+    pub to_working: Conversion,
+}
+
+impl Transport for Spectral {
+    type Radiance = Spectrum;
+    const ONE: Spectrum = Spectrum { values: [1.0; SPECTRUM_SAMPLES] };
+
+    fn emitted(&self, hit_record: &HitRecord) -> Spectrum {
+        hit_record.material.emitted_spectral(hit_record, &self.wavelengths)
+    }
+
+    fn scatter(&mut self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Spectrum, Ray)> {
+        hit_record.material.scatter_spectral(ray, hit_record, &mut self.wavelengths, sampler)
+    }
+
+    fn sky(&self, direction: Vector3D) -> Spectrum {
+        RgbSpectrum::illuminant(sky(direction)).sample(&self.wavelengths)
     }
-    return match world.hit(ray, 0.001, f64::INFINITY) {
-        Some(hit_record) => {
-            match hit_record.material.scatter(ray, &hit_record) {
-                Some((attenuation, scattered)) => attenuation * ray_color(&scattered, world, depth - 1),
-                None => Color { x: 0.0, y: 0.0, z: 0.0 }
+
+    fn max_component(radiance: Spectrum) -> f64 {
+        radiance.values.iter().fold(0.0, |max, &value| value.max(max))
+    }
+
+    // with the final wavelength densities, which dispersion may have changed after earlier light was found
+    fn resolve(&self, radiance: Spectrum) -> Color {
+        self.to_working.apply(xyz_to_srgb(self.wavelengths.estimate_xyz(&radiance)))
+    }
+}
+
+// bounces of each kind a path may take, within the overall depth; unlimited where unset
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LobeDepths {
+    pub diffuse: Option<u32>,
+    pub glossy: Option<u32>,
+    pub transmission: Option<u32>,
+    pub volume: Option<u32>,
+}
+
+impl LobeDepths {
+    fn limit(&self, lobe: Lobe) -> Option<u32> {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Glossy => self.glossy,
+            Lobe::Transmission => self.transmission,
+            Lobe::Volume => self.volume,
+        }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.diffuse.is_some() || self.glossy.is_some() || self.transmission.is_some() || self.volume.is_some()
+    }
+}
+
+// light found after the first bounce, scaled down to the indirect limit if it exceeds it
+fn clamp_indirect<T: Transport>(transport: &T, light: T::Radiance, bounce: u32, fireflies: &FireflySuppression) -> Option<T::Radiance> {
+    if bounce <= 1 || fireflies.max_indirect.is_none() {
+        return None;
+    }
+    clamp_factor(transport.resolve(light).luminance(), fireflies.max_indirect).map(|factor| light * factor)
+}
+
+// directions towards the sky are sampled uniformly over the sphere
+const SKY_PDF: f64 = 1.0 / (4.0 * PI);
+
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other * other)
+}
+
+// the sky seen from a scattering event in a medium in a uniform direction, dimmed by what lies in
+// the way and weighed against the phase function finding it by itself
+fn sky_through_media<T: Transport>(
+    transport: &T,
+    world: &dyn Hittable,
+    ray: &Ray,
+    hit_record: &HitRecord,
+    sampler: &mut dyn Sampler,
+) -> T::Radiance {
+    let direction = random_unit_vector(sampler);
+    let phase = hit_record.material.phase_pdf(ray, direction).unwrap_or(0.0);
+    count(Counter::ShadowRays);
+    let shadow_ray = Ray { origin: hit_record.point, direction, time: ray.time };
+    let transmittance = world.transmittance(&shadow_ray, 0.001, f64::INFINITY, sampler);
+    transport.sky(direction) * (transmittance * phase * power_heuristic(SKY_PDF, phase) / SKY_PDF)
+}
+
+// radiance arriving along the camera ray; `light_paths` also gets it split by where the light
+// came from and how it reached the camera
+pub fn ray_color<T: Transport>(
+    ray: &Ray,
+    world: &dyn Hittable,
+    settings: &RenderSettings,
+    transport: &mut T,
+    sampler: &mut dyn Sampler,
+    light_paths: Option<&mut LightPaths>,
+) -> Color {
+    let fireflies = &settings.fireflies;
+    // the kind of every bounce is only needed to limit the kinds or to regularize after diffuse ones
+    let classify = settings.lobe_depths.is_limited() || fireflies.regularize.is_some();
+    let mut lobe_bounces = [0; 4];
+    let mut ray = *ray;
+    let mut radiance = T::Radiance::default();
+    let mut throughput = T::ONE;
+    // light found along the path as (light, bounce, radiance), kept only for the split
+    let mut found = Vec::new();
+    let mut diffuse_share = 1.0;
+    // whether the path has bounced diffusely, after which regularization roughens specular lobes
+    let mut diffuse = false;
+    let mut clamped = false;
+    // the phase function's density for the direction the path left a medium in, where the sky was
+    // also sampled directly
+    let mut phase_pdf = None;
+    // the camera ray used bounce 0 of the sampler's dimensions
+    let mut bounce = 1;
+    loop {
+        if bounce as i32 > settings.depth {
+            record_path_length(bounce - 1);
+            break;
+        }
+        sampler.start_bounce(bounce);
+        if bounce > 1 {
+            count(Counter::BounceRays);
+        }
+        let Some(mut hit_record) = world.hit(&ray, 0.001, f64::INFINITY, sampler) else {
+            let mut light = throughput * transport.sky(ray.direction);
+            if let Some(pdf) = phase_pdf {
+                light = light * power_heuristic(pdf, SKY_PDF);
+            }
+            if let Some(limited) = clamp_indirect(transport, light, bounce, fireflies) {
+                light = limited;
+                clamped = true;
             }
-        },
-        None => {
-            let unit_direction = unit_vector(ray.direction);
-            let t = 0.5 * (unit_direction.y + 1.0);
-            (1.0 as f64 - t) * Color { x: 1.0, y: 1.0, z: 1.0 } + t * Color { x: 0.5, y: 0.7, z: 1.0 }
+            radiance = radiance + light;
+            if light_paths.is_some() {
+                found.push((Light::Sky, bounce, light));
+            }
+            record_path_length(bounce - 1);
+            break;
+        };
+        let mut emitted = throughput * transport.emitted(&hit_record);
+        if let Some(limited) = clamp_indirect(transport, emitted, bounce, fireflies) {
+            emitted = limited;
+            clamped = true;
+        }
+        radiance = radiance + emitted;
+        if light_paths.is_some() {
+            found.push((Light::Object(hit_record.object_id), bounce, emitted));
+        }
+        if let (true, Some(roughness)) = (diffuse, fireflies.regularize) {
+            if let Some(material) = hit_record.material.regularized(roughness) {
+                hit_record.material = material;
+            }
+        }
+        let Some((attenuation, scattered)) = transport.scatter(&ray, &hit_record, sampler) else {
+            record_path_length(bounce - 1);
+            break;
+        };
+        if bounce == 1 && light_paths.is_some() {
+            diffuse_share = hit_record.material.diffuse_share(&ray, &hit_record, &scattered);
+        }
+        if classify {
+            let lobe = hit_record.material.lobe(&ray, &hit_record, &scattered);
+            diffuse = diffuse || matches!(lobe, Lobe::Diffuse | Lobe::Volume);
+            let taken = &mut lobe_bounces[lobe as usize];
+            *taken += 1;
+            if settings.lobe_depths.limit(lobe).is_some_and(|limit| *taken > limit) {
+                record_path_length(bounce);
+                break;
+            }
+        }
+        throughput = throughput * attenuation;
+        // the sky the next bounce could still find is also sampled directly from inside media
+        phase_pdf = hit_record.material.phase_pdf(&ray, scattered.direction).filter(|_| (bounce as i32) < settings.depth);
+        if phase_pdf.is_some() {
+            let mut light = throughput * sky_through_media(transport, world, &ray, &hit_record, sampler);
+            if let Some(limited) = clamp_indirect(transport, light, bounce + 1, fireflies) {
+                light = limited;
+                clamped = true;
+            }
+            radiance = radiance + light;
+            if light_paths.is_some() {
+                found.push((Light::Sky, bounce + 1, light));
+            }
+        }
+        if settings.roulette.is_some_and(|minimum| bounce > minimum) {
+            let survival = T::max_component(throughput).min(1.0);
+            if sampler.next_1d() >= survival {
+                record_path_length(bounce);
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+        ray = scattered;
+        bounce += 1;
+    }
+
+    let color = transport.resolve(radiance);
+    let factor = clamp_factor(color.luminance(), fireflies.max_sample);
+    if clamped || factor.is_some() {
+        count(Counter::ClampedSamples);
+    }
+    let factor = factor.unwrap_or(1.0);
+    if let Some(light_paths) = light_paths {
+        for (light, bounce, radiance) in found {
+            light_paths.add(light, bounce, diffuse_share, factor * transport.resolve(radiance));
         }
-    };
-}
\ No newline at end of file
+    }
+    factor * color
+}
+
+fn sky(direction: Vector3D) -> Color {
+    let unit_direction = unit_vector(direction);
+    let t = 0.5 * (unit_direction.y + 1.0);
+    (1.0 - t) * Color { x: 1.0, y: 1.0, z: 1.0 } + t * Color { x: 0.5, y: 0.7, z: 1.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::renderer::color_space::ColorSpace;
+    use crate::renderer::bvh::Bvh;
+    use crate::renderer::heterogeneous_medium::HeterogeneousMedium;
+    use crate::renderer::material::henyey_greenstein::HenyeyGreenstein;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::material::metal::Metal;
+    use crate::renderer::material::Material;
+    use crate::renderer::sampler::independent::Independent;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::stats::take;
+    use crate::renderer::vector3d::Point;
+    use crate::renderer::voxel_grid::VoxelGrid;
+    use crate::renderer::world::HittableList;
+
+    // a matte ball straight ahead and a mirror ball to its right
+    fn balls() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere {
+            center: Point { x: 0.0, y: 0.0, z: -3.0 },
+            radius: 1.0,
+            material: Arc::new(Lambert { albedo: Color { x: 0.8, y: 0.8, z: 0.8 } }),
+        }));
+        world.add(Box::new(Sphere {
+            center: Point { x: 2.0, y: 0.0, z: -3.0 },
+            radius: 1.0,
+            material: Arc::new(Metal { albedo: Color { x: 0.9, y: 0.9, z: 0.9 }, roughness: 0.0 }),
+        }));
+        world
+    }
+
+    fn trace(target: Point, settings: &RenderSettings, count: usize) -> Vec<Color> {
+        let world = balls();
+        let ray = Ray { origin: Point::default(), direction: target - Point::default(), time: 0.0 };
+        let mut sampler = Independent::new(0, 0);
+        let mut transport = Rgb { to_working: Conversion::new(ColorSpace::Srgb, ColorSpace::Srgb) };
+        (0..count).map(|_| ray_color(&ray, &world, settings, &mut transport, &mut sampler, None)).collect()
+    }
+
+    fn samples(target: Point, fireflies: &FireflySuppression) -> Vec<Color> {
+        trace(target, &RenderSettings { depth: 8, fireflies: *fireflies, ..RenderSettings::default() }, 500)
+    }
+
+    fn mean_luminance(colors: &[Color]) -> f64 {
+        colors.iter().map(|color| color.luminance()).sum::<f64>() / colors.len() as f64
+    }
+
+    #[test]
+    fn test_clamped_samples_stay_under_the_limit() {
+        take();
+        let fireflies = FireflySuppression { max_sample: Some(0.3), ..FireflySuppression::default() };
+        let colors = samples(Point { x: 0.0, y: 0.0, z: -2.0 }, &fireflies);
+        assert!(colors.iter().all(|color| color.luminance() <= 0.3 + 1e-9));
+        assert!(take().counter(Counter::ClampedSamples) > 0);
+    }
+
+    #[test]
+    fn test_indirect_clamping_spares_direct_light() {
+        let fireflies = FireflySuppression { max_indirect: Some(0.05), ..FireflySuppression::default() };
+        let sky = samples(Point { x: 0.0, y: 1.0, z: 0.0 }, &fireflies);
+        assert!((sky[0].luminance() - samples(Point { x: 0.0, y: 1.0, z: 0.0 }, &FireflySuppression::default())[0].luminance()).abs() < 1e-12);
+        // every path off the matte ball finds the sky exactly once, after its first bounce
+        let matte = samples(Point { x: 0.0, y: 0.0, z: -2.0 }, &fireflies);
+        assert!(matte.iter().all(|color| color.luminance() <= 0.05 + 1e-9));
+    }
+
+    #[test]
+    fn test_regularization_waits_for_a_diffuse_bounce() {
+        // the mirror seen directly stays sharp, however rough regularization makes it later on
+        let fireflies = FireflySuppression { regularize: Some(0.5), ..FireflySuppression::default() };
+        let mirror = samples(Point { x: 1.5, y: 0.3, z: -2.2 }, &fireflies);
+        assert!(mirror.iter().all(|color| (*color - mirror[0]).length() < 1e-12));
+    }
+
+    #[test]
+    fn test_russian_roulette_is_unbiased_and_shortens_paths() {
+        let matte = Point { x: 0.0, y: -0.3, z: -2.0 };
+        let full = RenderSettings { depth: 8, roulette: None, ..RenderSettings::default() };
+        take();
+        let reference = mean_luminance(&trace(matte, &full, 20000));
+        let full_bounces = take().counter(Counter::BounceRays);
+        let estimate = mean_luminance(&trace(matte, &RenderSettings { roulette: Some(0), ..full }, 20000));
+        let roulette_bounces = take().counter(Counter::BounceRays);
+        assert!((estimate - reference).abs() < 0.02 * reference, "{} vs {}", estimate, reference);
+        assert!(roulette_bounces < full_bounces, "{} vs {}", roulette_bounces, full_bounces);
+    }
+
+    #[test]
+    fn test_lobe_depths_limit_each_kind_of_bounce() {
+        let no_diffuse = RenderSettings {
+            depth: 8,
+            lobe_depths: LobeDepths { diffuse: Some(0), ..LobeDepths::default() },
+            ..RenderSettings::default()
+        };
+        // the matte ball goes black, while the mirror still shows the sky
+        assert!(trace(Point { x: 0.0, y: 0.0, z: -2.0 }, &no_diffuse, 100).iter().all(|color| color.luminance() == 0.0));
+        assert!(trace(Point { x: 1.5, y: 0.3, z: -2.2 }, &no_diffuse, 100).iter().all(|color| color.luminance() > 0.5));
+        let no_glossy = RenderSettings { lobe_depths: LobeDepths { glossy: Some(0), ..LobeDepths::default() }, ..no_diffuse };
+        assert!(trace(Point { x: 1.5, y: 0.3, z: -2.2 }, &no_glossy, 100).iter().all(|color| color.luminance() == 0.0));
+    }
+
+    // the phase function without its density, so paths only find the sky by scattering into it
+    struct PhaseSampledOnly(HenyeyGreenstein);
+
+    impl Material for PhaseSampledOnly {
+        fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+            self.0.scatter(ray, hit_record, sampler)
+        }
+
+        fn albedo(&self, hit_record: &HitRecord) -> Color {
+            self.0.albedo(hit_record)
+        }
+    }
+
+    // a cloud over a matte ball that shades part of it from the sky
+    fn cloud(phase_function: Arc<dyn Material>) -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(HeterogeneousMedium {
+            grid: Arc::new(VoxelGrid::new([1, 1, 1], 1, vec![1.0]).unwrap()),
+            min: Point { x: -1.0, y: -1.0, z: -1.0 },
+            max: Point { x: 1.0, y: 1.0, z: 1.0 },
+            density_scale: 1.5,
+            phase_function,
+        }));
+        world.add(Box::new(Sphere {
+            center: Point { x: 0.0, y: -3.0, z: 0.0 },
+            radius: 1.5,
+            material: Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } }),
+        }));
+        world
+    }
+
+    #[test]
+    fn test_sky_sampled_from_media_agrees_with_phase_sampling() {
+        let phase_function = || HenyeyGreenstein { albedo: Color { x: 0.9, y: 0.9, z: 0.9 }, g: 0.4, emission: None };
+        let ray = Ray { origin: Point { x: 0.0, y: 0.0, z: 5.0 }, direction: Vector3D { x: 0.0, y: 0.0, z: -1.0 }, time: 0.0 };
+        let settings = RenderSettings { depth: 4, ..RenderSettings::default() };
+        let mut transport = Rgb { to_working: Conversion::new(ColorSpace::Srgb, ColorSpace::Srgb) };
+        let mut sampler = Independent::new(0, 0);
+        let mut mean = |world: &dyn Hittable| {
+            let colors: Vec<Color> = (0..40000).map(|_| ray_color(&ray, world, &settings, &mut transport, &mut sampler, None)).collect();
+            mean_luminance(&colors)
+        };
+
+        take();
+        let reference = mean(&cloud(Arc::new(PhaseSampledOnly(phase_function()))));
+        assert_eq!(take().counter(Counter::ShadowRays), 0);
+        let estimate = mean(&Bvh::new(cloud(Arc::new(phase_function())).into_objects()));
+        assert!(take().counter(Counter::ShadowRays) > 0);
+        assert!((estimate - reference).abs() < 0.02 * reference, "{} vs {}", estimate, reference);
+    }
+}
@@ -0,0 +1,353 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::renderer::tone_mapping::ToneMapping;
+use crate::renderer::vector3d::Color;
+
+pub(crate) type Matrix = [[f64; 3]; 3];
+
+pub(crate) fn transform(matrix: &Matrix, color: Color) -> Color {
+    let row = |row: &[f64; 3]| row[0] * color.x + row[1] * color.y + row[2] * color.z;
+    Color { x: row(&matrix[0]), y: row(&matrix[1]), z: row(&matrix[2]) }
+}
+
+pub(crate) fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+pub(crate) fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+    let determinant = m[0][0] * adjugate[0][0] + m[0][1] * adjugate[1][0] + m[0][2] * adjugate[2][0];
+    adjugate.map(|row| row.map(|value| value / determinant))
+}
+
+// XYZ of a chromaticity at luminance 1
+fn xyz(chromaticity: (f64, f64)) -> Color {
+    let (x, y) = chromaticity;
+    Color { x: x / y, y: 1.0, z: (1.0 - x - y) / y }
+}
+
+const D65: (f64, f64) = (0.3127, 0.3290);
+// the ACES white, close to D60
+const ACES_WHITE: (f64, f64) = (0.32168, 0.33767);
+
+// Bradford cone response, for adapting colors between white points
+const BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+// RGB spaces by their primaries and white point; all of them hold linear values
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorSpace {
+    // sRGB and Rec.709 share primaries and white
+    Srgb,
+    // ACES AP1, the usual working space for wide-gamut rendering
+    AcesCg,
+    DisplayP3,
+    Rec2020,
+}
+
+pub const COLOR_SPACES: [ColorSpace; 4] = [ColorSpace::Srgb, ColorSpace::AcesCg, ColorSpace::DisplayP3, ColorSpace::Rec2020];
+
+impl ColorSpace {
+    // red, green and blue primaries, then the white point
+    fn chromaticities(&self) -> [(f64, f64); 4] {
+        match self {
+            ColorSpace::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), D65],
+            ColorSpace::AcesCg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), ACES_WHITE],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65],
+        }
+    }
+
+    fn white(&self) -> Color {
+        xyz(self.chromaticities()[3])
+    }
+
+    pub fn to_xyz(self) -> Matrix {
+        let [red, green, blue, _] = self.chromaticities().map(xyz);
+        let primaries = [[red.x, green.x, blue.x], [red.y, green.y, blue.y], [red.z, green.z, blue.z]];
+        // scale the primaries so that RGB (1, 1, 1) lands on the white point
+        let scale = transform(&invert(&primaries), self.white());
+        primaries.map(|row| [row[0] * scale.x, row[1] * scale.y, row[2] * scale.z])
+    }
+
+    pub fn xyz_to_rgb(self) -> Matrix {
+        invert(&self.to_xyz())
+    }
+
+    // RGB in this space to RGB in `target`, adapting the white point with Bradford
+    pub fn conversion(&self, target: ColorSpace) -> Matrix {
+        let source_cone = transform(&BRADFORD, self.white());
+        let target_cone = transform(&BRADFORD, target.white());
+        let scale = [
+            [target_cone.x / source_cone.x, 0.0, 0.0],
+            [0.0, target_cone.y / source_cone.y, 0.0],
+            [0.0, 0.0, target_cone.z / source_cone.z],
+        ];
+        let adaptation = multiply(&invert(&BRADFORD), &multiply(&scale, &BRADFORD));
+        multiply(&invert(&target.to_xyz()), &multiply(&adaptation, &self.to_xyz()))
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::AcesCg => "acescg",
+            ColorSpace::DisplayP3 => "p3",
+            ColorSpace::Rec2020 => "rec2020",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        COLOR_SPACES
+            .iter()
+            .find(|space| space.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown color space '{}'", name))
+    }
+}
+
+// converts colors from the space they were authored in to the working space; free when they match
+#[derive(Copy, Clone, Debug)]
+pub struct Conversion {
+    matrix: Option<Matrix>,
+}
+
+impl Conversion {
+    pub fn new(source: ColorSpace, target: ColorSpace) -> Self {
+        Self { matrix: (source != target).then(|| source.conversion(target)) }
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        match &self.matrix {
+            Some(matrix) => transform(matrix, color),
+            None => color,
+        }
+    }
+}
+
+// how linear values are encoded in an image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Transfer {
+    // the piecewise sRGB curve, also used by Display P3
+    Srgb,
+    // a pure power law, e.g. 2.4 for BT.1886 on Rec.2020 displays
+    Gamma(f64),
+}
+
+impl Transfer {
+    pub fn encode(&self, linear: f64) -> f64 {
+        match *self {
+            Transfer::Srgb if linear <= 0.0031308 => 12.92 * linear,
+            Transfer::Srgb => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+            Transfer::Gamma(gamma) => linear.max(0.0).powf(1.0 / gamma),
+        }
+    }
+
+    pub fn decode(&self, encoded: f64) -> f64 {
+        match *self {
+            Transfer::Srgb if encoded <= 0.04045 => encoded / 12.92,
+            Transfer::Srgb => ((encoded + 0.055) / 1.055).powf(2.4),
+            Transfer::Gamma(gamma) => encoded.max(0.0).powf(gamma),
+        }
+    }
+}
+
+// what the values of an input image mean: colors in some space and encoding, or data such as
+// normals and heights that must be read as they are
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageTag {
+    Data,
+    Color(ColorSpace, Transfer),
+}
+
+// 8-bit color images are sRGB unless said otherwise
+pub const SRGB_IMAGE: ImageTag = ImageTag::Color(ColorSpace::Srgb, Transfer::Srgb);
+
+impl ImageTag {
+    // the image value as a linear color in `target`, or unchanged for data
+    pub fn decode(&self, value: Color, target: ColorSpace) -> Color {
+        match *self {
+            ImageTag::Data => value,
+            ImageTag::Color(space, transfer) => {
+                let linear = Color { x: transfer.decode(value.x), y: transfer.decode(value.y), z: transfer.decode(value.z) };
+                Conversion::new(space, target).apply(linear)
+            }
+        }
+    }
+}
+
+// the display an image is encoded for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Display {
+    Srgb,
+    DisplayP3,
+    // Rec.2020 primaries with the BT.1886 gamma of 2.4
+    Rec2020,
+}
+
+pub const DISPLAYS: [Display; 3] = [Display::Srgb, Display::DisplayP3, Display::Rec2020];
+
+impl Display {
+    pub fn space(&self) -> ColorSpace {
+        match self {
+            Display::Srgb => ColorSpace::Srgb,
+            Display::DisplayP3 => ColorSpace::DisplayP3,
+            Display::Rec2020 => ColorSpace::Rec2020,
+        }
+    }
+
+    pub fn transfer(&self) -> Transfer {
+        match self {
+            Display::Srgb | Display::DisplayP3 => Transfer::Srgb,
+            Display::Rec2020 => Transfer::Gamma(2.4),
+        }
+    }
+}
+
+impl fmt::Display for Display {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Display::Srgb => "srgb",
+            Display::DisplayP3 => "display-p3",
+            Display::Rec2020 => "rec2020",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Display {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        DISPLAYS
+            .iter()
+            .find(|display| display.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown display '{}'", name))
+    }
+}
+
+// from rendered radiance in the working space to encoded display values in 0..1: convert to the
+// display's primaries, expose and tone map there, then encode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OutputTransform {
+    pub working: ColorSpace,
+    pub display: Display,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        Self {
+            working: ColorSpace::Srgb,
+            display: Display::Srgb,
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+}
+
+impl OutputTransform {
+    pub fn apply(&self, radiance: Color) -> Color {
+        let display = Conversion::new(self.working, self.display.space()).apply(radiance);
+        let mapped = self.tone_mapping.apply(display);
+        let transfer = self.display.transfer();
+        Color { x: transfer.encode(mapped.x), y: transfer.encode(mapped.y), z: transfer.encode(mapped.z) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matrix(actual: &Matrix, expected: &Matrix, tolerance: f64) {
+        for (actual, expected) in actual.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((actual - expected).abs() < tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_srgb_to_xyz() {
+        let expected = [
+            [0.4124, 0.3576, 0.1805],
+            [0.2126, 0.7152, 0.0722],
+            [0.0193, 0.1192, 0.9505],
+        ];
+        assert_matrix(&ColorSpace::Srgb.to_xyz(), &expected, 1e-3);
+    }
+
+    #[test]
+    fn test_srgb_to_acescg_adapts_the_white_point() {
+        let expected = [
+            [0.6131, 0.3395, 0.0474],
+            [0.0702, 0.9164, 0.0134],
+            [0.0206, 0.1096, 0.8698],
+        ];
+        assert_matrix(&ColorSpace::Srgb.conversion(ColorSpace::AcesCg), &expected, 1e-3);
+    }
+
+    #[test]
+    fn test_conversions_keep_white_and_round_trip() {
+        let color = Color { x: 0.8, y: 0.3, z: 0.1 };
+        for source in COLOR_SPACES {
+            for target in COLOR_SPACES {
+                let white = transform(&source.conversion(target), Color { x: 1.0, y: 1.0, z: 1.0 });
+                assert!((white.x - 1.0).abs() < 1e-9 && (white.y - 1.0).abs() < 1e-9 && (white.z - 1.0).abs() < 1e-9);
+                let back = Conversion::new(target, source).apply(Conversion::new(source, target).apply(color));
+                assert!((back.x - color.x).abs() < 1e-9 && (back.y - color.y).abs() < 1e-9 && (back.z - color.z).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_gamuts_hold_srgb_red_inside() {
+        // saturated sRGB red needs less than full red in every wider space, and stays non-negative
+        for target in [ColorSpace::AcesCg, ColorSpace::DisplayP3, ColorSpace::Rec2020] {
+            let red = Conversion::new(ColorSpace::Srgb, target).apply(Color { x: 1.0, y: 0.0, z: 0.0 });
+            assert!(red.x < 1.0 && red.y >= 0.0 && red.z >= 0.0, "{}", target);
+        }
+    }
+
+    #[test]
+    fn test_transfer_round_trip() {
+        for transfer in [Transfer::Srgb, Transfer::Gamma(2.4)] {
+            for step in 0..=100 {
+                let value = step as f64 / 100.0;
+                assert!((transfer.decode(transfer.encode(value)) - value).abs() < 1e-12);
+            }
+        }
+        assert!((Transfer::Srgb.encode(0.18) - 0.4614).abs() < 1e-4);
+        // the two pieces of the sRGB curve meet
+        assert!((Transfer::Srgb.decode(0.04045 + 1e-9) - Transfer::Srgb.decode(0.04045)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse() {
+        for space in COLOR_SPACES {
+            assert_eq!(space.to_string().parse::<ColorSpace>(), Ok(space));
+        }
+        for display in DISPLAYS {
+            assert_eq!(display.to_string().parse::<Display>(), Ok(display));
+        }
+        assert!("xyz".parse::<ColorSpace>().is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use crate::renderer::aabb::Aabb;
+use crate::renderer::hit::{Hittable, HitRecord, ShadingFrame};
+use crate::renderer::material::Material;
+use crate::renderer::material::isotropic::Isotropic;
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::sphere::Sphere;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::vector3d::{Color, Point, Vector3D};
+
+// homogeneous participating medium filling a closed boundary shape
+pub(crate) struct ConstantMedium {
+    pub boundary: Box<dyn Hittable>,
+    pub density: f64,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function: Arc::new(Isotropic { albedo }),
+        }
+    }
+
+    // haze filling everything within `extent` of the origin, so distant geometry and the sky fade out
+    pub fn atmosphere(density: f64, albedo: Color, extent: f64) -> Self {
+        let boundary = Sphere {
+            center: Point::default(),
+            radius: extent,
+            material: Arc::new(Isotropic { albedo }),
+        };
+        Self::new(Box::new(boundary), density, albedo)
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        count(Counter::ConstantMediumTests);
+        // where the ray enters and leaves the boundary, which may lie behind its origin
+        let entry = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY, sampler)?;
+        let exit = self.boundary.hit(ray, entry.t + 0.0001, f64::INFINITY, sampler)?;
+
+        let t_enter = entry.t.max(t_min);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        // free-flight distance is exponentially distributed with the medium's density
+        let ray_length = ray.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = -(1.0 - sampler.next_1d()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        let normal = Vector3D { x: 1.0, y: 0.0, z: 0.0 };
+        Some(HitRecord {
+            point: ray.at(t),
+            // arbitrary: a medium has no surface
+            normal,
+            shading: ShadingFrame::new(normal, Vector3D { x: 0.0, y: 1.0, z: 0.0 }),
+            material: self.phase_function.clone(),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            time: ray.time,
+            object_id: 0,
+            material_id: 0,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.phase_function)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+
+    #[test]
+    fn test_transmittance_follows_beer_lambert() {
+        let boundary = Sphere {
+            center: Point::default(),
+            radius: 1.0,
+            material: Arc::new(Isotropic::default()),
+        };
+        let medium = ConstantMedium::new(Box::new(boundary), 0.8, Color::default());
+        // an unnormalized direction must not change the distances
+        let ray = Ray {
+            origin: Point { x: 0.0, y: 0.0, z: 5.0 },
+            direction: Vector3D { x: 0.0, y: 0.0, z: -3.0 },
+            time: 0.0,
+        };
+
+        let samples = 20000;
+        let mut sampler = Independent::new(0, 0);
+        let passed = (0..samples).filter(|_| medium.hit(&ray, 0.001, f64::INFINITY, &mut sampler).is_none()).count();
+        let expected = (-0.8_f64 * 2.0).exp();
+        assert!((passed as f64 / samples as f64 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_atmosphere_surrounds_the_origin() {
+        let atmosphere = ConstantMedium::atmosphere(10.0, Color::default(), 50.0);
+        let ray = Ray {
+            origin: Point::default(),
+            direction: Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+            time: 0.0,
+        };
+        let hit_record = atmosphere.hit(&ray, 0.001, f64::INFINITY, &mut Independent::new(0, 0)).unwrap();
+        assert!(hit_record.t > 0.001 && hit_record.t < 50.0);
+    }
+}
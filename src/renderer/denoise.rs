@@ -0,0 +1,170 @@
+use crate::renderer::aov::Surface;
+use crate::renderer::vector3d::Color;
+use crate::renderer::Image;
+
+// taps of the B3 spline the wavelet transform smooths with
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// edge-avoiding à-trous wavelet filter (Dammertz et al. 2010): every iteration blends pixels twice
+// as far apart as the last, weighted down across edges in the normal and albedo buffers and where
+// colors differ by more than their noise explains, as in spatiotemporal variance-guided filtering
+#[derive(Copy, Clone, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // how many standard deviations of noise two pixels' illumination may differ by and still blend
+    pub color_sigma: f64,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            color_sigma: 2.0,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.02,
+        }
+    }
+}
+
+// what the color is divided by before filtering, so that texture detail survives. The floor keeps
+// dark surfaces, the sky and pixels only partly covered by a surface from blowing up
+fn demodulation(albedo: Color) -> Color {
+    Color { x: albedo.x.max(0.1), y: albedo.y.max(0.1), z: albedo.z.max(0.1) }
+}
+
+// per-pixel variance estimates from few samples are noisy themselves, so they are blurred with
+// a 3x3 Gaussian before guiding the filter
+fn prefiltered(width: usize, height: usize, variances: &[f64]) -> Vec<f64> {
+    const TAPS: [f64; 3] = [0.25, 0.5, 0.25];
+    let mut blurred = Vec::with_capacity(variances.len());
+    for y in 0..height {
+        for x in 0..width {
+            let (mut sum, mut total) = (0.0, 0.0);
+            for (dy, ky) in TAPS.iter().enumerate() {
+                for (dx, kx) in TAPS.iter().enumerate() {
+                    let (sample_x, sample_y) = ((x + dx).wrapping_sub(1), (y + dy).wrapping_sub(1));
+                    if sample_x < width && sample_y < height {
+                        sum += kx * ky * variances[sample_y * width + sample_x];
+                        total += kx * ky;
+                    }
+                }
+            }
+            blurred.push(sum / total);
+        }
+    }
+    blurred
+}
+
+impl Denoiser {
+    // the features cover the image's pixels
+    pub fn apply(&self, image: &Image, features: &[Surface]) -> Vec<Color> {
+        let (width, height) = (image.width as i64, image.height as i64);
+        let demodulations: Vec<Color> = features.iter().map(|feature| demodulation(feature.albedo)).collect();
+        let mut illumination: Vec<Color> = image.pixels.iter().zip(&demodulations).map(|(pixel, by)| *pixel / *by).collect();
+        let variances: Vec<f64> = image
+            .variances
+            .iter()
+            .zip(&demodulations)
+            .map(|(variance, by)| variance / by.luminance().powi(2))
+            .collect();
+        let mut variances = prefiltered(image.width as usize, image.height as usize, &variances);
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let mut filtered = Vec::with_capacity(illumination.len());
+            let mut filtered_variances = Vec::with_capacity(variances.len());
+            for y in 0..height {
+                for x in 0..width {
+                    let center = (y * width + x) as usize;
+                    let (luminance, feature) = (illumination[center].luminance(), &features[center]);
+                    let deviation = self.color_sigma * variances[center].sqrt() + 1e-6;
+                    let (mut sum, mut variance, mut total) = (Color::default(), 0.0, 0.0);
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        let sample_y = y + (dy as i64 - 2) * step;
+                        if !(0..height).contains(&sample_y) {
+                            continue;
+                        }
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let sample_x = x + (dx as i64 - 2) * step;
+                            if !(0..width).contains(&sample_x) {
+                                continue;
+                            }
+                            let index = (sample_y * width + sample_x) as usize;
+                            let other = &features[index];
+                            let exponent = (illumination[index].luminance() - luminance).abs() / deviation
+                                + (other.normal - feature.normal).length_squared() / (self.normal_sigma * self.normal_sigma)
+                                + (other.albedo - feature.albedo).length_squared() / (self.albedo_sigma * self.albedo_sigma);
+                            let weight = kx * ky * (-exponent).exp();
+                            sum = sum + weight * illumination[index];
+                            variance += weight * weight * variances[index];
+                            total += weight;
+                        }
+                    }
+                    // the center tap always weighs in, so the total is never zero
+                    filtered.push(sum / total);
+                    filtered_variances.push(variance / (total * total));
+                }
+            }
+            illumination = filtered;
+            variances = filtered_variances;
+        }
+        illumination.iter().zip(&demodulations).map(|(color, by)| *color * *by).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::renderer::aov::render_surfaces;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::progress::Silent;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::vector3d::Point;
+    use crate::renderer::world::HittableList;
+    use crate::renderer::{render_image, RenderSettings};
+
+    // a grey ball on a reddish ground, lit by the sky only
+    fn lambert_scene() -> HittableList {
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere {
+            center: Point { x: 0.0, y: 0.0, z: -3.0 },
+            radius: 1.0,
+            material: Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } }),
+        }));
+        world.add(Box::new(Sphere {
+            center: Point { x: 0.0, y: -101.0, z: -3.0 },
+            radius: 100.0,
+            material: Arc::new(Lambert { albedo: Color { x: 0.7, y: 0.3, z: 0.2 } }),
+        }));
+        world
+    }
+
+    fn mse(image: &[Color], reference: &[Color]) -> f64 {
+        image.iter().zip(reference).map(|(pixel, expected)| (*pixel - *expected).length_squared()).sum::<f64>() / image.len() as f64
+    }
+
+    #[test]
+    fn test_denoising_lowers_the_error_against_a_reference() {
+        // large enough that silhouette pixels, which no filter can clean up, don't dominate the error
+        let settings = RenderSettings { width: 96, height: Some(54), samples_per_pixel: 8, depth: 6, threads: 4, ..RenderSettings::default() };
+        let world = lambert_scene();
+        let noisy = render_image(&settings, &world, &Silent);
+        let reference = render_image(&RenderSettings { samples_per_pixel: 256, seed: 1, ..settings }, &world, &Silent);
+        let features = render_surfaces(&settings, &world);
+        let denoised = Denoiser::default().apply(&noisy, &features);
+        let (before, after) = (mse(&noisy.pixels, &reference.pixels), mse(&denoised, &reference.pixels));
+        assert!(after < 0.5 * before, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn test_flat_image_is_unchanged() {
+        let feature = Surface { albedo: Color { x: 0.5, y: 0.25, z: 0.1 }, ..Surface::default() };
+        let color = Color { x: 0.2, y: 0.1, z: 0.04 };
+        let image = Image { width: 4, height: 3, pixels: vec![color; 12], samples: vec![4; 12], variances: vec![0.01; 12], layers: Vec::new() };
+        let denoised = Denoiser::default().apply(&image, &[feature; 12]);
+        assert!(denoised.iter().all(|pixel| (*pixel - color).length() < 1e-12));
+    }
+}
@@ -0,0 +1,141 @@
+use std::io;
+
+// pixel types of the OpenEXR channel list
+const UINT: i32 = 0;
+const FLOAT: i32 = 2;
+
+pub enum Samples {
+    Float(Vec<f32>),
+    Uint(Vec<u32>),
+}
+
+// one channel of an image, rows from top to bottom; layered files name channels layer.channel
+pub struct Channel {
+    pub name: String,
+    pub samples: Samples,
+}
+
+fn attribute(bytes: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    for text in [name, kind] {
+        bytes.extend(text.as_bytes());
+        bytes.push(0);
+    }
+    bytes.extend((value.len() as i32).to_le_bytes());
+    bytes.extend(value);
+}
+
+// a single-part scanline OpenEXR file without compression
+pub fn encode(width: u32, height: u32, mut channels: Vec<Channel>) -> io::Result<Vec<u8>> {
+    let count = (width * height) as usize;
+    if channels.iter().any(|channel| match &channel.samples {
+        Samples::Float(samples) => samples.len() != count,
+        Samples::Uint(samples) => samples.len() != count,
+    }) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "every channel needs one sample per pixel"));
+    }
+    // readers expect the channel list, and the channels within each line, in alphabetical order
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut bytes = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    let mut list = Vec::new();
+    for channel in &channels {
+        list.extend(channel.name.as_bytes());
+        list.push(0);
+        let kind = match channel.samples {
+            Samples::Float(_) => FLOAT,
+            Samples::Uint(_) => UINT,
+        };
+        list.extend(kind.to_le_bytes());
+        // linear flag and reserved bytes, then the x and y subsampling
+        list.extend([0, 0, 0, 0]);
+        list.extend(1i32.to_le_bytes());
+        list.extend(1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut bytes, "channels", "chlist", &list);
+    attribute(&mut bytes, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1].iter().flat_map(|value| value.to_le_bytes()).collect();
+    attribute(&mut bytes, "dataWindow", "box2i", &window);
+    attribute(&mut bytes, "displayWindow", "box2i", &window);
+    attribute(&mut bytes, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut bytes, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut bytes, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut bytes, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    bytes.push(0);
+
+    // uncompressed files store one line per chunk, found through a table of offsets from the file's start
+    let line_size = 4 * width as usize * channels.len();
+    let first_chunk = bytes.len() + 8 * height as usize;
+    for y in 0..height as usize {
+        bytes.extend(((first_chunk + y * (8 + line_size)) as u64).to_le_bytes());
+    }
+    for y in 0..height as usize {
+        bytes.extend((y as i32).to_le_bytes());
+        bytes.extend((line_size as i32).to_le_bytes());
+        let line = y * width as usize..(y + 1) * width as usize;
+        for channel in &channels {
+            match &channel.samples {
+                Samples::Float(samples) => samples[line.clone()].iter().for_each(|sample| bytes.extend(sample.to_le_bytes())),
+                Samples::Uint(samples) => samples[line.clone()].iter().for_each(|sample| bytes.extend(sample.to_le_bytes())),
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_string(bytes: &[u8], at: usize) -> String {
+        let end = at + bytes[at..].iter().position(|&byte| byte == 0).unwrap();
+        String::from_utf8(bytes[at..end].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_encoded_file_has_sorted_channels_and_a_valid_offset_table() {
+        let channels = vec![
+            Channel { name: String::from("depth.Z"), samples: Samples::Float(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]) },
+            Channel { name: String::from("B"), samples: Samples::Uint(vec![7, 8, 9, 10, 11, 12]) },
+        ];
+        let bytes = encode(3, 2, channels).unwrap();
+        assert_eq!(bytes[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        // walk the attributes to the end of the header
+        let mut at = 8;
+        let mut names = Vec::new();
+        while bytes[at] != 0 {
+            let name = read_string(&bytes, at);
+            at += name.len() + 1;
+            let kind = read_string(&bytes, at);
+            at += kind.len() + 1;
+            let size = read_i32(&bytes, at) as usize;
+            if name == "channels" {
+                assert_eq!(read_string(&bytes, at + 4), "B");
+                assert_eq!(read_string(&bytes, at + 4 + 18), "depth.Z");
+            }
+            names.push(name);
+            at += 4 + size;
+        }
+        assert!(names.contains(&String::from("dataWindow")) && names.contains(&String::from("compression")));
+
+        let table = at + 1;
+        let second = u64::from_le_bytes(bytes[table + 8..table + 16].try_into().unwrap()) as usize;
+        assert_eq!(read_i32(&bytes, second), 1);
+        assert_eq!(read_i32(&bytes, second + 4), 24);
+        // the B channel of the second line comes first, then its depths
+        assert_eq!(read_i32(&bytes, second + 8), 10);
+        assert_eq!(f32::from_le_bytes(bytes[second + 20..second + 24].try_into().unwrap()), 4.0);
+        assert_eq!(bytes.len(), second + 8 + 24);
+    }
+
+    #[test]
+    fn test_mismatched_channels_are_rejected() {
+        let channels = vec![Channel { name: String::from("R"), samples: Samples::Float(vec![0.0; 5]) }];
+        assert!(encode(3, 2, channels).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+// pixel reconstruction filter; offsets are in pixels from the pixel centre and the weights need not
+// be normalized, since every pixel divides by the weights it received
+pub trait Filter: Sync {
+    fn radius(&self) -> f64;
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // negative lobes: sharper, but may ring around bright edges
+    Mitchell,
+    BlackmanHarris,
+}
+
+pub const FILTER_KINDS: [FilterKind; 5] = [
+    FilterKind::Box,
+    FilterKind::Tent,
+    FilterKind::Gaussian,
+    FilterKind::Mitchell,
+    FilterKind::BlackmanHarris,
+];
+
+impl FilterKind {
+    pub fn default_radius(&self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell | FilterKind::BlackmanHarris => 2.0,
+        }
+    }
+
+    pub fn create(&self, radius: Option<f64>) -> Box<dyn Filter> {
+        let radius = radius.unwrap_or(self.default_radius());
+        match self {
+            FilterKind::Box => Box::new(BoxFilter { radius }),
+            FilterKind::Tent => Box::new(Tent { radius }),
+            FilterKind::Gaussian => Box::new(Gaussian { radius, sigma: 0.5 }),
+            FilterKind::Mitchell => Box::new(Mitchell { radius, b: 1.0 / 3.0, c: 1.0 / 3.0 }),
+            FilterKind::BlackmanHarris => Box::new(BlackmanHarris { radius }),
+        }
+    }
+}
+
+impl fmt::Display for FilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::BlackmanHarris => "blackman-harris",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        FILTER_KINDS
+            .iter()
+            .find(|kind| kind.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown filter '{}'", name))
+    }
+}
+
+// the pixel's own box, i.e. plain averaging at radius 0.5
+struct BoxFilter {
+    radius: f64,
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (x.abs() <= self.radius && y.abs() <= self.radius) as u32 as f64
+    }
+}
+
+struct Tent {
+    radius: f64,
+}
+
+impl Filter for Tent {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let profile = |x: f64| (self.radius - x.abs()).max(0.0);
+        profile(x) * profile(y)
+    }
+}
+
+// shifted down so that it reaches zero at the radius instead of being cut off
+struct Gaussian {
+    radius: f64,
+    sigma: f64,
+}
+
+impl Filter for Gaussian {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let gaussian = |x: f64| (-x * x / (2.0 * self.sigma * self.sigma)).exp();
+        let profile = |x: f64| (gaussian(x) - gaussian(self.radius)).max(0.0);
+        profile(x) * profile(y)
+    }
+}
+
+// Mitchell and Netravali, "Reconstruction Filters in Computer Graphics" (1988), stretched from its
+// natural support of 2 to the radius
+struct Mitchell {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl Mitchell {
+    fn profile(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x / self.radius).abs();
+        if x >= 2.0 {
+            0.0
+        } else if x >= 1.0 {
+            ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+        }
+    }
+}
+
+impl Filter for Mitchell {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.profile(x) * self.profile(y)
+    }
+}
+
+// four-term Blackman-Harris window spanning the filter's diameter
+struct BlackmanHarris {
+    radius: f64,
+}
+
+impl BlackmanHarris {
+    fn profile(&self, x: f64) -> f64 {
+        if x.abs() >= self.radius {
+            return 0.0;
+        }
+        let phase = PI * (x.abs() / self.radius + 1.0);
+        0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos() - 0.01168 * (3.0 * phase).cos()
+    }
+}
+
+impl Filter for BlackmanHarris {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.profile(x) * self.profile(y)
+    }
+}
+
+// how many pixels beyond its own a sample taken inside a pixel can reach
+pub fn margin(filter: &dyn Filter) -> u32 {
+    (filter.radius() - 0.5).ceil().max(0.0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_peak_in_the_middle_and_vanish_at_the_radius() {
+        for kind in FILTER_KINDS {
+            let filter = kind.create(None);
+            let radius = filter.radius();
+            let peak = filter.evaluate(0.0, 0.0);
+            assert!(peak > 0.0, "{}", kind);
+            for step in 1..20 {
+                let x = radius * step as f64 / 20.0;
+                assert!(filter.evaluate(x, 0.0) <= peak, "{}", kind);
+                assert_eq!(filter.evaluate(x, 0.3), filter.evaluate(-x, -0.3), "{}", kind);
+            }
+            assert!(filter.evaluate(radius + 1e-9, 0.0).abs() < 1e-4, "{}", kind);
+            assert!(filter.evaluate(0.0, -radius - 1e-9).abs() < 1e-4, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_mitchell_has_negative_lobes() {
+        let filter = FilterKind::Mitchell.create(None);
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+        assert!((filter.evaluate(0.0, 0.0) - (8.0 / 9.0) * (8.0 / 9.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_margin() {
+        assert_eq!(margin(FilterKind::Box.create(None).as_ref()), 0);
+        assert_eq!(margin(FilterKind::Tent.create(None).as_ref()), 1);
+        assert_eq!(margin(FilterKind::Mitchell.create(None).as_ref()), 2);
+        assert_eq!(margin(FilterKind::Mitchell.create(Some(1.2)).as_ref()), 1);
+    }
+
+    #[test]
+    fn test_parse() {
+        for kind in FILTER_KINDS {
+            assert_eq!(kind.to_string().parse::<FilterKind>(), Ok(kind));
+        }
+        assert!("lanczos".parse::<FilterKind>().is_err());
+    }
+}
@@ -0,0 +1,31 @@
+// trades a little energy for fewer fireflies, the rare and very bright samples of small bright
+// features seen through specular bounces that take thousands of samples to average out
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FireflySuppression {
+    // the largest luminance one camera sample may have
+    pub max_sample: Option<f64>,
+    // the largest luminance light found after the first bounce may add to a sample
+    pub max_indirect: Option<f64>,
+    // once a path has bounced diffusely, near-specular lobes are at least this rough
+    pub regularize: Option<f64>,
+}
+
+// what a color of this luminance is scaled by to stay within `max`, if it has to be
+pub fn clamp_factor(luminance: f64, max: Option<f64>) -> Option<f64> {
+    match max {
+        Some(max) if luminance > max => Some(max / luminance),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_colors_over_the_limit_are_scaled() {
+        assert_eq!(clamp_factor(8.0, Some(2.0)), Some(0.25));
+        assert_eq!(clamp_factor(1.0, Some(2.0)), None);
+        assert_eq!(clamp_factor(1e9, None), None);
+    }
+}
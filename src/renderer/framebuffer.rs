@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use crate::renderer::adaptive::RunningVariance;
+use crate::renderer::aov::{LightPaths, PathLayer};
+use crate::renderer::filter::Filter;
+use crate::renderer::vector3d::Color;
+use crate::renderer::Image;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Pixel {
+    pub sum: Color,
+    pub estimate: RunningVariance,
+}
+
+impl Pixel {
+    pub fn add(&mut self, color: Color) {
+        self.sum = self.sum + color;
+        self.estimate.push(color.luminance());
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.estimate.count()
+    }
+
+    pub fn mean_variance(&self) -> f64 {
+        self.estimate.variance() / self.samples().max(1) as f64
+    }
+
+    pub fn mean(&self) -> Color {
+        if self.samples() == 0 {
+            return Color::default();
+        }
+        self.sum / self.samples() as f64
+    }
+}
+
+// 2^32; the filtered sums are kept in fixed point so that they come out the same whatever order
+// neighbouring tiles splat into a pixel in
+const SPLAT_SCALE: f64 = 4294967296.0;
+
+// filter-weighted radiance and the sum of the weights that reached a pixel
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Splat {
+    pub sums: [i128; 4],
+}
+
+impl Splat {
+    pub fn add(&mut self, color: Color, weight: f64) {
+        for (sum, value) in self.sums.iter_mut().zip([color.x * weight, color.y * weight, color.z * weight, weight]) {
+            // saturates on overflow and drops NaNs
+            *sum = sum.saturating_add((value * SPLAT_SCALE) as i128);
+        }
+    }
+
+    pub fn merge(&mut self, other: &Splat) {
+        for (sum, other) in self.sums.iter_mut().zip(other.sums) {
+            *sum = sum.saturating_add(other);
+        }
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.sums[3] as f64 / SPLAT_SCALE
+    }
+
+    // the filtered color, or the pixel's plain mean when negative lobes cancelled most of the weight;
+    // ringing below black is clamped away
+    pub fn resolve(&self, pixel: &Pixel) -> Color {
+        self.filtered(self.weight(), pixel).unwrap_or_else(|| pixel.mean())
+    }
+
+    // a light-path layer's part of the pixel: normalized by the weight of every sample the pixel
+    // got, not just of those that reached the layer, and black where weights cancel
+    pub fn resolve_layer(&self, beauty: &Splat, pixel: &Pixel) -> Color {
+        self.filtered(beauty.weight(), pixel).unwrap_or_default()
+    }
+
+    fn filtered(&self, weight: f64, pixel: &Pixel) -> Option<Color> {
+        if weight <= 1e-3 * pixel.samples() as f64 {
+            return None;
+        }
+        let channel = |sum: i128| (sum as f64 / SPLAT_SCALE / weight).max(0.0);
+        Some(Color { x: channel(self.sums[0]), y: channel(self.sums[1]), z: channel(self.sums[2]) })
+    }
+}
+
+// rectangle of the film in pixels, from the top left corner
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Window {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Window {
+    pub fn contains(&self, other: &Window) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    // grown by margin pixels on every side, without leaving `bounds`
+    pub fn expand(&self, margin: u32, bounds: &Window) -> Window {
+        let x = self.x.saturating_sub(margin).max(bounds.x);
+        let y = self.y.saturating_sub(margin).max(bounds.y);
+        let right = (self.x + self.width + margin).min(bounds.x + bounds.width);
+        let bottom = (self.y + self.height + margin).min(bounds.y + bounds.height);
+        Window { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+// (x, y) is a sample's position on the film in pixels; it reaches the pixels of the window whose
+// centres lie within the filter radius, counting one edge of the interval only so that a box of
+// radius 0.5 covers exactly one pixel
+fn footprint(window: &Window, x: f64, y: f64, filter: &dyn Filter, mut visit: impl FnMut(usize, f64)) {
+    let radius = filter.radius();
+    let range = |position: f64, start: u32, length: u32| {
+        let first = ((position - 0.5 - radius).floor() + 1.0).max(start as f64) as u32;
+        let last = ((position - 0.5 + radius).floor() + 1.0).min((start + length) as f64).max(first as f64) as u32;
+        first..last
+    };
+    for pixel_y in range(y, window.y, window.height) {
+        for pixel_x in range(x, window.x, window.width) {
+            let weight = filter.evaluate(pixel_x as f64 + 0.5 - x, pixel_y as f64 + 0.5 - y);
+            if weight != 0.0 {
+                visit(((pixel_y - window.y) * window.width + pixel_x - window.x) as usize, weight);
+            }
+        }
+    }
+}
+
+// what the samples of one tile contribute to the pixels their filter reaches
+pub struct SplatTile {
+    pub window: Window,
+    pub splats: Vec<Splat>,
+    // the light-path split of the same samples, by layer as they turn up
+    pub layers: BTreeMap<PathLayer, Vec<Splat>>,
+}
+
+impl SplatTile {
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            splats: vec![Splat::default(); (window.width * window.height) as usize],
+            layers: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, x: f64, y: f64, color: Color, filter: &dyn Filter) {
+        footprint(&self.window, x, y, filter, |index, weight| self.splats[index].add(color, weight));
+    }
+
+    // splats every layer with the weights the beauty got, so that the layers add up to it
+    pub fn add_light_paths(&mut self, x: f64, y: f64, light_paths: &LightPaths, filter: &dyn Filter) {
+        let count = self.splats.len();
+        for &(layer, color) in &light_paths.layers {
+            let splats = self.layers.entry(layer).or_insert_with(|| vec![Splat::default(); count]);
+            footprint(&self.window, x, y, filter, |index, weight| splats[index].add(color, weight));
+        }
+    }
+}
+
+// adds splats over `window` into ones over the larger `sampled` window
+fn merge(target: &mut [Splat], sampled: &Window, window: &Window, splats: &[Splat]) {
+    for (row, y) in splats.chunks(window.width as usize).zip(window.y..) {
+        let start = ((y - sampled.y) * sampled.width + window.x - sampled.x) as usize;
+        for (splat, other) in target[start..start + row.len()].iter_mut().zip(row) {
+            splat.merge(other);
+        }
+    }
+}
+
+// accumulated samples of every pixel in a window of the film, rows from top to bottom. Pixels are
+// sampled over `sampled`, which adds the filter's reach around a crop window so that the crop's
+// edges receive every sample the full frame would give them
+pub struct Framebuffer {
+    pub window: Window,
+    pub sampled: Window,
+    pub pixels: Vec<Pixel>,
+    pub splats: Vec<Splat>,
+    // filled when rendering light-path AOVs; checkpoints leave them out
+    pub layers: BTreeMap<PathLayer, Vec<Splat>>,
+}
+
+impl Framebuffer {
+    pub fn new(window: Window, sampled: Window) -> Self {
+        let count = (sampled.width * sampled.height) as usize;
+        Self {
+            window,
+            sampled,
+            pixels: vec![Pixel::default(); count],
+            splats: vec![Splat::default(); count],
+            layers: BTreeMap::new(),
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y - self.sampled.y) * self.sampled.width + x - self.sampled.x) as usize
+    }
+
+    fn window_indices(&self, window: Window) -> impl Iterator<Item = usize> + '_ {
+        (window.y..window.y + window.height).flat_map(move |y| (window.x..window.x + window.width).map(move |x| self.index(x, y)))
+    }
+
+    pub fn read(&self, tile: &Window) -> Vec<Pixel> {
+        (tile.y..tile.y + tile.height)
+            .flat_map(|y| {
+                let start = self.index(tile.x, y);
+                self.pixels[start..start + tile.width as usize].iter().copied()
+            })
+            .collect()
+    }
+
+    pub fn write(&mut self, tile: &Window, pixels: &[Pixel]) {
+        for (row, y) in pixels.chunks(tile.width as usize).zip(tile.y..) {
+            let start = self.index(tile.x, y);
+            self.pixels[start..start + tile.width as usize].copy_from_slice(row);
+        }
+    }
+
+    // the tile's window must lie within the sampled window
+    pub fn splat(&mut self, tile: &SplatTile) {
+        merge(&mut self.splats, &self.sampled, &tile.window, &tile.splats);
+        let count = self.pixels.len();
+        for (layer, splats) in &tile.layers {
+            let target = self.layers.entry(*layer).or_insert_with(|| vec![Splat::default(); count]);
+            merge(target, &self.sampled, &tile.window, splats);
+        }
+    }
+
+    // replaces the pixels under a smaller framebuffer's window, e.g. a crop re-rendered at more samples
+    pub fn patch(&mut self, region: &Framebuffer) -> Result<(), String> {
+        if !self.window.contains(&region.window) {
+            return Err(format!("{:?} does not lie within {:?}", region.window, self.window));
+        }
+        for (from, to) in region.window_indices(region.window).zip(self.window_indices(region.window)).collect::<Vec<_>>() {
+            self.pixels[to] = region.pixels[from];
+            self.splats[to] = region.splats[from];
+        }
+        Ok(())
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image {
+            width: self.window.width,
+            height: self.window.height,
+            pixels: self.window_indices(self.window).map(|index| self.splats[index].resolve(&self.pixels[index])).collect(),
+            samples: self.window_indices(self.window).map(|index| self.pixels[index].samples()).collect(),
+            variances: self.window_indices(self.window).map(|index| self.pixels[index].mean_variance()).collect(),
+            layers: self
+                .layers
+                .iter()
+                .map(|(layer, splats)| {
+                    let pixels = self.window_indices(self.window).map(|index| splats[index].resolve_layer(&self.splats[index], &self.pixels[index]));
+                    (*layer, pixels.collect())
+                })
+                .collect(),
+        }
+    }
+
+    // mean display-space confidence interval over the window's pixels
+    pub fn noise(&self) -> f64 {
+        let total: f64 = self.window_indices(self.window).map(|index| self.pixels[index].estimate.display_error()).sum();
+        total / (self.window.width * self.window.height) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::filter::FilterKind;
+
+    #[test]
+    fn test_patch_replaces_only_the_region() {
+        let film = Window { x: 0, y: 0, width: 4, height: 3 };
+        let mut full = Framebuffer::new(film, film);
+        let crop = Window { x: 1, y: 1, width: 2, height: 2 };
+        // the margin pixels of the region are sampled but not copied
+        let mut region = Framebuffer::new(crop, crop.expand(1, &film));
+        region.pixels.iter_mut().for_each(|pixel| pixel.add(Color { x: 1.0, y: 1.0, z: 1.0 }));
+        full.patch(&region).unwrap();
+        let samples: Vec<u32> = full.pixels.iter().map(Pixel::samples).collect();
+        assert_eq!(samples, vec![0, 0, 0, 0, 0, 1, 1, 0, 0, 1, 1, 0]);
+
+        let outside = Window { x: 3, y: 0, width: 2, height: 1 };
+        assert!(full.patch(&Framebuffer::new(outside, outside)).is_err());
+    }
+
+    #[test]
+    fn test_expand_stays_within_bounds() {
+        let film = Window { x: 0, y: 0, width: 10, height: 8 };
+        assert_eq!(Window { x: 1, y: 5, width: 3, height: 2 }.expand(2, &film), Window { x: 0, y: 3, width: 6, height: 5 });
+        assert_eq!(film.expand(3, &film), film);
+    }
+
+    #[test]
+    fn test_box_splat_covers_one_pixel() {
+        let filter = FilterKind::Box.create(None);
+        let mut tile = SplatTile::new(Window { x: 0, y: 0, width: 3, height: 3 });
+        for (x, y) in [(1.0, 1.0), (1.5, 1.5), (1.999, 1.0)] {
+            tile.add(x, y, Color { x: 1.0, y: 1.0, z: 1.0 }, filter.as_ref());
+        }
+        let weights: Vec<f64> = tile.splats.iter().map(Splat::weight).collect();
+        assert_eq!(weights, vec![0.0, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_cancelled_weight_falls_back_to_the_mean() {
+        let mut pixel = Pixel::default();
+        pixel.add(Color { x: 0.5, y: 0.5, z: 0.5 });
+        let mut splat = Splat::default();
+        splat.add(Color { x: 0.5, y: 0.5, z: 0.5 }, -0.2);
+        assert_eq!(splat.resolve(&pixel).x, 0.5);
+        // ringing below black is clamped
+        splat.add(Color { x: -1.0, y: 1.0, z: 1.0 }, 1.0);
+        assert_eq!(splat.resolve(&pixel).x, 0.0);
+    }
+}
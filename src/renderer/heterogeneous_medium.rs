@@ -0,0 +1,182 @@
+use std::sync::Arc;
+use crate::renderer::aabb::Aabb;
+use crate::renderer::hit::{Hittable, HitRecord, ShadingFrame};
+use crate::renderer::material::Material;
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::vector3d::{Point, Vector3D};
+use crate::renderer::voxel_grid::VoxelGrid;
+
+// participating medium whose density comes from a voxel grid stretched over an axis-aligned box
+pub(crate) struct HeterogeneousMedium {
+    pub grid: Arc<VoxelGrid>,
+    pub min: Point,
+    pub max: Point,
+    pub density_scale: f64,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl HeterogeneousMedium {
+    // parametric range of the ray inside the box, if any
+    fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let inverse = 1.0 / direction[axis];
+            let mut near = (min[axis] - origin[axis]) * inverse;
+            let mut far = (max[axis] - origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from a zero direction inside the slab leaves the range untouched
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    fn density(&self, point: Point) -> f64 {
+        let position = (point - self.min) / (self.max - self.min);
+        self.density_scale * self.grid.sample(position, 0)
+    }
+
+    fn majorant(&self) -> f64 {
+        self.density_scale * self.grid.max_density()
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        count(Counter::HeterogeneousMediumTests);
+        let (t0, t1) = self.clip(ray, t_min, t_max)?;
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        // delta tracking: step through a homogenized medium of the majorant density and accept
+        // tentative collisions with probability density / majorant
+        let ray_length = ray.direction.length();
+        let mut t = t0;
+        loop {
+            t += -(1.0 - sampler.next_1d()).ln() / (majorant * ray_length);
+            if t >= t1 {
+                return None;
+            }
+            let point = ray.at(t);
+            if sampler.next_1d() * majorant < self.density(point) {
+                let normal = Vector3D { x: 1.0, y: 0.0, z: 0.0 };
+                return Some(HitRecord {
+                    point,
+                    // arbitrary: a medium has no surface
+                    normal,
+                    shading: ShadingFrame::new(normal, Vector3D { x: 0.0, y: 1.0, z: 0.0 }),
+                    material: self.phase_function.clone(),
+                    t,
+                    u: 0.0,
+                    v: 0.0,
+                    front_face: true,
+                    time: ray.time,
+                    object_id: 0,
+                    material_id: 0,
+                });
+            }
+        }
+    }
+
+    // unbiased transmittance estimate between t_min and t_max by ratio tracking
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        count(Counter::HeterogeneousMediumTests);
+        let (t0, t1) = match self.clip(ray, t_min, t_max) {
+            Some(range) => range,
+            None => return 1.0,
+        };
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return 1.0;
+        }
+        let ray_length = ray.direction.length();
+        let mut t = t0;
+        let mut transmittance = 1.0;
+        loop {
+            t += -(1.0 - sampler.next_1d()).ln() / (majorant * ray_length);
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density(ray.at(t)) / majorant;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb { min: self.min, max: self.max })
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.phase_function)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+    use crate::renderer::material::isotropic::Isotropic;
+
+    fn ramp() -> HeterogeneousMedium {
+        // density rises linearly from 0 to 2 along x across a unit box
+        let size = 64;
+        let data = (0..size).map(|x| 2.0 * (x as f64 + 0.5) / size as f64).collect();
+        HeterogeneousMedium {
+            grid: Arc::new(VoxelGrid::new([size, 1, 1], 1, data).unwrap()),
+            min: Point { x: 0.0, y: 0.0, z: 0.0 },
+            max: Point { x: 1.0, y: 1.0, z: 1.0 },
+            density_scale: 1.5,
+            phase_function: Arc::new(Isotropic::default()),
+        }
+    }
+
+    #[test]
+    fn test_delta_and_ratio_tracking_agree_with_beer_lambert() {
+        let medium = ramp();
+        let ray = Ray {
+            origin: Point { x: -1.0, y: 0.5, z: 0.5 },
+            direction: Vector3D { x: 2.0, y: 0.0, z: 0.0 },
+            time: 0.0,
+        };
+        // optical depth is the integral of 1.5 * 2x over [0, 1]
+        let expected = (-1.5_f64).exp();
+
+        let samples = 20000;
+        let mut sampler = Independent::new(0, 0);
+        let escaped = (0..samples).filter(|_| medium.hit(&ray, 0.001, f64::INFINITY, &mut sampler).is_none()).count();
+        assert!((escaped as f64 / samples as f64 - expected).abs() < 0.015);
+
+        let ratio: f64 = (0..samples).map(|_| medium.transmittance(&ray, 0.001, f64::INFINITY, &mut sampler)).sum::<f64>() / samples as f64;
+        assert!((ratio - expected).abs() < 0.01, "{}", ratio);
+    }
+
+    #[test]
+    fn test_rays_missing_the_box_pass() {
+        let medium = ramp();
+        let mut sampler = Independent::new(0, 0);
+        let ray = Ray {
+            origin: Point { x: -1.0, y: 2.0, z: 0.5 },
+            direction: Vector3D { x: 1.0, y: 0.0, z: 0.0 },
+            time: 0.0,
+        };
+        assert!(medium.hit(&ray, 0.001, f64::INFINITY, &mut sampler).is_none());
+        assert_eq!(medium.transmittance(&ray, 0.001, f64::INFINITY, &mut sampler), 1.0);
+    }
+}
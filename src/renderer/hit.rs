@@ -1,14 +1,71 @@
-use std::rc::Rc;
+use std::sync::Arc;
+use crate::renderer::aabb::Aabb;
 use crate::renderer::material::Material;
-use crate::renderer::ray::{dot, Ray};
-use crate::renderer::vector3d::{Point, Vector3D};
+use crate::renderer::ray::{cross, dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Point, unit_vector, Vector3D};
 
+// distance along the geometric normal used to lift spawned rays off the surface
+const RAY_OFFSET: f64 = 1e-4;
+
+// smallest allowed cosine between a shading-normal reflection and the geometric surface
+const MIN_REFLECTION_COS: f64 = 1e-3;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ShadingFrame {
+    pub normal: Vector3D,
+    pub tangent: Vector3D,
+    pub bitangent: Vector3D,
+}
+
+impl ShadingFrame {
+    pub fn new(normal: Vector3D, tangent: Vector3D) -> Self {
+        // Gram-Schmidt the tangent against the normal, falling back to an arbitrary
+        // perpendicular when the tangent is degenerate (e.g. at a sphere's poles)
+        let mut tangent = tangent - dot(tangent, normal) * normal;
+        if tangent.is_near_zero() {
+            tangent = if normal.x.abs() > 0.9 {
+                cross(normal, Vector3D { x: 0.0, y: 1.0, z: 0.0 })
+            } else {
+                cross(normal, Vector3D { x: 1.0, y: 0.0, z: 0.0 })
+            };
+        }
+        let tangent = unit_vector(tangent);
+        Self {
+            normal,
+            tangent,
+            bitangent: cross(normal, tangent),
+        }
+    }
+
+    pub fn to_world(self, local: Vector3D) -> Vector3D {
+        local.x * self.tangent + local.y * self.bitangent + local.z * self.normal
+    }
+
+    pub fn to_local(self, world: Vector3D) -> Vector3D {
+        Vector3D {
+            x: dot(world, self.tangent),
+            y: dot(world, self.bitangent),
+            z: dot(world, self.normal),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Point,
     pub normal: Vector3D,
-    pub material: Rc<dyn Material>,
+    pub shading: ShadingFrame,
+    pub material: Arc<dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    // the time of the ray that found the hit, passed on to the rays it spawns
+    pub time: f64,
+    // the object's index in the scene and its material's, counting from 1; 0 where nothing set them
+    pub object_id: u32,
+    pub material_id: u32,
 }
 
 impl HitRecord {
@@ -19,10 +76,152 @@ impl HitRecord {
         } else {
             self.normal = -outward_normal;
         }
+        self.shading = ShadingFrame::new(self.normal, self.shading.tangent);
+    }
+
+    // without an incoming ray, as for emission, nothing is reflected that could leak
+    pub fn set_shading_normal(&mut self, ray: Option<&Ray>, shading_normal: Vector3D) {
+        let mut normal = unit_vector(shading_normal);
+        if dot(normal, self.normal) < 0.0 {
+            normal = -normal;
+        }
+        let Some(ray) = ray else {
+            self.shading = ShadingFrame::new(normal, self.shading.tangent);
+            return;
+        };
+
+        // a perturbed normal can mirror the incoming ray below the geometric surface,
+        // which leaks light through it; bend the normal back towards the geometric one
+        // until the mirror direction clears the surface again
+        let incoming = -unit_vector(ray.direction);
+        let reflects_above = |n: Vector3D| {
+            let reflected = 2.0 * dot(incoming, n) * n - incoming;
+            dot(reflected, self.normal) >= MIN_REFLECTION_COS
+        };
+        if !reflects_above(normal) {
+            let mut lo = 0.0;
+            let mut hi = 1.0;
+            for _ in 0..16 {
+                let mid = 0.5 * (lo + hi);
+                if reflects_above(unit_vector((1.0 - mid) * normal + mid * self.normal)) {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            normal = unit_vector((1.0 - hi) * normal + hi * self.normal);
+        }
+
+        self.shading = ShadingFrame::new(normal, self.shading.tangent);
+    }
+
+    pub fn spawn_ray(&self, direction: Vector3D) -> Ray {
+        // offset along the geometric normal so shading-normal directions can't self-intersect
+        let offset = if dot(direction, self.normal) > 0.0 {
+            self.normal * RAY_OFFSET
+        } else {
+            -self.normal * RAY_OFFSET
+        };
+        Ray {
+            origin: self.point + offset,
+            direction,
+            time: self.time,
+        }
+    }
+
+    pub fn is_above_surface(&self, direction: Vector3D) -> bool {
+        dot(direction, self.normal) > 0.0
     }
 
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
-}
\ No newline at end of file
+pub trait Hittable: Send + Sync {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord>;
+    // None for objects without finite bounds
+    fn bounding_box(&self) -> Option<Aabb>;
+    // where the object is between the two times, for hierarchies built for a single frame; moving
+    // objects bound less than their whole motion here
+    fn bounds_during(&self, _start: f64, _end: f64) -> Option<Aabb> {
+        self.bounding_box()
+    }
+    // what the object is made of, for numbering materials; None for aggregates
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        None
+    }
+    // the fraction of light that makes it along the ray between the two distances. Surfaces block
+    // all of it, so by default this is whether the ray hits anything; media estimate it
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        if self.hit(ray, t_min, t_max, sampler).is_some() { 0.0 } else { 1.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::material::lambert::Lambert;
+
+    fn grazing_hit(ray: &Ray) -> HitRecord {
+        let up = Vector3D { x: 0.0, y: 1.0, z: 0.0 };
+        let mut hit_record = HitRecord {
+            point: Point::default(),
+            normal: up,
+            shading: ShadingFrame::new(up, Vector3D { x: 1.0, y: 0.0, z: 0.0 }),
+            material: Arc::new(Lambert::default()),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+            time: ray.time,
+            object_id: 0,
+            material_id: 0,
+        };
+        hit_record.set_face_normal(ray, up);
+        hit_record
+    }
+
+    #[test]
+    fn test_shading_frame_is_orthonormal() {
+        let frame = ShadingFrame::new(
+            Vector3D { x: 0.0, y: 0.0, z: 1.0 },
+            Vector3D { x: 1.0, y: 0.0, z: 1.0 },
+        );
+        assert!(dot(frame.normal, frame.tangent).abs() < 1e-12);
+        assert!((frame.tangent.length() - 1.0).abs() < 1e-12);
+        assert!((frame.bitangent.length() - 1.0).abs() < 1e-12);
+        let world = frame.to_world(Vector3D { x: 0.0, y: 0.0, z: 1.0 });
+        assert_eq!(world.z, 1.0);
+    }
+
+    #[test]
+    fn test_shading_normal_does_not_leak_light() {
+        let ray = Ray {
+            origin: Point { x: -1.0, y: 0.1, z: 0.0 },
+            direction: Vector3D { x: 1.0, y: -0.1, z: 0.0 },
+            time: 0.0,
+        };
+        let mut hit_record = grazing_hit(&ray);
+
+        // tilted away from the viewer, so a mirror bounce would end up below the surface
+        hit_record.set_shading_normal(Some(&ray), Vector3D { x: 1.0, y: 1.0, z: 0.0 });
+
+        let incoming = -unit_vector(ray.direction);
+        let normal = hit_record.shading.normal;
+        let reflected = 2.0 * dot(incoming, normal) * normal - incoming;
+        assert!(dot(reflected, hit_record.normal) > 0.0);
+        assert!(dot(normal, hit_record.shading.tangent).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_spawn_ray_offsets_along_geometric_normal() {
+        let ray = Ray {
+            origin: Point { x: 0.0, y: 1.0, z: 0.0 },
+            direction: Vector3D { x: 0.0, y: -1.0, z: 0.0 },
+            time: 0.0,
+        };
+        let hit_record = grazing_hit(&ray);
+        let above = hit_record.spawn_ray(Vector3D { x: 0.0, y: 1.0, z: 0.0 });
+        let below = hit_record.spawn_ray(Vector3D { x: 0.0, y: -1.0, z: 0.0 });
+        assert!(above.origin.y > 0.0);
+        assert!(below.origin.y < 0.0);
+    }
+}
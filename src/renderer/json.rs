@@ -0,0 +1,242 @@
+use std::io;
+
+// a parsed JSON document; objects keep their members in file order
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let mut parser = Parser { bytes: source.as_bytes(), position: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    // the member of an object with the given name
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn index(&self, index: usize) -> Option<&Json> {
+        self.as_array()?.get(index)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("malformed JSON at byte {}: {}", self.position, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> io::Result<Json> {
+        if !self.bytes[self.position..].starts_with(text.as_bytes()) {
+            return Err(self.error("unknown literal"));
+        }
+        self.position += text.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Json> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let name = self.string()?;
+            self.expect(b':')?;
+            members.push((name, self.value()?));
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> io::Result<Json> {
+        self.expect(b'[')?;
+        let mut values = Vec::new();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("malformed number"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut string = Vec::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.position) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = self.bytes.get(self.position).copied();
+                    self.position += 1;
+                    let character = match escaped {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    string.extend(character.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => string.push(byte),
+            }
+        }
+        String::from_utf8(string).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn hex_digits(&mut self) -> io::Result<u32> {
+        let digits = self.bytes.get(self.position..self.position + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+        let code = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()).ok_or_else(|| self.error("malformed \\u escape"))?;
+        self.position += 4;
+        Ok(code)
+    }
+
+    // the four hex digits after \u, joined with the low half of a surrogate pair
+    fn unicode_escape(&mut self) -> io::Result<char> {
+        let high = self.hex_digits()?;
+        let code = if (0xd800..0xdc00).contains(&high) && self.bytes[self.position..].starts_with(b"\\u") {
+            self.position += 2;
+            let low = self.hex_digits()?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid code point"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_document() {
+        let json = Json::parse(r#" { "name": "caf\u00e9 \"mix\"", "factors": [1, -0.5, 2e1], "flags": { "on": true, "off": false, "none": null }, "empty": [] } "#).unwrap();
+        assert_eq!(json.get("name").and_then(Json::as_str), Some("café \"mix\""));
+        let factors: Vec<f64> = json.get("factors").and_then(Json::as_array).unwrap().iter().filter_map(Json::as_f64).collect();
+        assert_eq!(factors, [1.0, -0.5, 20.0]);
+        assert_eq!(json.get("flags").and_then(|flags| flags.get("on")), Some(&Json::Bool(true)));
+        assert_eq!(json.get("flags").and_then(|flags| flags.get("none")), Some(&Json::Null));
+        assert_eq!(json.get("empty").and_then(|empty| empty.index(0)), None);
+        assert_eq!(json.get("missing"), None);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_documents() {
+        for source in ["", "{", "[1, 2", "{\"a\" 1}", "[1,]", "tru", "\"open", "1 2", "{\"a\": 1,}", "\"\\q\""] {
+            assert!(Json::parse(source).is_err(), "{}", source);
+        }
+    }
+}
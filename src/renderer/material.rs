@@ -1,14 +1,378 @@
 pub mod lambert;
 pub mod metal;
+pub mod normal_map;
+pub mod bump_map;
+pub mod microfacet;
+pub mod conductor;
+pub mod rough_dielectric;
+pub mod principled;
+pub mod isotropic;
+pub mod henyey_greenstein;
+pub mod dispersion;
+pub mod diffuse_light;
 
+use std::sync::Arc;
 use crate::renderer::hit::HitRecord;
 use crate::renderer::ray::Ray;
-use crate::renderer::vector3d::Color;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{RgbSpectrum, Spectrum, Wavelengths};
+use crate::renderer::vector3d::{Color, Vector3D};
 
-pub trait Material {
+// the kinds of bounce a path takes, each of which may have its own depth limit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    Transmission,
+    Volume,
+}
+
+pub trait Material: Send + Sync {
     fn scatter(
         &self,
         ray: &Ray,
-        hit_record: &HitRecord
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
     ) -> Option<(Color, Ray)>;
-}
\ No newline at end of file
+
+    fn emitted(&self, _: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // the surface color the albedo AOV reports and denoisers are guided by
+    fn albedo(&self, hit_record: &HitRecord) -> Color;
+
+    // how much of the weight `scatter` gave `scattered` came from diffuse lobes, the rest being
+    // specular: glossy reflection and transmission. Media count as diffuse
+    fn diffuse_share(&self, _: &Ray, _: &HitRecord, _: &Ray) -> f64 {
+        1.0
+    }
+
+    // which kind of bounce scattering the ray into `scattered` was
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Lobe {
+        if !hit_record.is_above_surface(scattered.direction) {
+            Lobe::Transmission
+        } else if self.diffuse_share(ray, hit_record, scattered) > 0.5 {
+            Lobe::Diffuse
+        } else {
+            Lobe::Glossy
+        }
+    }
+
+    // path regularization: this material with its near-specular lobes widened to at least
+    // `roughness`, or None where that changes nothing
+    fn regularized(&self, _roughness: f64) -> Option<Arc<dyn Material>> {
+        None
+    }
+
+    // phase functions of media: the density of scattering the ray into `direction`, which is also
+    // the density `scatter` samples it with. None for surfaces, which aren't lit by shadow rays
+    fn phase_pdf(&self, _: &Ray, _: Vector3D) -> Option<f64> {
+        None
+    }
+
+    // spectral rendering: the attenuation at the path's wavelengths. By default the RGB attenuation
+    // is upsampled; materials whose optics depend on the wavelength override this
+    fn scatter_spectral(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Spectrum, Ray)> {
+        self.scatter(ray, hit_record, sampler)
+            .map(|(attenuation, scattered)| (RgbSpectrum::unbounded(attenuation).sample(wavelengths), scattered))
+    }
+
+    fn emitted_spectral(&self, hit_record: &HitRecord, wavelengths: &Wavelengths) -> Spectrum {
+        RgbSpectrum::illuminant(self.emitted(hit_record)).sample(wavelengths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+    use crate::renderer::color_space::ImageTag;
+    use crate::renderer::hit::Hittable;
+    use crate::renderer::material::bump_map::BumpMap;
+    use crate::renderer::material::conductor::Conductor;
+    use crate::renderer::material::diffuse_light::DiffuseLight;
+    use crate::renderer::material::isotropic::Isotropic;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::material::metal::Metal;
+    use crate::renderer::material::normal_map::NormalMap;
+    use crate::renderer::material::principled::{Principled, Scalar, Tinted};
+    use crate::renderer::material::dispersion::Ior;
+    use crate::renderer::material::rough_dielectric::RoughDielectric;
+    use crate::renderer::spectrum::Wavelengths;
+    use crate::renderer::sphere::Sphere;
+    use crate::renderer::texture::image::ImageTexture;
+    use crate::renderer::vector3d::{unit_vector, Point, Vector3D};
+
+    const SAMPLES: usize = 20000;
+
+    // every escaping path sees a uniform white environment, so a lossless BSDF returns exactly 1
+    fn white_furnace(world: &dyn Hittable, ray: &Ray, depth: i32, sampler: &mut dyn Sampler) -> Color {
+        if depth <= 0 {
+            return Color::default();
+        }
+        match world.hit(ray, 0.001, f64::INFINITY, sampler) {
+            Some(hit_record) => match hit_record.material.scatter(ray, &hit_record, sampler) {
+                Some((attenuation, scattered)) => attenuation * white_furnace(world, &scattered, depth - 1, sampler),
+                None => Color::default(),
+            },
+            None => Color { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    // average furnace response of a unit sphere seen from cos_theta off its +z pole
+    fn furnace_at(material: Arc<dyn Material>, cos_theta: f64, depth: i32) -> f64 {
+        let sphere = Sphere {
+            center: Point::default(),
+            radius: 1.0,
+            material,
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let towards_eye = Vector3D { x: sin_theta, y: 0.0, z: cos_theta };
+        let ray = Ray {
+            origin: Point { x: 0.0, y: 0.0, z: 1.0 } + 2.0 * towards_eye,
+            direction: -towards_eye,
+            time: 0.0,
+        };
+        let mut sampler = Independent::new(0, 0);
+        let mut total = 0.0;
+        for _ in 0..SAMPLES {
+            total += white_furnace(&sphere, &ray, depth, &mut sampler).y;
+        }
+        total / SAMPLES as f64
+    }
+
+    // what a ray looking down -z from `origin` hits on a unit sphere of the material at the origin
+    fn hit_unit_sphere(material: Arc<dyn Material>, origin: Point) -> (Ray, HitRecord, Independent) {
+        let sphere = Sphere { center: Point::default(), radius: 1.0, material };
+        let ray = Ray { origin, direction: Vector3D { x: 0.0, y: 0.0, z: -1.0 }, time: 0.0 };
+        let mut sampler = Independent::new(0, 0);
+        let hit_record = sphere.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+        (ray, hit_record, sampler)
+    }
+
+    fn perfect_conductor(roughness: f64) -> Conductor {
+        Conductor {
+            eta: Color { x: 1e-3, y: 1e-3, z: 1e-3 },
+            k: Color { x: 1e4, y: 1e4, z: 1e4 },
+            roughness,
+            anisotropy: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_conductor_never_creates_energy() {
+        for roughness in [0.0, 0.2, 0.5, 1.0] {
+            for cos_theta in [1.0, 0.5, 0.1] {
+                let albedo = furnace_at(Arc::new(perfect_conductor(roughness)), cos_theta, 2);
+                assert!(albedo <= 1.0 + 1e-9, "roughness {} cos {}: {}", roughness, cos_theta, albedo);
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_conductor_preserves_energy() {
+        let albedo = furnace_at(Arc::new(perfect_conductor(0.1)), 0.8, 2);
+        assert!(albedo > 0.99, "{}", albedo);
+    }
+
+    #[test]
+    fn test_rough_conductor_loses_only_multiple_scattering() {
+        // single-scattering GGX misses the energy of inter-reflections between microfacets;
+        // at alpha = 0.25 the directional albedo integrates to about 0.916
+        let albedo = furnace_at(Arc::new(perfect_conductor(0.5)), 1.0, 2);
+        assert!((albedo - 0.916).abs() < 0.01, "{}", albedo);
+    }
+
+    #[test]
+    fn test_gold_absorbs_blue() {
+        let gold = Arc::new(Conductor::gold(0.3));
+        let sphere = Sphere { center: Point::default(), radius: 1.0, material: gold };
+        let ray = Ray {
+            origin: Point { x: 0.0, y: 0.0, z: 3.0 },
+            direction: Vector3D { x: 0.0, y: 0.0, z: -1.0 },
+            time: 0.0,
+        };
+        let color = white_furnace(&sphere, &ray, 2, &mut Independent::new(0, 0));
+        assert!(color.x > color.z);
+    }
+
+    #[test]
+    fn test_dielectric_furnace() {
+        for roughness in [0.0, 0.3] {
+            let glass = RoughDielectric {
+                ior: Ior::Constant(1.5),
+                roughness,
+                tint: Color { x: 1.0, y: 1.0, z: 1.0 },
+            };
+            // light bounces around inside the sphere, so let paths run long
+            let albedo = furnace_at(Arc::new(glass), 0.7, 64);
+            assert!(albedo <= 1.0 + 1e-9, "roughness {}: {}", roughness, albedo);
+            assert!(albedo > 0.95, "roughness {}: {}", roughness, albedo);
+        }
+    }
+
+    #[test]
+    fn test_dispersive_glass_bends_blue_more_than_red() {
+        let glass = RoughDielectric { ior: Ior::BK7, roughness: 0.0, tint: Color { x: 1.0, y: 1.0, z: 1.0 } };
+        let (ray, hit_record, mut sampler) = hit_unit_sphere(Arc::new(glass), Point { x: 0.0, y: 0.6, z: 3.0 });
+        let refracted = |lambda: f64, sampler: &mut Independent| loop {
+            let mut wavelengths = Wavelengths { lambda: [lambda, 500.0, 600.0, 700.0], pdf: [1.0; 4] };
+            let (_, scattered) = glass.scatter_spectral(&ray, &hit_record, &mut wavelengths, sampler).unwrap();
+            assert_eq!(wavelengths.pdf, [0.25, 0.0, 0.0, 0.0]);
+            // skip reflections
+            if scattered.direction.z < 0.0 {
+                return scattered.direction;
+            }
+        };
+        let (blue, red) = (refracted(420.0, &mut sampler), refracted(680.0, &mut sampler));
+        assert!(blue.y < red.y, "{:?} {:?}", blue, red);
+    }
+
+    #[test]
+    fn test_principled_furnace() {
+        let white = Tinted::constant(Color { x: 1.0, y: 1.0, z: 1.0 });
+        let metal = Principled {
+            base_color: white.clone(),
+            metallic: Scalar::constant(1.0),
+            roughness: Scalar::constant(0.3),
+            ..Principled::default()
+        };
+        let glass = Principled {
+            base_color: white.clone(),
+            roughness: Scalar::constant(0.1),
+            transmission: Scalar::constant(1.0),
+            ..Principled::default()
+        };
+        let coated = Principled {
+            base_color: Tinted::constant(Color { x: 0.5, y: 0.5, z: 0.5 }),
+            roughness: Scalar::constant(0.5),
+            clearcoat: Scalar::constant(1.0),
+            ..Principled::default()
+        };
+        for (name, material, depth) in [("metal", metal, 2), ("glass", glass, 64), ("coated", coated, 2)] {
+            for cos_theta in [1.0, 0.5] {
+                let albedo = furnace_at(Arc::new(material.clone()), cos_theta, depth);
+                assert!(albedo <= 1.01, "{} at cos {}: {}", name, cos_theta, albedo);
+                assert!(albedo > 0.5, "{} at cos {}: {}", name, cos_theta, albedo);
+            }
+        }
+    }
+
+    #[test]
+    fn test_normal_and_bump_maps_keep_what_they_wrap() {
+        // a flat normal map and a flat height map, so the wrapped materials see their own frames
+        let flat = Arc::new(ImageTexture::parse_ppm(b"P3 1 1 255 128 128 255", ImageTag::Data).unwrap());
+        let wrap = |bump: bool, material: Arc<dyn Material>| -> Arc<dyn Material> {
+            if bump {
+                Arc::new(BumpMap { height: flat.clone(), scale: 1.0, material })
+            } else {
+                Arc::new(NormalMap { map: flat.clone(), strength: 1.0, material })
+            }
+        };
+        let ray = Ray { origin: Point { x: 0.0, y: 0.6, z: 3.0 }, direction: Vector3D { x: 0.0, y: 0.0, z: -1.0 }, time: 0.0 };
+        let mut sampler = Independent::new(0, 0);
+        for bump in [false, true] {
+            let light = Sphere { center: Point::default(), radius: 1.0, material: wrap(bump, Arc::new(DiffuseLight { color: Color { x: 1.0, y: 0.5, z: 0.25 }, intensity: 4.0 })) };
+            let hit_record = light.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            assert_eq!(hit_record.material.emitted(&hit_record), Color { x: 4.0, y: 2.0, z: 1.0 });
+            let wavelengths = Wavelengths { lambda: [450.0, 500.0, 600.0, 700.0], pdf: [1.0; 4] };
+            assert!(hit_record.material.emitted_spectral(&hit_record, &wavelengths).values.iter().all(|&value| value > 0.0));
+
+            // dispersion keeps only the hero wavelength, which an upsampled RGB attenuation would not
+            let glass = RoughDielectric { ior: Ior::BK7, roughness: 0.0, tint: Color { x: 1.0, y: 1.0, z: 1.0 } };
+            let prism = Sphere { center: Point::default(), radius: 1.0, material: wrap(bump, Arc::new(glass)) };
+            let hit_record = prism.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            let mut wavelengths = Wavelengths { lambda: [420.0, 500.0, 600.0, 700.0], pdf: [1.0; 4] };
+            hit_record.material.scatter_spectral(&ray, &hit_record, &mut wavelengths, &mut sampler).unwrap();
+            assert_eq!(wavelengths.pdf, [0.25, 0.0, 0.0, 0.0]);
+
+            let fog = Sphere { center: Point::default(), radius: 1.0, material: wrap(bump, Arc::new(Isotropic::default())) };
+            let hit_record = fog.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            let (_, scattered) = hit_record.material.scatter(&ray, &hit_record, &mut sampler).unwrap();
+            assert_eq!(hit_record.material.lobe(&ray, &hit_record, &scattered), Lobe::Volume);
+        }
+    }
+
+    #[test]
+    fn test_opaque_principled_reflects_off_back_faces() {
+        // from the center of the sphere, every hit is on the inside of its shell
+        let scattered_out = |material: Principled| {
+            let sphere = Sphere { center: Point::default(), radius: 1.0, material: Arc::new(material) };
+            let ray = Ray { origin: Point::default(), direction: Vector3D { x: 0.0, y: 0.0, z: 1.0 }, time: 0.0 };
+            let mut sampler = Independent::new(0, 0);
+            let hit_record = sphere.hit(&ray, 0.001, f64::INFINITY, &mut sampler).unwrap();
+            assert!(!hit_record.front_face);
+            (0..100)
+                .filter_map(|_| hit_record.material.scatter(&ray, &hit_record, &mut sampler))
+                .filter(|(_, scattered)| scattered.direction.z > 0.0)
+                .count()
+        };
+        assert_eq!(scattered_out(Principled::default()), 0);
+        assert!(scattered_out(Principled { transmission: Scalar::constant(1.0), ..Principled::default() }) > 50);
+    }
+
+    #[test]
+    fn test_diffuse_share_separates_the_lobes() {
+        // the mean share of scattering events off the +z pole, seen from straight above
+        let mean_share = |material: Arc<dyn Material>| {
+            let (ray, hit_record, mut sampler) = hit_unit_sphere(material, Point { x: 0.0, y: 0.0, z: 3.0 });
+            let shares: Vec<f64> = (0..1000)
+                .filter_map(|_| hit_record.material.scatter(&ray, &hit_record, &mut sampler))
+                .map(|(_, scattered)| hit_record.material.diffuse_share(&ray, &hit_record, &scattered))
+                .collect();
+            shares.iter().sum::<f64>() / shares.len() as f64
+        };
+        assert_eq!(mean_share(Arc::new(Lambert::default())), 1.0);
+        assert_eq!(mean_share(Arc::new(perfect_conductor(0.3))), 0.0);
+        let metal = Principled { metallic: Scalar::constant(1.0), ..Principled::default() };
+        assert_eq!(mean_share(Arc::new(metal)), 0.0);
+        // a rough plastic mostly scatters diffusely, with a little glossy reflection
+        let plastic = mean_share(Arc::new(Principled::default()));
+        assert!(plastic > 0.7 && plastic < 1.0, "{}", plastic);
+    }
+
+    #[test]
+    fn test_regularized_mirror_blurs_its_reflection() {
+        let mirror = Metal { albedo: Color { x: 0.9, y: 0.9, z: 0.9 }, roughness: 0.0 };
+        assert!(Lambert::default().regularized(0.3).is_none());
+        assert!(Metal { roughness: 0.5, ..mirror }.regularized(0.3).is_none());
+
+        let (ray, hit_record, mut sampler) = hit_unit_sphere(mirror.regularized(0.3).unwrap(), Point { x: 0.0, y: 0.0, z: 3.0 });
+        let spread: Vec<f64> = (0..100)
+            .filter_map(|_| hit_record.material.scatter(&ray, &hit_record, &mut sampler))
+            .map(|(_, scattered)| unit_vector(scattered.direction).z)
+            .collect();
+        assert!(spread.iter().all(|&z| z > 0.0));
+        assert!(spread.iter().any(|&z| z < 0.99), "{:?}", spread);
+        // a rough mirror still never reflects more than its albedo
+        let albedo = furnace_at(mirror.regularized(0.3).unwrap(), 0.7, 2);
+        assert!(albedo <= 0.9 + 1e-9, "{}", albedo);
+    }
+
+    #[test]
+    fn test_bounces_are_classified_by_lobe() {
+        // the kinds of 100 bounces off the +z pole, seen from straight above
+        let lobes = |material: Arc<dyn Material>| {
+            let (ray, hit_record, mut sampler) = hit_unit_sphere(material, Point { x: 0.0, y: 0.0, z: 3.0 });
+            (0..100)
+                .filter_map(|_| hit_record.material.scatter(&ray, &hit_record, &mut sampler))
+                .map(|(_, scattered)| hit_record.material.lobe(&ray, &hit_record, &scattered))
+                .collect::<Vec<Lobe>>()
+        };
+        assert!(lobes(Arc::new(Lambert::default())).iter().all(|&lobe| lobe == Lobe::Diffuse));
+        assert!(lobes(Arc::new(perfect_conductor(0.3))).iter().all(|&lobe| lobe == Lobe::Glossy));
+        assert!(lobes(Arc::new(Isotropic::default())).iter().all(|&lobe| lobe == Lobe::Volume));
+        // glass at normal incidence transmits all but a few percent, which it reflects
+        let glass = RoughDielectric { ior: Ior::Constant(1.5), roughness: 0.0, tint: Color { x: 1.0, y: 1.0, z: 1.0 } };
+        let glass = lobes(Arc::new(glass));
+        let transmitted = glass.iter().filter(|&&lobe| lobe == Lobe::Transmission).count();
+        assert!(transmitted > 80 && glass.iter().all(|&lobe| lobe == Lobe::Transmission || lobe == Lobe::Glossy), "{:?}", glass);
+    }
+}
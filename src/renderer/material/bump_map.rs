@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::{Lobe, Material};
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{Spectrum, Wavelengths};
+use crate::renderer::texture::Texture;
+use crate::renderer::vector3d::{Color, Vector3D};
+
+// step in uv space used for the height map's finite differences
+const DELTA: f64 = 1.0 / 1024.0;
+
+// wraps another material and perturbs its shading normal with the gradient of a scalar height map
+pub(crate) struct BumpMap {
+    pub height: Arc<dyn Texture>,
+    pub scale: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl BumpMap {
+    fn height_at(&self, u: f64, v: f64, hit_record: &HitRecord) -> f64 {
+        // scalar maps are read from their luminance
+        let texel = self.height.value(u, v, &hit_record.point);
+        0.2126 * texel.x + 0.7152 * texel.y + 0.0722 * texel.z
+    }
+
+    // the hit record with the bumped normal the wrapped material sees
+    fn shade(&self, ray: Option<&Ray>, hit_record: &HitRecord) -> HitRecord {
+        let (u, v) = (hit_record.u, hit_record.v);
+        let height = self.height_at(u, v, hit_record);
+        let dh_du = (self.height_at(u + DELTA, v, hit_record) - height) / DELTA;
+        let dh_dv = (self.height_at(u, v + DELTA, hit_record) - height) / DELTA;
+
+        let frame = hit_record.shading;
+        let normal = frame.normal - self.scale * (dh_du * frame.tangent + dh_dv * frame.bitangent);
+
+        let mut shaded = hit_record.clone();
+        shaded.set_shading_normal(ray, normal);
+        shaded
+    }
+}
+
+impl Material for BumpMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        self.material.scatter(ray, &self.shade(Some(ray), hit_record), sampler)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.material.emitted(&self.shade(None, hit_record))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.material.albedo(hit_record)
+    }
+
+    fn diffuse_share(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.diffuse_share(ray, &self.shade(Some(ray), hit_record), scattered)
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Lobe {
+        self.material.lobe(ray, &self.shade(Some(ray), hit_record), scattered)
+    }
+
+    fn phase_pdf(&self, ray: &Ray, direction: Vector3D) -> Option<f64> {
+        self.material.phase_pdf(ray, direction)
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        let material = self.material.regularized(roughness)?;
+        Some(Arc::new(BumpMap { height: self.height.clone(), scale: self.scale, material }))
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Spectrum, Ray)> {
+        self.material.scatter_spectral(ray, &self.shade(Some(ray), hit_record), wavelengths, sampler)
+    }
+
+    fn emitted_spectral(&self, hit_record: &HitRecord, wavelengths: &Wavelengths) -> Spectrum {
+        self.material.emitted_spectral(&self.shade(None, hit_record), wavelengths)
+    }
+}
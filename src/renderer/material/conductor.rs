@@ -0,0 +1,88 @@
+use std::sync::Arc;
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::Material;
+use crate::renderer::material::microfacet::{fresnel_conductor, Ggx, reflect};
+use crate::renderer::ray::{dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Color, unit_vector};
+
+// rough metal described by its complex index of refraction eta + ik per RGB channel
+#[derive(Copy, Clone)]
+pub(crate) struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+    pub anisotropy: f64,
+}
+
+impl Conductor {
+    pub fn gold(roughness: f64) -> Self {
+        Self::preset(Color { x: 0.143, y: 0.374, z: 1.442 }, Color { x: 3.983, y: 2.385, z: 1.603 }, roughness)
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::preset(Color { x: 0.200, y: 0.924, z: 1.102 }, Color { x: 3.912, y: 2.452, z: 2.142 }, roughness)
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::preset(Color { x: 1.657, y: 0.880, z: 0.521 }, Color { x: 9.224, y: 6.270, z: 4.837 }, roughness)
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::preset(Color { x: 0.155, y: 0.117, z: 0.138 }, Color { x: 4.828, y: 3.122, z: 2.147 }, roughness)
+    }
+
+    fn preset(eta: Color, k: Color, roughness: f64) -> Self {
+        Self { eta, k, roughness, anisotropy: 0.0 }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        let frame = hit_record.shading;
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness, self.anisotropy);
+        let (u1, u2) = sampler.next_2d();
+        let wm = ggx.sample_visible_normal(wo, u1, u2);
+        let wi = reflect(wo, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let direction = frame.to_world(wi);
+        if !hit_record.is_above_surface(direction) {
+            return None;
+        }
+
+        // with visible-normal sampling D and the jacobian cancel, leaving F * G2 / G1
+        let fresnel = fresnel_conductor(dot(wo, wm), self.eta, self.k);
+        let weight = if ggx.is_smooth() {
+            1.0
+        } else {
+            ggx.masking_shadowing(wo, wi) / ggx.masking(wo)
+        };
+        Some((fresnel * weight, hit_record.spawn_ray(direction)))
+    }
+
+    // the reflectance at normal incidence
+    fn albedo(&self, _: &HitRecord) -> Color {
+        fresnel_conductor(1.0, self.eta, self.k)
+    }
+
+    fn diffuse_share(&self, _: &Ray, _: &HitRecord, _: &Ray) -> f64 {
+        0.0
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        (self.roughness < roughness).then(|| Arc::new(Conductor { roughness, ..*self }) as Arc<dyn Material>)
+    }
+}
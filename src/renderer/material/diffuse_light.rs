@@ -0,0 +1,30 @@
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::Material;
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::Color;
+
+// an area light glowing evenly from its front faces and absorbing whatever reaches it
+#[derive(Copy, Clone, Default)]
+pub(crate) struct DiffuseLight {
+    pub color: Color,
+    pub intensity: f64,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        if hit_record.front_face {
+            self.color * self.intensity
+        } else {
+            Color::default()
+        }
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.color
+    }
+}
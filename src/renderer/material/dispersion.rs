@@ -0,0 +1,69 @@
+// index of refraction, constant or a function of the wavelength
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / λ², with λ in μm
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b λ² / (λ² - c), with λ in μm
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+// the helium d line, where glass catalogues quote the index; RGB rendering uses the index there
+const D_LINE: f64 = 587.56;
+
+impl Ior {
+    // Schott N-BK7 crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    // Malitson (1965)
+    pub const FUSED_SILICA: Ior = Ior::Sellmeier {
+        b: [0.6961663, 0.4079426, 0.8974794],
+        c: [0.00467914826, 0.0135120631, 97.9340025],
+    };
+    // Peter (1923)
+    pub const DIAMOND: Ior = Ior::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+    // fitted to water at 20°C over the visible range
+    pub const WATER: Ior = Ior::Cauchy { a: 1.3240, b: 0.003104 };
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let square = micrometres * micrometres;
+        match *self {
+            Ior::Constant(ior) => ior,
+            Ior::Cauchy { a, b } => a + b / square,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * square / (square - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn nominal(&self) -> f64 {
+        self.at(D_LINE)
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_match_their_catalogue_values() {
+        for (ior, expected) in [(Ior::BK7, 1.5168), (Ior::FUSED_SILICA, 1.4585), (Ior::DIAMOND, 2.4175), (Ior::WATER, 1.3330)] {
+            assert!((ior.nominal() - expected).abs() < 1e-3, "{:?}: {}", ior, ior.nominal());
+            // normal dispersion: blue bends more than red
+            assert!(ior.at(400.0) > ior.at(550.0) && ior.at(550.0) > ior.at(700.0), "{:?}", ior);
+        }
+        assert_eq!(Ior::Constant(1.5).at(400.0), 1.5);
+        assert!(!Ior::Constant(1.5).is_dispersive());
+    }
+}
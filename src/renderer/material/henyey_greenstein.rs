@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use crate::renderer::hit::{HitRecord, ShadingFrame};
+use crate::renderer::material::{Lobe, Material};
+use crate::renderer::ray::{dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{RgbSpectrum, Spectrum, Wavelengths};
+use crate::renderer::vector3d::{Color, unit_vector, Vector3D};
+use crate::renderer::voxel_grid::VoxelGrid;
+
+// emission driven by a voxel grid's temperature channel
+pub(crate) struct Blackbody {
+    pub grid: Arc<VoxelGrid>,
+    // maps [0, 1]^3 grid coordinates to world space as min + coordinates * extent
+    pub min: Vector3D,
+    pub extent: Vector3D,
+    pub scale: f64,
+}
+
+// spectral radiance of a blackbody at the given wavelength, relative to a 6500K one at 555nm
+fn planck(wavelength_nm: f64, temperature: f64) -> f64 {
+    // second radiation constant hc/k in nm*K
+    const C2: f64 = 1.4388e7;
+    let relative = |wavelength: f64, temperature: f64| {
+        1.0 / (wavelength.powi(5) * ((C2 / (wavelength * temperature)).exp() - 1.0))
+    };
+    relative(wavelength_nm, temperature) / relative(555.0, 6500.0)
+}
+
+pub fn blackbody_color(temperature: f64) -> Color {
+    if temperature <= 0.0 {
+        return Color::default();
+    }
+    Color {
+        x: planck(610.0, temperature),
+        y: planck(550.0, temperature),
+        z: planck(465.0, temperature),
+    }
+}
+
+// anisotropic phase function for participating media: g > 0 scatters forwards, g < 0 backwards
+pub(crate) struct HenyeyGreenstein {
+    pub albedo: Color,
+    pub g: f64,
+    pub emission: Option<Blackbody>,
+}
+
+impl HenyeyGreenstein {
+    // cosine between the incoming propagation direction and the scattered one
+    fn sample_cos_theta(&self, u: f64) -> f64 {
+        let g = self.g.clamp(-0.99, 0.99);
+        if g.abs() < 1e-3 {
+            return 1.0 - 2.0 * u;
+        }
+        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+        ((1.0 + g * g - square * square) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g.clamp(-0.99, 0.99);
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        let forward = unit_vector(ray.direction);
+        let frame = ShadingFrame::new(forward, hit_record.shading.tangent);
+        let (u1, u2) = sampler.next_2d();
+        let cos_theta = self.sample_cos_theta(u1);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let local = Vector3D { x: sin_theta * phi.cos(), y: sin_theta * phi.sin(), z: cos_theta };
+        Some((self.albedo, Ray {
+            origin: hit_record.point,
+            direction: frame.to_world(local),
+            time: hit_record.time,
+        }))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn lobe(&self, _: &Ray, _: &HitRecord, _: &Ray) -> Lobe {
+        Lobe::Volume
+    }
+
+    fn phase_pdf(&self, ray: &Ray, direction: Vector3D) -> Option<f64> {
+        Some(self.phase(dot(unit_vector(ray.direction), unit_vector(direction))))
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        match &self.emission {
+            Some(emission) => {
+                let position = (hit_record.point - emission.min) / emission.extent;
+                let temperature = emission.grid.sample(position, 1);
+                // only the absorbed fraction of collisions emits
+                (1.0 - self.albedo) * emission.scale * blackbody_color(temperature)
+            }
+            None => Color::default(),
+        }
+    }
+
+    // the blackbody's own spectrum rather than its three-sample approximation
+    fn emitted_spectral(&self, hit_record: &HitRecord, wavelengths: &Wavelengths) -> Spectrum {
+        match &self.emission {
+            Some(emission) => {
+                let position = (hit_record.point - emission.min) / emission.extent;
+                let temperature = emission.grid.sample(position, 1);
+                if temperature <= 0.0 {
+                    return Spectrum::default();
+                }
+                let absorbed = RgbSpectrum::unbounded(1.0 - self.albedo).sample(wavelengths);
+                absorbed * wavelengths.map(|lambda| planck(lambda, temperature)) * emission.scale
+            }
+            None => Spectrum::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+
+    #[test]
+    fn test_sampled_mean_cosine_is_g() {
+        // the mean cosine of Henyey-Greenstein is its anisotropy parameter
+        for g in [-0.6, 0.0, 0.3, 0.85] {
+            let phase = HenyeyGreenstein { albedo: Color::default(), g, emission: None };
+            let samples = 50000;
+            let mut sampler = Independent::new(0, 0);
+            let mean: f64 = (0..samples).map(|_| phase.sample_cos_theta(sampler.next_1d())).sum::<f64>() / samples as f64;
+            assert!((mean - g).abs() < 0.01, "g {}: {}", g, mean);
+        }
+    }
+
+    #[test]
+    fn test_phase_is_normalized() {
+        let phase = HenyeyGreenstein { albedo: Color::default(), g: 0.7, emission: None };
+        let steps = 100000;
+        let integral: f64 = (0..steps)
+            .map(|i| phase.phase(-1.0 + 2.0 * (i as f64 + 0.5) / steps as f64) * 2.0 / steps as f64 * 2.0 * PI)
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+    }
+
+    #[test]
+    fn test_blackbody_shifts_towards_blue() {
+        let ember = blackbody_color(1500.0);
+        let daylight = blackbody_color(6500.0);
+        assert!(ember.x > ember.z * 10.0);
+        assert!((daylight.y - 1.0).abs() < 0.05);
+        assert!(blackbody_color(3000.0).y > ember.y);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::{Lobe, Material};
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Color, random_unit_vector};
+
+// phase function scattering equally in all directions, for participating media
+#[derive(Copy, Clone, Default)]
+pub(crate) struct Isotropic {
+    pub albedo: Color
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        // scattering happens inside the medium, so there is no surface to offset from
+        Some((self.albedo, Ray {
+            origin: hit_record.point,
+            direction: random_unit_vector(sampler),
+            time: hit_record.time,
+        }))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn lobe(&self, _: &Ray, _: &HitRecord, _: &Ray) -> Lobe {
+        Lobe::Volume
+    }
+}
@@ -1,7 +1,20 @@
+use std::f64::consts::PI;
 use crate::renderer::hit::HitRecord;
 use crate::renderer::material::Material;
 use crate::renderer::ray::Ray;
-use crate::renderer::vector3d::{Color, random_unit_vector};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Color, random_unit_vector, Vector3D};
+
+// cosine-weighted direction around +z, for lobes sampled in a local shading frame
+pub(crate) fn cosine_direction(u1: f64, u2: f64) -> Vector3D {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vector3D {
+        x: r * phi.cos(),
+        y: r * phi.sin(),
+        z: (1.0 - u1).max(0.0).sqrt(),
+    }
+}
 
 #[derive(Copy, Clone, Default)]
 pub(crate) struct Lambert {
@@ -12,20 +25,27 @@ impl Material for Lambert {
     fn scatter(
         &self,
         _: &Ray,
-        hit_record: &HitRecord
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
     ) -> Option<(Color, Ray)> {
 
-        let mut scatter_direction = hit_record.normal + random_unit_vector();
+        let mut scatter_direction = hit_record.shading.normal + random_unit_vector(sampler);
 
         // catch degenerate scatter direction
         if scatter_direction.is_near_zero() {
-            scatter_direction = hit_record.normal;
+            scatter_direction = hit_record.shading.normal;
         }
 
-        Some((self.albedo, Ray {
-            origin: hit_record.point,
-            direction: scatter_direction,
-        }))
+        // a perturbed shading normal can send rays through the actual surface
+        if !hit_record.is_above_surface(scatter_direction) {
+            return None;
+        }
+
+        Some((self.albedo, hit_record.spawn_ray(scatter_direction)))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo
     }
 }
 
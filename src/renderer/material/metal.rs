@@ -1,32 +1,69 @@
+use std::sync::Arc;
 use crate::renderer::hit::HitRecord;
 use crate::renderer::material::Material;
+use crate::renderer::material::microfacet::{self, Ggx};
 use crate::renderer::ray::{dot, Ray};
+use crate::renderer::sampler::Sampler;
 use crate::renderer::vector3d::{Color, unit_vector, Vector3D};
 
 #[derive(Copy, Clone, Default)]
 pub(crate) struct Metal {
-    pub(crate) albedo: Color
+    pub(crate) albedo: Color,
+    // GGX roughness; a perfect mirror at 0
+    pub(crate) roughness: f64,
 }
 
-fn reflect(vector: Vector3D, normal: Vector3D) -> Vector3D {
+pub(crate) fn reflect(vector: Vector3D, normal: Vector3D) -> Vector3D {
     vector - 2.0 * dot(vector, normal) * normal
 }
 
+impl Metal {
+    fn scatter_rough(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let frame = hit_record.shading;
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let ggx = Ggx::from_roughness(self.roughness, 0.0);
+        let (u1, u2) = sampler.next_2d();
+        let wi = microfacet::reflect(wo, ggx.sample_visible_normal(wo, u1, u2));
+        let direction = frame.to_world(wi);
+        if wi.z <= 0.0 || !hit_record.is_above_surface(direction) {
+            return None;
+        }
+        // visible-normal sampling leaves G2 / G1 of the constant-reflectance BRDF
+        let weight = ggx.masking_shadowing(wo, wi) / ggx.masking(wo);
+        Some((weight * self.albedo, hit_record.spawn_ray(direction)))
+    }
+}
+
 impl Material for Metal {
     fn scatter(
         &self,
         ray: &Ray,
-        hit_record: &HitRecord
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
     ) -> Option<(Color, Ray)> {
-        let reflected = reflect(unit_vector(ray.direction), hit_record.normal);
-        let scattered = Ray {
-            origin: hit_record.point,
-            direction: reflected
-        };
-
-        if dot(scattered.direction, hit_record.normal) > 0.0 {
-            return Some((self.albedo, scattered));
+        if self.roughness > 0.0 {
+            return self.scatter_rough(ray, hit_record, sampler);
+        }
+        let reflected = reflect(unit_vector(ray.direction), hit_record.shading.normal);
+
+        if dot(reflected, hit_record.shading.normal) > 0.0 && hit_record.is_above_surface(reflected) {
+            return Some((self.albedo, hit_record.spawn_ray(reflected)));
         }
         None
     }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn diffuse_share(&self, _: &Ray, _: &HitRecord, _: &Ray) -> f64 {
+        0.0
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        (self.roughness < roughness).then(|| Arc::new(Metal { roughness, ..*self }) as Arc<dyn Material>)
+    }
 }
\ No newline at end of file
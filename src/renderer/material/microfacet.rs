@@ -0,0 +1,198 @@
+use std::f64::consts::PI;
+use crate::renderer::ray::{cross, dot};
+use crate::renderer::vector3d::{Color, unit_vector, Vector3D};
+
+// below this alpha the distribution is treated as a perfect mirror
+const SMOOTH_ALPHA: f64 = 1e-4;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals in a local shading frame (z = normal)
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    // perceptual roughness is squared into alpha; anisotropy stretches the highlight along the tangent
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = (roughness * roughness).max(0.0);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self {
+            alpha_x: alpha / aspect,
+            alpha_y: alpha * aspect,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    pub fn distribution(&self, wm: Vector3D) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let x = wm.x / self.alpha_x;
+        let y = wm.y / self.alpha_y;
+        let denominator = x * x + y * y + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    fn lambda(&self, w: Vector3D) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let projected = self.alpha_x * self.alpha_x * w.x * w.x + self.alpha_y * self.alpha_y * w.y * w.y;
+        0.5 * (-1.0 + (1.0 + projected / cos2).sqrt())
+    }
+
+    pub fn masking(&self, wo: Vector3D) -> f64 {
+        1.0 / (1.0 + self.lambda(wo))
+    }
+
+    // Smith height-correlated masking-shadowing
+    pub fn masking_shadowing(&self, wo: Vector3D, wi: Vector3D) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // samples a microfacet normal proportionally to its visible projected area from wo (Heitz 2018)
+    pub fn sample_visible_normal(&self, wo: Vector3D, u1: f64, u2: f64) -> Vector3D {
+        if self.is_smooth() {
+            return Vector3D { x: 0.0, y: 0.0, z: 1.0 };
+        }
+
+        let vh = unit_vector(Vector3D { x: self.alpha_x * wo.x, y: self.alpha_y * wo.y, z: wo.z });
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vector3D { x: -vh.y, y: vh.x, z: 0.0 } / length_squared.sqrt()
+        } else {
+            Vector3D { x: 1.0, y: 0.0, z: 0.0 }
+        };
+        let t2 = cross(vh, t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        unit_vector(Vector3D {
+            x: self.alpha_x * nh.x,
+            y: self.alpha_y * nh.y,
+            z: nh.z.max(1e-6),
+        })
+    }
+}
+
+pub fn reflect(wo: Vector3D, wm: Vector3D) -> Vector3D {
+    2.0 * dot(wo, wm) * wm - wo
+}
+
+// refracts wo through a microfacet wm on its side, eta being the transmitted over incident index
+pub fn refract(wo: Vector3D, wm: Vector3D, eta: f64) -> Option<Vector3D> {
+    let cos_i = dot(wo, wm);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * wm)
+}
+
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// exact Fresnel reflectance of a conductor with complex index eta + ik, per channel
+pub fn fresnel_conductor(cos_i: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let t0 = eta * eta - k * k - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos2.sqrt() * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Color {
+        x: channel(eta.x, k.x),
+        y: channel(eta.y, k.y),
+        z: channel(eta.z, k.z),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::Sampler;
+    use crate::renderer::sampler::independent::Independent;
+
+    #[test]
+    fn test_distribution_is_normalized() {
+        // the projected area of all microfacets equals the macro surface: integral of D(m) cos(m) = 1
+        let ggx = Ggx::from_roughness(0.5, 0.3);
+        let steps = 400;
+        let mut total = 0.0;
+        for i in 0..steps {
+            for j in 0..steps {
+                let cos_theta = (i as f64 + 0.5) / steps as f64;
+                let phi = 2.0 * PI * (j as f64 + 0.5) / steps as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let wm = Vector3D { x: sin_theta * phi.cos(), y: sin_theta * phi.sin(), z: cos_theta };
+                total += ggx.distribution(wm) * cos_theta / (steps * steps) as f64 * 2.0 * PI;
+            }
+        }
+        assert!((total - 1.0).abs() < 0.01, "{}", total);
+    }
+
+    #[test]
+    fn test_visible_normals_face_the_viewer() {
+        let ggx = Ggx::from_roughness(0.8, 0.0);
+        let wo = unit_vector(Vector3D { x: 1.0, y: 0.0, z: 0.2 });
+        let mut sampler = Independent::new(0, 0);
+        for _ in 0..1000 {
+            let (u1, u2) = sampler.next_2d();
+            let wm = ggx.sample_visible_normal(wo, u1, u2);
+            assert!(wm.z > 0.0);
+            assert!(dot(wo, wm) >= -1e-9);
+        }
+    }
+
+    #[test]
+    fn test_fresnel_limits() {
+        // glass reflects 4% head-on, everything at grazing angles, and totally internally past the critical angle
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert!(fresnel_dielectric(0.0, 1.5) > 0.999);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+
+        // a conductor with no extinction reduces to the dielectric case
+        let one = Color { x: 1.5, y: 1.5, z: 1.5 };
+        let conductor = fresnel_conductor(0.7, one, Color::default());
+        assert!((conductor.x - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_refract_bends_towards_normal() {
+        let wm = Vector3D { x: 0.0, y: 0.0, z: 1.0 };
+        let wo = unit_vector(Vector3D { x: 1.0, y: 0.0, z: 1.0 });
+        let wi = refract(wo, wm, 1.5).unwrap();
+        assert!((wi.length() - 1.0).abs() < 1e-9);
+        assert!(wi.z < 0.0);
+        // Snell: sin(theta_t) = sin(theta_i) / eta
+        assert!((wi.x.abs() - (0.5_f64.sqrt() / 1.5)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::{Lobe, Material};
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{Spectrum, Wavelengths};
+use crate::renderer::texture::Texture;
+use crate::renderer::vector3d::{Color, Vector3D};
+
+// wraps another material and perturbs its shading normal with a tangent-space normal map
+pub(crate) struct NormalMap {
+    pub map: Arc<dyn Texture>,
+    pub strength: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl NormalMap {
+    // the hit record with the mapped normal the wrapped material sees
+    fn shade(&self, ray: Option<&Ray>, hit_record: &HitRecord) -> HitRecord {
+        let texel = self.map.value(hit_record.u, hit_record.v, &hit_record.point);
+
+        // texels encode [-1, 1] as [0, 1]; strength blends towards the unperturbed (0, 0, 1)
+        let mapped = 2.0 * texel - 1.0;
+        let local = Vector3D {
+            x: self.strength * mapped.x,
+            y: self.strength * mapped.y,
+            z: 1.0 + self.strength * (mapped.z - 1.0),
+        };
+
+        let mut shaded = hit_record.clone();
+        shaded.set_shading_normal(ray, hit_record.shading.to_world(local));
+        shaded
+    }
+}
+
+impl Material for NormalMap {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        self.material.scatter(ray, &self.shade(Some(ray), hit_record), sampler)
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        self.material.emitted(&self.shade(None, hit_record))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.material.albedo(hit_record)
+    }
+
+    fn diffuse_share(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        self.material.diffuse_share(ray, &self.shade(Some(ray), hit_record), scattered)
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> Lobe {
+        self.material.lobe(ray, &self.shade(Some(ray), hit_record), scattered)
+    }
+
+    fn phase_pdf(&self, ray: &Ray, direction: Vector3D) -> Option<f64> {
+        self.material.phase_pdf(ray, direction)
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        let material = self.material.regularized(roughness)?;
+        Some(Arc::new(NormalMap { map: self.map.clone(), strength: self.strength, material }))
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Spectrum, Ray)> {
+        self.material.scatter_spectral(ray, &self.shade(Some(ray), hit_record), wavelengths, sampler)
+    }
+
+    fn emitted_spectral(&self, hit_record: &HitRecord, wavelengths: &Wavelengths) -> Spectrum {
+        self.material.emitted_spectral(&self.shade(None, hit_record), wavelengths)
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::renderer::color_space::{ImageTag, SRGB_IMAGE};
+use crate::renderer::hit::HitRecord;
+use crate::renderer::json::Json;
+use crate::renderer::material::Material;
+use crate::renderer::material::dispersion::Ior;
+use crate::renderer::material::lambert::cosine_direction;
+use crate::renderer::material::microfacet::{fresnel_dielectric, Ggx, reflect, refract};
+use crate::renderer::material::rough_dielectric::RoughDielectric;
+use crate::renderer::ray::{dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::texture::Texture;
+use crate::renderer::texture::image::ImageTexture;
+use crate::renderer::vector3d::{Color, unit_vector, Vector3D};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+// a scalar material input: factor times one channel of an optional texture, as in glTF
+#[derive(Clone)]
+pub(crate) struct Scalar {
+    pub factor: f64,
+    pub texture: Option<(Arc<dyn Texture>, Channel)>,
+}
+
+impl Scalar {
+    pub fn constant(factor: f64) -> Self {
+        Self { factor, texture: None }
+    }
+
+    fn at(&self, hit_record: &HitRecord) -> f64 {
+        match &self.texture {
+            Some((texture, channel)) => {
+                let texel = texture.value(hit_record.u, hit_record.v, &hit_record.point);
+                self.factor * match channel {
+                    Channel::Red => texel.x,
+                    Channel::Green => texel.y,
+                    Channel::Blue => texel.z,
+                }
+            }
+            None => self.factor,
+        }
+    }
+}
+
+// a color material input: factor times an optional texture
+#[derive(Clone)]
+pub(crate) struct Tinted {
+    pub factor: Color,
+    pub texture: Option<Arc<dyn Texture>>,
+}
+
+impl Tinted {
+    pub fn constant(factor: Color) -> Self {
+        Self { factor, texture: None }
+    }
+
+    fn at(&self, hit_record: &HitRecord) -> Color {
+        match &self.texture {
+            Some(texture) => self.factor * texture.value(hit_record.u, hit_record.v, &hit_record.point),
+            None => self.factor,
+        }
+    }
+}
+
+// layered uber-material after Burley's Disney BSDF, parameterized like glTF's metallic-roughness model
+#[derive(Clone)]
+pub(crate) struct Principled {
+    pub base_color: Tinted,
+    pub metallic: Scalar,
+    pub roughness: Scalar,
+    // scales the dielectric F0 derived from ior, as KHR_materials_specular
+    pub specular: Scalar,
+    pub ior: f64,
+    pub sheen: Tinted,
+    pub clearcoat: Scalar,
+    pub clearcoat_roughness: Scalar,
+    pub transmission: Scalar,
+    pub anisotropy: Scalar,
+    pub subsurface: Scalar,
+    // the least roughness of every lobe, raised by path regularization
+    pub regularization: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Tinted::constant(Color { x: 0.8, y: 0.8, z: 0.8 }),
+            metallic: Scalar::constant(0.0),
+            roughness: Scalar::constant(0.5),
+            specular: Scalar::constant(1.0),
+            ior: 1.5,
+            sheen: Tinted::constant(Color::default()),
+            clearcoat: Scalar::constant(0.0),
+            clearcoat_roughness: Scalar::constant(0.03),
+            transmission: Scalar::constant(0.0),
+            anisotropy: Scalar::constant(0.0),
+            subsurface: Scalar::constant(0.0),
+            regularization: 0.0,
+        }
+    }
+}
+
+// the material inputs evaluated at one surface point
+struct Lobes {
+    base_color: Color,
+    metallic: f64,
+    specular_f0: Color,
+    sheen: Color,
+    clearcoat: f64,
+    transmission: f64,
+    subsurface: f64,
+    roughness: f64,
+    specular: Ggx,
+    coat: Ggx,
+}
+
+fn luminance(color: Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+fn schlick(f0: Color, cos: f64) -> Color {
+    f0 + schlick_weight(cos) * (1.0 - f0)
+}
+
+fn microfacet_pdf(ggx: &Ggx, wo: Vector3D, wm: Vector3D) -> f64 {
+    ggx.masking(wo) * ggx.distribution(wm) / (4.0 * wo.z)
+}
+
+impl Lobes {
+    // un-normalized probabilities of picking the diffuse, specular, clearcoat and transmission lobes
+    fn selection(&self, wo: Vector3D) -> [f64; 4] {
+        let dielectric = 1.0 - self.metallic;
+        let fresnel = luminance(schlick(self.specular_f0, wo.z));
+        [
+            dielectric * (1.0 - self.transmission) * (luminance(self.base_color) + luminance(self.sheen)),
+            fresnel.max(0.05),
+            0.25 * self.clearcoat,
+            dielectric * self.transmission * luminance(self.base_color) * (1.0 - fresnel),
+        ]
+    }
+
+    fn coat_attenuation(&self, wo: Vector3D) -> f64 {
+        1.0 - self.clearcoat * schlick(Color { x: 0.04, y: 0.04, z: 0.04 }, wo.z).x
+    }
+
+    // BSDF times cosine above the surface, split into the diffuse and the glossy lobes
+    fn reflection(&self, wo: Vector3D, wi: Vector3D) -> (Color, Color) {
+        let wm = unit_vector(wo + wi);
+        let cos_d = dot(wi, wm);
+        let (fo, fi) = (schlick_weight(wo.z), schlick_weight(wi.z));
+
+        // Burley diffuse with retro-reflection, blended with the Hanrahan-Krueger subsurface approximation
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let diffuse = (1.0 + (fd90 - 1.0) * fo) * (1.0 + (fd90 - 1.0) * fi);
+        let fss90 = self.roughness * cos_d * cos_d;
+        let fss = (1.0 + (fss90 - 1.0) * fo) * (1.0 + (fss90 - 1.0) * fi);
+        let subsurface = 1.25 * (fss * (1.0 / (wo.z + wi.z) - 0.5) + 0.5);
+        let diffuse_weight = (1.0 - self.metallic) * (1.0 - self.transmission);
+        let diffuse = diffuse_weight
+            * ((1.0 - self.subsurface) * diffuse + self.subsurface * subsurface)
+            * self.base_color / PI
+            + diffuse_weight * schlick_weight(cos_d) * self.sheen;
+
+        let specular = schlick(self.specular_f0, cos_d)
+            * (self.specular.distribution(wm) * self.specular.masking_shadowing(wo, wi) / (4.0 * wo.z * wi.z));
+        let coat = self.clearcoat
+            * schlick(Color { x: 0.04, y: 0.04, z: 0.04 }, cos_d).x
+            * self.coat.distribution(wm) * self.coat.masking_shadowing(wo, wi) / (4.0 * wo.z * wi.z);
+
+        let attenuation = self.coat_attenuation(wo) * wi.z;
+        (attenuation * diffuse, attenuation * specular + coat * wi.z * Color { x: 1.0, y: 1.0, z: 1.0 })
+    }
+
+    // BSDF times cosine and the pdf of sampling wi through the reflection lobes, both above the surface
+    fn evaluate_reflection(&self, wo: Vector3D, wi: Vector3D, probabilities: &[f64; 4]) -> (Color, f64) {
+        let (diffuse, glossy) = self.reflection(wo, wi);
+        let wm = unit_vector(wo + wi);
+        let pdf = probabilities[0] * wi.z / PI
+            + probabilities[1] * microfacet_pdf(&self.specular, wo, wm)
+            + probabilities[2] * microfacet_pdf(&self.coat, wo, wm);
+        (diffuse + glossy, pdf)
+    }
+}
+
+impl Principled {
+    fn lobes(&self, hit_record: &HitRecord) -> Lobes {
+        let base_color = self.base_color.at(hit_record);
+        let metallic = self.metallic.at(hit_record).clamp(0.0, 1.0);
+        // keep a little roughness so the microfacet pdfs stay finite for mixture sampling
+        let roughness = self.roughness.at(hit_record).max(self.regularization).clamp(0.02, 1.0);
+        let anisotropy = self.anisotropy.at(hit_record).clamp(0.0, 1.0);
+        let reflectance = ((self.ior - 1.0) / (self.ior + 1.0)).powi(2);
+        let dielectric_f0 = (reflectance * self.specular.at(hit_record)).clamp(0.0, 1.0);
+        let specular_f0 = (1.0 - metallic) * Color { x: dielectric_f0, y: dielectric_f0, z: dielectric_f0 }
+            + metallic * base_color;
+        Lobes {
+            base_color,
+            metallic,
+            specular_f0,
+            sheen: self.sheen.at(hit_record),
+            clearcoat: self.clearcoat.at(hit_record).clamp(0.0, 1.0),
+            transmission: self.transmission.at(hit_record).clamp(0.0, 1.0),
+            subsurface: self.subsurface.at(hit_record).clamp(0.0, 1.0),
+            roughness,
+            specular: Ggx::from_roughness(roughness, anisotropy),
+            coat: Ggx::from_roughness(self.clearcoat_roughness.at(hit_record).max(self.regularization).clamp(0.02, 1.0), 0.0),
+        }
+    }
+
+    // whether any light passes into the surface rather than only being reflected off it
+    fn transmits(&self, hit_record: &HitRecord) -> bool {
+        (1.0 - self.metallic.at(hit_record).clamp(0.0, 1.0)) * self.transmission.at(hit_record).clamp(0.0, 1.0) > 0.0
+    }
+
+    pub fn from_gltf(gltf: &GltfMaterial) -> Self {
+        let metallic_roughness = gltf.metallic_roughness_texture.clone();
+        let base = [gltf.base_color_factor[0], gltf.base_color_factor[1], gltf.base_color_factor[2]];
+        Self {
+            base_color: Tinted {
+                factor: Color { x: base[0], y: base[1], z: base[2] },
+                texture: gltf.base_color_texture.clone(),
+            },
+            // glTF packs roughness into the green and metalness into the blue channel
+            metallic: Scalar {
+                factor: gltf.metallic_factor,
+                texture: metallic_roughness.clone().map(|texture| (texture, Channel::Blue)),
+            },
+            roughness: Scalar {
+                factor: gltf.roughness_factor,
+                texture: metallic_roughness.map(|texture| (texture, Channel::Green)),
+            },
+            specular: Scalar::constant(gltf.specular_factor),
+            ior: gltf.ior,
+            sheen: Tinted::constant(Color {
+                x: gltf.sheen_color_factor[0],
+                y: gltf.sheen_color_factor[1],
+                z: gltf.sheen_color_factor[2],
+            }),
+            clearcoat: Scalar::constant(gltf.clearcoat_factor),
+            clearcoat_roughness: Scalar::constant(gltf.clearcoat_roughness_factor),
+            transmission: Scalar::constant(gltf.transmission_factor),
+            anisotropy: Scalar::constant(gltf.anisotropy_strength),
+            subsurface: Scalar::constant(0.0),
+            regularization: 0.0,
+        }
+    }
+
+    // reads every material of a Wavefront MTL file using the PBR extension statements (Pr, Pm, Ps, Pc, ...)
+    pub fn from_mtl(source: &str, directory: &Path) -> io::Result<HashMap<String, Self>> {
+        let mut materials = HashMap::new();
+        let mut current: Option<(String, Self)> = None;
+
+        for line in source.lines() {
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue,
+            };
+            let arguments: Vec<&str> = tokens.collect();
+
+            if keyword == "newmtl" {
+                if let Some((name, material)) = current.take() {
+                    materials.insert(name, material);
+                }
+                current = Some((arguments.join(" "), Self::default()));
+                continue;
+            }
+            let material = match current.as_mut() {
+                Some((_, material)) => material,
+                None => continue,
+            };
+
+            let number = |index: usize| -> io::Result<f64> {
+                arguments
+                    .get(index)
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed '{}' statement", line.trim())))
+            };
+            let texture = |tag: ImageTag| -> io::Result<Arc<dyn Texture>> {
+                // options such as -bm come before the file name, which is always last
+                let file = arguments.last().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("missing texture in '{}'", line.trim()))
+                })?;
+                Ok(Arc::new(ImageTexture::from_ppm(directory.join(file), tag)?))
+            };
+
+            match keyword {
+                "Kd" => material.base_color.factor = Color { x: number(0)?, y: number(1)?, z: number(2)? },
+                "map_Kd" => material.base_color.texture = Some(texture(SRGB_IMAGE)?),
+                "Pm" => material.metallic.factor = number(0)?,
+                "map_Pm" => material.metallic.texture = Some((texture(ImageTag::Data)?, Channel::Red)),
+                "Pr" => material.roughness.factor = number(0)?,
+                "map_Pr" => material.roughness.texture = Some((texture(ImageTag::Data)?, Channel::Red)),
+                "Ps" => {
+                    let sheen = number(0)?;
+                    material.sheen.factor = Color { x: sheen, y: sheen, z: sheen };
+                }
+                "map_Ps" => material.sheen.texture = Some(texture(SRGB_IMAGE)?),
+                "Pc" => material.clearcoat.factor = number(0)?,
+                "Pcr" => material.clearcoat_roughness.factor = number(0)?,
+                "aniso" => material.anisotropy.factor = number(0)?,
+                "Ni" => material.ior = number(0)?,
+                // MTL has no transmission statement; exporters write transparency as dissolve
+                "d" => material.transmission.factor = 1.0 - number(0)?,
+                "Tr" => material.transmission.factor = number(0)?,
+                _ => {}
+            }
+        }
+        if let Some((name, material)) = current {
+            materials.insert(name, material);
+        }
+        Ok(materials)
+    }
+}
+
+// the metallic-roughness core of a glTF 2.0 material plus the KHR_materials_* extensions Principled understands
+#[derive(Clone)]
+pub(crate) struct GltfMaterial {
+    pub base_color_factor: [f64; 4],
+    pub base_color_texture: Option<Arc<dyn Texture>>,
+    pub metallic_factor: f64,
+    pub roughness_factor: f64,
+    pub metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    pub specular_factor: f64,
+    pub ior: f64,
+    pub sheen_color_factor: [f64; 3],
+    pub clearcoat_factor: f64,
+    pub clearcoat_roughness_factor: f64,
+    pub transmission_factor: f64,
+    pub anisotropy_strength: f64,
+}
+
+impl Default for GltfMaterial {
+    // defaults as given by the glTF specification
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            specular_factor: 1.0,
+            ior: 1.5,
+            sheen_color_factor: [0.0, 0.0, 0.0],
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            transmission_factor: 0.0,
+            anisotropy_strength: 0.0,
+        }
+    }
+}
+
+// a number property of a glTF object, or its default where the object or the property is missing
+fn gltf_number(object: Option<&Json>, name: &str, default: f64) -> io::Result<f64> {
+    match object.and_then(|object| object.get(name)) {
+        Some(value) => value
+            .as_f64()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("glTF property '{}' is not a number", name))),
+        None => Ok(default),
+    }
+}
+
+fn gltf_numbers<const N: usize>(object: Option<&Json>, name: &str, default: [f64; N]) -> io::Result<[f64; N]> {
+    match object.and_then(|object| object.get(name)) {
+        Some(value) => value
+            .as_array()
+            .and_then(|values| values.iter().map(Json::as_f64).collect::<Option<Vec<f64>>>())
+            .and_then(|values| values.try_into().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("glTF property '{}' is not {} numbers", name, N))),
+        None => Ok(default),
+    }
+}
+
+impl GltfMaterial {
+    // reads every material of a glTF 2.0 document, by name or, for unnamed ones, by index. Textures
+    // are looked up through the document's textures and images and must be PPM files in `directory`
+    pub fn parse(document: &str, directory: &Path) -> io::Result<HashMap<String, Self>> {
+        let json = Json::parse(document)?;
+        let texture = |info: Option<&Json>, tag: ImageTag| -> io::Result<Option<Arc<dyn Texture>>> {
+            let Some(info) = info else {
+                return Ok(None);
+            };
+            let uri = info
+                .get("index")
+                .and_then(Json::as_f64)
+                .and_then(|index| json.get("textures")?.index(index as usize))
+                .and_then(|texture| json.get("images")?.index(texture.get("source")?.as_f64()? as usize))
+                .and_then(|image| image.get("uri")?.as_str())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "glTF texture reference to no image file"))?;
+            Ok(Some(Arc::new(ImageTexture::from_ppm(directory.join(uri), tag)?)))
+        };
+
+        let mut materials = HashMap::new();
+        let list = json.get("materials").and_then(Json::as_array).unwrap_or_default();
+        for (index, material) in list.iter().enumerate() {
+            let pbr = material.get("pbrMetallicRoughness");
+            let extension = |name: &str| material.get("extensions").and_then(|extensions| extensions.get(name));
+            let clearcoat = extension("KHR_materials_clearcoat");
+            let defaults = Self::default();
+            let gltf = Self {
+                base_color_factor: gltf_numbers(pbr, "baseColorFactor", defaults.base_color_factor)?,
+                base_color_texture: texture(pbr.and_then(|pbr| pbr.get("baseColorTexture")), SRGB_IMAGE)?,
+                metallic_factor: gltf_number(pbr, "metallicFactor", defaults.metallic_factor)?,
+                roughness_factor: gltf_number(pbr, "roughnessFactor", defaults.roughness_factor)?,
+                metallic_roughness_texture: texture(pbr.and_then(|pbr| pbr.get("metallicRoughnessTexture")), ImageTag::Data)?,
+                specular_factor: gltf_number(extension("KHR_materials_specular"), "specularFactor", defaults.specular_factor)?,
+                ior: gltf_number(extension("KHR_materials_ior"), "ior", defaults.ior)?,
+                sheen_color_factor: gltf_numbers(extension("KHR_materials_sheen"), "sheenColorFactor", defaults.sheen_color_factor)?,
+                clearcoat_factor: gltf_number(clearcoat, "clearcoatFactor", defaults.clearcoat_factor)?,
+                clearcoat_roughness_factor: gltf_number(clearcoat, "clearcoatRoughnessFactor", defaults.clearcoat_roughness_factor)?,
+                transmission_factor: gltf_number(extension("KHR_materials_transmission"), "transmissionFactor", defaults.transmission_factor)?,
+                anisotropy_strength: gltf_number(extension("KHR_materials_anisotropy"), "anisotropyStrength", defaults.anisotropy_strength)?,
+            };
+            let name = material.get("name").and_then(Json::as_str).map_or_else(|| index.to_string(), str::to_string);
+            materials.insert(name, gltf);
+        }
+        Ok(materials)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        // paths inside a transmissive object only see the smooth-to-rough glass interface; opaque
+        // surfaces seen from behind, like the inside of an open mesh, shade as their front does
+        if !hit_record.front_face && self.transmits(hit_record) {
+            let roughness = self.roughness.at(hit_record).max(self.regularization);
+            return RoughDielectric { ior: Ior::Constant(self.ior), roughness, tint: Color { x: 1.0, y: 1.0, z: 1.0 } }
+                .scatter(ray, hit_record, sampler);
+        }
+
+        let frame = hit_record.shading;
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(hit_record);
+        let weights = lobes.selection(wo);
+        let total: f64 = weights.iter().sum();
+        let probabilities = weights.map(|weight| weight / total);
+
+        let choice = sampler.next_1d();
+        let (u1, u2) = sampler.next_2d();
+        let wi = if choice < probabilities[0] {
+            cosine_direction(u1, u2)
+        } else if choice < probabilities[0] + probabilities[1] {
+            reflect(wo, lobes.specular.sample_visible_normal(wo, u1, u2))
+        } else if choice < probabilities[0] + probabilities[1] + probabilities[2] {
+            reflect(wo, lobes.coat.sample_visible_normal(wo, u1, u2))
+        } else {
+            // transmission is the only lobe below the surface, so its weight needs no mixture pdf
+            let wm = lobes.specular.sample_visible_normal(wo, u1, u2);
+            let wi = refract(wo, wm, self.ior)?;
+            let direction = frame.to_world(wi);
+            if wi.z >= 0.0 || hit_record.is_above_surface(direction) {
+                return None;
+            }
+            let fresnel = fresnel_dielectric(dot(wo, wm), self.ior);
+            let weight = (1.0 - lobes.metallic) * lobes.transmission * (1.0 - fresnel) * lobes.coat_attenuation(wo)
+                * lobes.specular.masking_shadowing(wo, wi) / lobes.specular.masking(wo) / probabilities[3];
+            return Some((weight * lobes.base_color, hit_record.spawn_ray(direction)));
+        };
+
+        let direction = frame.to_world(wi);
+        if wi.z <= 0.0 || !hit_record.is_above_surface(direction) {
+            return None;
+        }
+        let (value, pdf) = lobes.evaluate_reflection(wo, wi, &probabilities);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((value / pdf, hit_record.spawn_ray(direction)))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.base_color.at(hit_record)
+    }
+
+    fn diffuse_share(&self, ray: &Ray, hit_record: &HitRecord, scattered: &Ray) -> f64 {
+        let frame = hit_record.shading;
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        let wi = frame.to_local(unit_vector(scattered.direction));
+        // back faces of transmissive surfaces and transmission only go through the glass interface
+        if (!hit_record.front_face && self.transmits(hit_record)) || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let (diffuse, glossy) = self.lobes(hit_record).reflection(wo, wi);
+        let total = luminance(diffuse + glossy);
+        if total > 0.0 { luminance(diffuse) / total } else { 0.0 }
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        (self.regularization < roughness).then(|| Arc::new(Principled { regularization: roughness, ..self.clone() }) as Arc<dyn Material>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_mtl() {
+        let source = "# exported\nnewmtl brushed steel\nKd 0.6 0.6 0.65\nPm 1\nPr 0.35\naniso 0.8\n\nnewmtl glass\nNi 1.45\nd 0.1\n";
+        let materials = Principled::from_mtl(source, Path::new(".")).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let steel = &materials["brushed steel"];
+        assert_eq!(steel.metallic.factor, 1.0);
+        assert_eq!(steel.roughness.factor, 0.35);
+        assert_eq!(steel.anisotropy.factor, 0.8);
+        assert_eq!(steel.base_color.factor.z, 0.65);
+
+        let glass = &materials["glass"];
+        assert_eq!(glass.ior, 1.45);
+        assert!((glass.transmission.factor - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_from_mtl_rejects_malformed_numbers() {
+        assert!(Principled::from_mtl("newmtl broken\nPr rough\n", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_parse_gltf_materials() {
+        let document = r#"{
+            "asset": { "version": "2.0" },
+            "materials": [
+                {
+                    "name": "lacquer",
+                    "pbrMetallicRoughness": { "baseColorFactor": [0.5, 0.1, 0.1, 1.0], "metallicFactor": 0.0, "roughnessFactor": 0.4 },
+                    "extensions": {
+                        "KHR_materials_clearcoat": { "clearcoatFactor": 1.0, "clearcoatRoughnessFactor": 0.05 },
+                        "KHR_materials_ior": { "ior": 1.4 },
+                        "KHR_materials_unknown": { "ignored": true }
+                    }
+                },
+                { "extensions": { "KHR_materials_transmission": { "transmissionFactor": 0.8 }, "KHR_materials_anisotropy": { "anisotropyStrength": 0.6 } } }
+            ]
+        }"#;
+        let materials = GltfMaterial::parse(document, Path::new(".")).unwrap();
+        let lacquer = Principled::from_gltf(&materials["lacquer"]);
+        assert_eq!(lacquer.base_color.factor, Color { x: 0.5, y: 0.1, z: 0.1 });
+        assert_eq!((lacquer.metallic.factor, lacquer.roughness.factor), (0.0, 0.4));
+        assert_eq!((lacquer.clearcoat.factor, lacquer.clearcoat_roughness.factor, lacquer.ior), (1.0, 0.05, 1.4));
+        // unnamed materials go by their index, with the specification's defaults where unset
+        let unnamed = Principled::from_gltf(&materials["1"]);
+        assert_eq!((unnamed.transmission.factor, unnamed.anisotropy.factor), (0.8, 0.6));
+        assert_eq!((unnamed.metallic.factor, unnamed.specular.factor), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_parse_gltf_rejects_bad_properties() {
+        let material = |properties: &str| format!(r#"{{ "materials": [ {{ "pbrMetallicRoughness": {} }} ] }}"#, properties);
+        assert!(GltfMaterial::parse(&material(r#"{ "metallicFactor": "high" }"#), Path::new(".")).is_err());
+        assert!(GltfMaterial::parse(&material(r#"{ "baseColorFactor": [1, 1] }"#), Path::new(".")).is_err());
+        assert!(GltfMaterial::parse(&material(r#"{ "baseColorTexture": { "index": 0 } }"#), Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_from_gltf_uses_spec_defaults() {
+        let material = Principled::from_gltf(&GltfMaterial::default());
+        assert_eq!(material.metallic.factor, 1.0);
+        assert_eq!(material.roughness.factor, 1.0);
+        assert!(material.metallic.texture.is_none());
+    }
+}
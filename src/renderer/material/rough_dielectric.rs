@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use crate::renderer::hit::HitRecord;
+use crate::renderer::material::Material;
+use crate::renderer::material::dispersion::Ior;
+use crate::renderer::material::microfacet::{fresnel_dielectric, Ggx, reflect, refract};
+use crate::renderer::ray::{dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::spectrum::{RgbSpectrum, Spectrum, Wavelengths};
+use crate::renderer::vector3d::{Color, unit_vector};
+
+// glass-like interface that reflects or transmits through GGX microfacets
+#[derive(Copy, Clone)]
+pub(crate) struct RoughDielectric {
+    pub ior: Ior,
+    pub roughness: f64,
+    pub tint: Color,
+}
+
+impl RoughDielectric {
+    // the scattered ray and its weight, and whether it was transmitted
+    fn sample(&self, ray: &Ray, hit_record: &HitRecord, ior: f64, sampler: &mut dyn Sampler) -> Option<(f64, bool, Ray)> {
+        // the shading frame always faces the incoming ray, so only the ratio of indices flips
+        let eta = if hit_record.front_face { ior } else { 1.0 / ior };
+        let frame = hit_record.shading;
+        let wo = frame.to_local(-unit_vector(ray.direction));
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let ggx = Ggx::from_roughness(self.roughness, 0.0);
+        let (u1, u2) = sampler.next_2d();
+        let wm = ggx.sample_visible_normal(wo, u1, u2);
+        let cos_i = dot(wo, wm);
+        let fresnel = fresnel_dielectric(cos_i, eta);
+
+        // choosing reflection with probability F cancels the Fresnel term from the weight
+        let wi = if sampler.next_1d() < fresnel {
+            let wi = reflect(wo, wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract(wo, wm, eta)?;
+            if wi.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let direction = frame.to_world(wi);
+        if hit_record.is_above_surface(direction) != (wi.z > 0.0) {
+            return None;
+        }
+
+        let weight = if ggx.is_smooth() {
+            1.0
+        } else {
+            ggx.masking_shadowing(wo, wi) / ggx.masking(wo)
+        };
+        Some((weight, wi.z < 0.0, hit_record.spawn_ray(direction)))
+    }
+
+    fn attenuation(&self, weight: f64, transmitted: bool) -> Color {
+        if transmitted { self.tint * weight } else { Color { x: weight, y: weight, z: weight } }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Color, Ray)> {
+        let (weight, transmitted, scattered) = self.sample(ray, hit_record, self.ior.nominal(), sampler)?;
+        Some((self.attenuation(weight, transmitted), scattered))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Color {
+        self.tint
+    }
+
+    fn diffuse_share(&self, _: &Ray, _: &HitRecord, _: &Ray) -> f64 {
+        0.0
+    }
+
+    fn regularized(&self, roughness: f64) -> Option<Arc<dyn Material>> {
+        (self.roughness < roughness).then(|| Arc::new(RoughDielectric { roughness, ..*self }) as Arc<dyn Material>)
+    }
+
+    // a dispersive interface sends every wavelength its own way, so the path follows the hero's
+    fn scatter_spectral(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut dyn Sampler
+    ) -> Option<(Spectrum, Ray)> {
+        if self.ior.is_dispersive() {
+            wavelengths.terminate_secondary();
+        }
+        let (weight, transmitted, scattered) = self.sample(ray, hit_record, self.ior.at(wavelengths.lambda[0]), sampler)?;
+        Some((RgbSpectrum::unbounded(self.attenuation(weight, transmitted)).sample(wavelengths), scattered))
+    }
+}
@@ -0,0 +1,377 @@
+use std::f64::consts::PI;
+use crate::renderer::aabb::Aabb;
+use crate::renderer::hit::{Hittable, HitRecord, ShadingFrame};
+use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::vector3d::{Point, Vector3D};
+
+// the most an object may turn between the times its swept bounds are sampled at
+const MAX_BOUNDS_STEP_DEGREES: f64 = 5.0;
+
+pub trait Interpolate: Copy {
+    fn lerp(self, other: Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + t * (other - self)
+    }
+}
+
+impl Interpolate for Vector3D {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + t * (other - self)
+    }
+}
+
+// how a key's value goes over into the next key's
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    // holds the value until the next key
+    Step,
+    // eases along a cubic Bezier from (0, 0) to (1, 1) with these two handles, x being the fraction of
+    // the time and y the fraction of the change between the keys, as CSS's cubic-bezier(). The handles
+    // are kept within the unit square, so the values stay between the keys'
+    Bezier { out_handle: (f64, f64), in_handle: (f64, f64) },
+}
+
+impl Interpolation {
+    // slow out of the key and slow into the next
+    pub const EASE: Interpolation = Interpolation::Bezier { out_handle: (0.42, 0.0), in_handle: (0.58, 1.0) };
+
+    // the fraction of the change between the keys reached at fraction x of the time between them
+    fn progress(self, x: f64) -> f64 {
+        match self {
+            Interpolation::Linear => x,
+            Interpolation::Step => 0.0,
+            Interpolation::Bezier { out_handle, in_handle } => {
+                let cubic = |a: f64, b: f64, s: f64| {
+                    let r = 1.0 - s;
+                    3.0 * r * r * s * a.clamp(0.0, 1.0) + 3.0 * r * s * s * b.clamp(0.0, 1.0) + s * s * s
+                };
+                // with the handles' x within [0, 1] the curve's x grows with its parameter
+                let (mut lo, mut hi) = (0.0, 1.0);
+                for _ in 0..48 {
+                    let mid = 0.5 * (lo + hi);
+                    if cubic(out_handle.0, in_handle.0, mid) < x {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                cubic(out_handle.1, in_handle.1, 0.5 * (lo + hi))
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Key<T> {
+    pub time: f64,
+    pub value: T,
+    // towards the next key
+    pub interpolation: Interpolation,
+}
+
+impl<T> Key<T> {
+    pub fn new(time: f64, value: T, interpolation: Interpolation) -> Self {
+        Self { time, value, interpolation }
+    }
+}
+
+// a track of values keyed at times, interpolated between the keys as each key says and held
+// before the first and after the last
+#[derive(Clone, Debug)]
+pub struct Keyframes<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T: Interpolate> Keyframes<T> {
+    // linear between the keys
+    pub fn new(keys: Vec<(f64, T)>) -> Self {
+        Self::with_keys(keys.into_iter().map(|(time, value)| Key::new(time, value, Interpolation::Linear)).collect())
+    }
+
+    pub fn with_keys(mut keys: Vec<Key<T>>) -> Self {
+        assert!(!keys.is_empty(), "keyframes need at least one key");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new(vec![(0.0, value)])
+    }
+
+    pub fn linear(start: (f64, T), end: (f64, T)) -> Self {
+        Self::new(vec![start, end])
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let next = self.keys.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return self.keys[0].value;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].value;
+        }
+        let (from, to) = (&self.keys[next - 1], &self.keys[next]);
+        from.value.lerp(to.value, from.interpolation.progress((time - from.time) / (to.time - from.time)))
+    }
+
+    pub fn keys(&self) -> &[Key<T>] {
+        &self.keys
+    }
+
+    // the stretches of the track between `start` and `end` as (from, to, lowest, highest): every value
+    // the track takes there is from.lerp(to, p) of one of them, with p between lowest and highest
+    pub fn reach(&self, start: f64, end: f64) -> Vec<(T, T, f64, f64)> {
+        let (first, last) = (&self.keys[0], &self.keys[self.keys.len() - 1]);
+        let mut reach = Vec::new();
+        if self.keys.len() == 1 || start < first.time {
+            reach.push((first.value, first.value, 0.0, 0.0));
+        }
+        if end > last.time {
+            reach.push((last.value, last.value, 0.0, 0.0));
+        }
+        for pair in self.keys.windows(2) {
+            let (from, to) = (&pair[0], &pair[1]);
+            let (from_time, to_time) = (start.max(from.time), end.min(to.time));
+            if from_time > to_time {
+                continue;
+            }
+            let fraction = |time: f64| (time - from.time) / (to.time - from.time);
+            let (lowest, highest) = match from.interpolation {
+                Interpolation::Linear => (fraction(from_time), fraction(to_time)),
+                Interpolation::Step => (0.0, if to_time >= to.time { 1.0 } else { 0.0 }),
+                // an eased span may turn back within the window, so its extremes are searched for
+                Interpolation::Bezier { .. } => (0..=32)
+                    .map(|step| {
+                        let time = from_time + (to_time - from_time) * step as f64 / 32.0;
+                        from.interpolation.progress(fraction(time))
+                    })
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(lowest, highest), p| (lowest.min(p), highest.max(p))),
+            };
+            reach.push((from.value, to.value, lowest, highest));
+        }
+        reach
+    }
+}
+
+// scales uniformly, then rotates about x, y and z in that order, then translates. Rotations are
+// Euler angles in degrees and interpolate as such, so a wheel keyed from 0 to 720 turns twice
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub translation: Vector3D,
+    pub rotation: Vector3D,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self { translation: Vector3D::default(), rotation: Vector3D::default(), scale: 1.0 }
+    }
+}
+
+impl Interpolate for Transform {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        Self {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.lerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+fn rotate_about(v: Vector3D, axis: usize, degrees: f64) -> Vector3D {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    match axis {
+        0 => Vector3D { x: v.x, y: cos * v.y - sin * v.z, z: sin * v.y + cos * v.z },
+        1 => Vector3D { x: cos * v.x + sin * v.z, y: v.y, z: -sin * v.x + cos * v.z },
+        _ => Vector3D { x: cos * v.x - sin * v.y, y: sin * v.x + cos * v.y, z: v.z },
+    }
+}
+
+impl Transform {
+    fn rotate(&self, v: Vector3D) -> Vector3D {
+        let v = rotate_about(v, 0, self.rotation.x);
+        let v = rotate_about(v, 1, self.rotation.y);
+        rotate_about(v, 2, self.rotation.z)
+    }
+
+    fn unrotate(&self, v: Vector3D) -> Vector3D {
+        let v = rotate_about(v, 2, -self.rotation.z);
+        let v = rotate_about(v, 1, -self.rotation.y);
+        rotate_about(v, 0, -self.rotation.x)
+    }
+
+    pub fn point_to_world(&self, point: Point) -> Point {
+        self.translation + self.rotate(self.scale * point)
+    }
+
+    pub fn point_to_object(&self, point: Point) -> Point {
+        self.unrotate(point - self.translation) / self.scale
+    }
+
+    pub fn vector_to_object(&self, vector: Vector3D) -> Vector3D {
+        self.unrotate(vector) / self.scale
+    }
+
+    // the scale is uniform, so normals only turn
+    pub fn normal_to_world(&self, normal: Vector3D) -> Vector3D {
+        self.rotate(normal)
+    }
+}
+
+// an object placed by a transform that may be keyed over time, e.g. a spinning wheel
+pub(crate) struct Transformed {
+    object: Box<dyn Hittable>,
+    motion: Keyframes<Transform>,
+}
+
+impl Transformed {
+    pub fn new(object: Box<dyn Hittable>, motion: Keyframes<Transform>) -> Self {
+        Self { object, motion }
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
+        let transform = self.motion.at(ray.time);
+        // the direction is mapped along with the origin, so distances along the ray stay the same
+        let local = Ray {
+            origin: transform.point_to_object(ray.origin),
+            direction: transform.vector_to_object(ray.direction),
+            time: ray.time,
+        };
+        let mut record = self.object.hit(&local, t_min, t_max, sampler)?;
+        record.point = transform.point_to_world(record.point);
+        record.normal = transform.normal_to_world(record.normal);
+        record.shading = ShadingFrame {
+            normal: transform.normal_to_world(record.shading.normal),
+            tangent: transform.normal_to_world(record.shading.tangent),
+            bitangent: transform.normal_to_world(record.shading.bitangent),
+        };
+        Some(record)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let transform = self.motion.at(ray.time);
+        let local = Ray {
+            origin: transform.point_to_object(ray.origin),
+            direction: transform.vector_to_object(ray.direction),
+            time: ray.time,
+        };
+        self.object.transmittance(&local, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds_during(f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    fn bounds_during(&self, start: f64, end: f64) -> Option<Aabb> {
+        let bounds = self.object.bounds_during(start, end)?;
+        let corners: Vec<Point> = (0..8)
+            .map(|corner| Point {
+                x: if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                y: if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                z: if corner & 4 == 0 { bounds.min.z } else { bounds.max.z },
+            })
+            .collect();
+
+        // sample every stretch of the motion finely enough that the object turns by at most the step
+        // between samples, which bounds how far a corner's arc bulges past the chord between them
+        let mut swept = Aabb::empty();
+        for (from, to, lowest, highest) in self.motion.reach(start, end) {
+            let turn = (to.rotation.x - from.rotation.x).abs()
+                + (to.rotation.y - from.rotation.y).abs()
+                + (to.rotation.z - from.rotation.z).abs();
+            let steps = (turn * (highest - lowest) / MAX_BOUNDS_STEP_DEGREES).ceil().max(1.0) as usize;
+            for step in 0..=steps {
+                let transform = from.lerp(to, lowest + (highest - lowest) * step as f64 / steps as f64);
+                for &corner in &corners {
+                    swept = swept.include(transform.point_to_world(corner));
+                }
+            }
+        }
+
+        let scale = self.motion.keys().iter().map(|key| key.value.scale.abs()).fold(0.0, f64::max);
+        let radius = corners.iter().map(|corner| corner.length()).fold(0.0, f64::max) * scale;
+        let bulge = radius * (1.0 - (0.5 * MAX_BOUNDS_STEP_DEGREES * PI / 180.0).cos());
+        let padding = Vector3D { x: bulge, y: bulge, z: bulge };
+        Some(Aabb { min: swept.min - padding, max: swept.max + padding })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::renderer::bvh::Bvh;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::polyhedron::ConvexPolyhedron;
+    use crate::renderer::sampler::SamplerKind;
+    use crate::renderer::sphere::MovingSphere;
+
+    fn ray_at(origin: Point, direction: Vector3D, time: f64) -> Ray {
+        Ray { origin, direction, time }
+    }
+
+    #[test]
+    fn test_keyframes_interpolate_between_keys_and_hold_outside() {
+        let keys = Keyframes::new(vec![(2.0, 10.0), (0.0, 0.0), (1.0, 4.0)]);
+        assert_eq!(keys.at(-1.0), 0.0);
+        assert_eq!(keys.at(0.5), 2.0);
+        assert_eq!(keys.at(1.5), 7.0);
+        assert_eq!(keys.at(3.0), 10.0);
+    }
+
+    #[test]
+    fn test_moving_sphere_is_found_through_the_hierarchy_at_every_time() {
+        let material = Arc::new(Lambert::default());
+        let center = Keyframes::linear(
+            (0.0, Point { x: -2.0, y: 0.0, z: -3.0 }),
+            (1.0, Point { x: 2.0, y: 0.0, z: -3.0 }),
+        );
+        let world = Bvh::new(vec![Box::new(MovingSphere { center, radius: 0.5, material })]);
+        let mut sampler = SamplerKind::Independent.create(0, 1, false);
+        let down_z = Vector3D { x: 0.0, y: 0.0, z: -1.0 };
+        for (x, time) in [(-2.0, 0.0), (0.0, 0.5), (2.0, 1.0)] {
+            let ray = ray_at(Point { x, y: 0.0, z: 0.0 }, down_z, time);
+            let record = world.hit(&ray, 0.001, f64::INFINITY, sampler.as_mut()).unwrap();
+            assert!((record.t - 2.5).abs() < 1e-9);
+            assert_eq!(record.time, time);
+        }
+        let early = ray_at(Point { x: 2.0, y: 0.0, z: 0.0 }, down_z, 0.0);
+        assert!(world.hit(&early, 0.001, f64::INFINITY, sampler.as_mut()).is_none());
+    }
+
+    #[test]
+    fn test_spinning_object_is_bounded_and_hit_where_it_has_turned_to() {
+        // a bar along +x, half a turn about z over the shutter
+        let bar = ConvexPolyhedron::cuboid(
+            Point { x: 0.5, y: -0.1, z: -0.1 },
+            Point { x: 1.5, y: 0.1, z: 0.1 },
+            Arc::new(Lambert::default()),
+        );
+        let spin = Keyframes::linear(
+            (0.0, Transform { translation: Vector3D { x: 0.0, y: 0.0, z: -3.0 }, ..Transform::default() }),
+            (1.0, Transform {
+                translation: Vector3D { x: 0.0, y: 0.0, z: -3.0 },
+                rotation: Vector3D { x: 0.0, y: 0.0, z: 180.0 },
+                scale: 1.0,
+            }),
+        );
+        let world = Bvh::new(vec![Box::new(Transformed::new(Box::new(bar), spin))]);
+        let mut sampler = SamplerKind::Independent.create(0, 1, false);
+        let down_z = Vector3D { x: 0.0, y: 0.0, z: -1.0 };
+        for (target, time) in [((1.0, 0.0), 0.0), ((0.0, 1.0), 0.5), ((-1.0, 0.0), 1.0)] {
+            let ray = ray_at(Point { x: target.0, y: target.1, z: 0.0 }, down_z, time);
+            let record = world.hit(&ray, 0.001, f64::INFINITY, sampler.as_mut()).unwrap();
+            assert!((record.t - 2.9).abs() < 1e-9);
+            assert!((record.normal.z - 1.0).abs() < 1e-9);
+        }
+        let elsewhere = ray_at(Point { x: 0.0, y: 1.0, z: 0.0 }, down_z, 0.0);
+        assert!(world.hit(&elsewhere, 0.001, f64::INFINITY, sampler.as_mut()).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use std::io;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// the most a stored deflate block holds
+const MAX_BLOCK: usize = 65535;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+// an 8-bit RGB PNG of pixels given row by row from the top, red first. The rows go into the zlib
+// stream unfiltered in stored blocks, which every reader decodes but which are not compressed
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> io::Result<Vec<u8>> {
+    let row = width as usize * 3;
+    if rgb.len() != row * height as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "expected three bytes per pixel"));
+    }
+    let mut scanlines = Vec::with_capacity(rgb.len() + height as usize);
+    for line in rgb.chunks(row.max(1)).take(height as usize) {
+        // filter type 0, none
+        scanlines.push(0);
+        scanlines.extend(line);
+    }
+
+    // zlib header for deflate with a 32K window, then the blocks and the checksum
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = scanlines.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        stream.push(blocks.peek().is_none() as u8);
+        let length = block.len() as u16;
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(&scanlines).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &stream);
+    chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_chunks_are_checksummed_and_hold_the_rows() {
+        // wide enough that the image data spans two stored blocks
+        let (width, height) = (200, 120);
+        let rgb: Vec<u8> = (0..width * height * 3).map(|index| (index * 7 % 251) as u8).collect();
+        let png = encode(width, height, &rgb).unwrap();
+        assert_eq!(png[..8], SIGNATURE);
+
+        let mut at = 8;
+        let mut kinds = Vec::new();
+        let mut stream = Vec::new();
+        while at < png.len() {
+            let length = read_u32(&png, at) as usize;
+            let kind = &png[at + 4..at + 8];
+            assert_eq!(read_u32(&png, at + 8 + length), crc32(&png[at + 4..at + 8 + length]));
+            if kind == b"IDAT" {
+                stream.extend(&png[at + 8..at + 8 + length]);
+            }
+            kinds.push(String::from_utf8_lossy(kind).to_string());
+            at += 12 + length;
+        }
+        assert_eq!(kinds, ["IHDR", "IDAT", "IEND"]);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+
+        let mut position = 2;
+        let mut scanlines = Vec::new();
+        loop {
+            let last = stream[position] == 1;
+            let length = u16::from_le_bytes([stream[position + 1], stream[position + 2]]) as usize;
+            scanlines.extend(&stream[position + 5..position + 5 + length]);
+            position += 5 + length;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(read_u32(&stream, position), adler32(&scanlines));
+        let row = width as usize * 3;
+        for (y, line) in scanlines.chunks(row + 1).enumerate() {
+            assert_eq!(line[0], 0);
+            assert_eq!(line[1..], rgb[y * row..(y + 1) * row]);
+        }
+    }
+
+    #[test]
+    fn test_short_pixel_data_is_rejected() {
+        assert!(encode(3, 2, &[0; 17]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use crate::renderer::aabb::Aabb;
+use crate::renderer::hit::{Hittable, HitRecord, ShadingFrame};
+use crate::renderer::material::Material;
+use crate::renderer::ray::{cross, dot, Ray};
+use crate::renderer::sampler::Sampler;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::vector3d::{Point, unit_vector, Vector3D};
+
+// the half-space dot(normal, p) <= offset, with the normal pointing out of the solid
+#[derive(Debug, Copy, Clone)]
+struct Plane {
+    normal: Vector3D,
+    offset: f64,
+}
+
+impl Plane {
+    fn through(point: Point, normal: Vector3D) -> Self {
+        let normal = unit_vector(normal);
+        Self { normal, offset: dot(normal, point) }
+    }
+}
+
+// convex solid bounded by planes, e.g. boxes and prisms
+pub(crate) struct ConvexPolyhedron {
+    planes: Vec<Plane>,
+    bounds: Aabb,
+    pub material: Arc<dyn Material>,
+}
+
+impl ConvexPolyhedron {
+    pub fn cuboid(min: Point, max: Point, material: Arc<dyn Material>) -> Self {
+        let axes = [
+            Vector3D { x: 1.0, y: 0.0, z: 0.0 },
+            Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+            Vector3D { x: 0.0, y: 0.0, z: 1.0 },
+        ];
+        let planes = axes.iter().flat_map(|&axis| [Plane::through(max, axis), Plane::through(min, -axis)]).collect();
+        Self { planes, bounds: Aabb { min, max }, material }
+    }
+
+    // the triangle swept along `extrusion`
+    pub fn prism(base: [Point; 3], extrusion: Vector3D, material: Arc<dyn Material>) -> Self {
+        let mut planes = Vec::new();
+        for index in 0..3 {
+            let (from, to, opposite) = (base[index], base[(index + 1) % 3], base[(index + 2) % 3]);
+            let normal = cross(to - from, extrusion);
+            let outward = if dot(normal, opposite - from) > 0.0 { -normal } else { normal };
+            planes.push(Plane::through(from, outward));
+        }
+        let normal = cross(base[1] - base[0], base[2] - base[0]);
+        let bottom = if dot(normal, extrusion) > 0.0 { -normal } else { normal };
+        planes.push(Plane::through(base[0], bottom));
+        planes.push(Plane::through(base[0] + extrusion, -bottom));
+        let bounds = base.iter().fold(Aabb::empty(), |bounds, &corner| bounds.include(corner).include(corner + extrusion));
+        Self { planes, bounds, material }
+    }
+}
+
+impl Hittable for ConvexPolyhedron {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitRecord> {
+        count(Counter::PolyhedronTests);
+        // the ray is inside between the last plane it enters and the first one it leaves
+        let (mut near, mut far) = (f64::NEG_INFINITY, f64::INFINITY);
+        let (mut entering, mut leaving) = (Vector3D::default(), Vector3D::default());
+        for plane in &self.planes {
+            let distance = plane.offset - dot(plane.normal, ray.origin);
+            let speed = dot(plane.normal, ray.direction);
+            if speed == 0.0 {
+                if distance < 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let t = distance / speed;
+            if speed < 0.0 && t > near {
+                near = t;
+                entering = plane.normal;
+            } else if speed > 0.0 && t < far {
+                far = t;
+                leaving = plane.normal;
+            }
+            if near > far {
+                return None;
+            }
+        }
+        let (t, outward_normal) = if near >= t_min && near <= t_max {
+            (near, entering)
+        } else if far >= t_min && far <= t_max {
+            (far, leaving)
+        } else {
+            return None;
+        };
+
+        let mut hit_record = HitRecord {
+            point: ray.at(t),
+            normal: outward_normal,
+            // the frame picks a tangent of its own
+            shading: ShadingFrame::new(outward_normal, Vector3D::default()),
+            material: self.material.clone(),
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: false,
+            time: ray.time,
+            object_id: 0,
+            material_id: 0,
+        };
+        hit_record.set_face_normal(ray, outward_normal);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::material::lambert::Lambert;
+    use crate::renderer::sampler::independent::Independent;
+
+    fn ray(origin: Point, direction: Vector3D) -> Ray {
+        Ray { origin, direction, time: 0.0 }
+    }
+
+    #[test]
+    fn test_cuboid_is_hit_from_outside_and_inside() {
+        let cuboid = ConvexPolyhedron::cuboid(
+            Point { x: -1.0, y: -1.0, z: -1.0 },
+            Point { x: 1.0, y: 2.0, z: 1.0 },
+            Arc::new(Lambert::default()),
+        );
+        let mut sampler = Independent::new(0, 0);
+        let outside = ray(Point { x: 0.0, y: 0.0, z: 5.0 }, Vector3D { x: 0.0, y: 0.0, z: -1.0 });
+        let hit = cuboid.hit(&outside, 0.001, f64::INFINITY, &mut sampler).unwrap();
+        assert_eq!(hit.t, 4.0);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal.z, 1.0);
+
+        let inside = ray(Point { x: 0.0, y: 0.0, z: 0.0 }, Vector3D { x: 0.0, y: 1.0, z: 0.0 });
+        let hit = cuboid.hit(&inside, 0.001, f64::INFINITY, &mut sampler).unwrap();
+        assert_eq!(hit.t, 2.0);
+        assert!(!hit.front_face);
+        assert_eq!(hit.normal.y, -1.0);
+
+        let beside = ray(Point { x: 3.0, y: 0.0, z: 5.0 }, Vector3D { x: 0.0, y: 0.0, z: -1.0 });
+        assert!(cuboid.hit(&beside, 0.001, f64::INFINITY, &mut sampler).is_none());
+    }
+
+    #[test]
+    fn test_prism_faces_point_outwards() {
+        let base = [
+            Point { x: 0.0, y: 0.0, z: 0.0 },
+            Point { x: 0.0, y: 0.0, z: -1.0 },
+            Point { x: 0.0, y: 1.0, z: -0.5 },
+        ];
+        let prism = ConvexPolyhedron::prism(base, Vector3D { x: 2.0, y: 0.0, z: 0.0 }, Arc::new(Lambert::default()));
+        let bounds = prism.bounding_box().unwrap();
+        assert_eq!((bounds.min.x, bounds.max.x, bounds.max.y), (0.0, 2.0, 1.0));
+        let mut sampler = Independent::new(0, 0);
+        // straight down onto the sloped faces near the ridge, and up through the flat bottom
+        let down = ray(Point { x: 1.0, y: 3.0, z: -0.4 }, Vector3D { x: 0.0, y: -1.0, z: 0.0 });
+        let hit = prism.hit(&down, 0.001, f64::INFINITY, &mut sampler).unwrap();
+        assert!((hit.point.y - 0.8).abs() < 1e-9 && hit.front_face);
+        let up = ray(Point { x: 1.0, y: -1.0, z: -0.5 }, Vector3D { x: 0.0, y: 1.0, z: 0.0 });
+        assert_eq!(prism.hit(&up, 0.001, f64::INFINITY, &mut sampler).unwrap().t, 1.0);
+        let past_the_end = ray(Point { x: 2.5, y: -1.0, z: -0.5 }, Vector3D { x: 0.0, y: 1.0, z: 0.0 });
+        assert!(prism.hit(&past_the_end, 0.001, f64::INFINITY, &mut sampler).is_none());
+    }
+}
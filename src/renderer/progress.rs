@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::renderer::stats::Statistics;
+
+// receives progress reports from the render workers; every method may be called from any thread
+pub trait Progress: Sync {
+    fn start(&self, _tiles: usize) {}
+    // with the work counted while rendering the tile
+    fn tile_finished(&self, _statistics: &Statistics) {}
+    fn finish(&self) {}
+    // after each pass of a progressive render, with the samples per pixel it reached
+    fn pass_finished(&self, _samples: u32, _elapsed: Duration, _noise: f64) {}
+}
+
+// for callers that don't want any output
+pub struct Silent;
+
+impl Progress for Silent {}
+
+// adds up the statistics of every tile and passes the reports on
+pub struct StatisticsCollector<'a> {
+    progress: &'a dyn Progress,
+    total: Mutex<Statistics>,
+}
+
+impl<'a> StatisticsCollector<'a> {
+    pub fn new(progress: &'a dyn Progress) -> Self {
+        Self {
+            progress,
+            total: Mutex::new(Statistics::default()),
+        }
+    }
+
+    pub fn into_statistics(self) -> Statistics {
+        self.total.into_inner().unwrap()
+    }
+}
+
+impl Progress for StatisticsCollector<'_> {
+    fn start(&self, tiles: usize) {
+        self.progress.start(tiles);
+    }
+
+    fn tile_finished(&self, statistics: &Statistics) {
+        self.total.lock().unwrap().merge(statistics);
+        self.progress.tile_finished(statistics);
+    }
+
+    fn finish(&self) {
+        self.progress.finish();
+    }
+
+    fn pass_finished(&self, samples: u32, elapsed: Duration, noise: f64) {
+        self.progress.pass_finished(samples, elapsed, noise);
+    }
+}
+
+const BAR_WIDTH: usize = 30;
+
+struct BarState {
+    tiles: usize,
+    finished: usize,
+    rays: u64,
+    start: Instant,
+    last_draw: Option<Instant>,
+}
+
+// single-line terminal progress bar on stderr, redrawn at most every `interval`
+pub struct ProgressBar {
+    interval: Duration,
+    state: Mutex<BarState>,
+}
+
+impl ProgressBar {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: Mutex::new(BarState {
+                tiles: 0,
+                finished: 0,
+                rays: 0,
+                start: Instant::now(),
+                last_draw: None,
+            }),
+        }
+    }
+}
+
+impl Default for ProgressBar {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100))
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn format_rate(rays: u64, elapsed: Duration) -> String {
+    let rate = rays as f64 / elapsed.as_secs_f64().max(1e-9);
+    if rate >= 1e6 {
+        format!("{:.2} Mrays/s", rate / 1e6)
+    } else {
+        format!("{:.1} krays/s", rate / 1e3)
+    }
+}
+
+impl BarState {
+    fn line(&self) -> String {
+        let fraction = if self.tiles == 0 { 1.0 } else { self.finished as f64 / self.tiles as f64 };
+        let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+        let elapsed = self.start.elapsed();
+        let eta = if self.finished == 0 {
+            String::from("--:--:--")
+        } else {
+            format_duration(elapsed.mul_f64((1.0 - fraction) / fraction))
+        };
+        format!(
+            "[{}{}] {:3.0}% {}/{} tiles, ETA {}, {}",
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            100.0 * fraction,
+            self.finished,
+            self.tiles,
+            eta,
+            format_rate(self.rays, elapsed),
+        )
+    }
+}
+
+impl Progress for ProgressBar {
+    fn start(&self, tiles: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.tiles = tiles;
+        state.finished = 0;
+        state.rays = 0;
+        state.start = Instant::now();
+        state.last_draw = None;
+    }
+
+    fn tile_finished(&self, statistics: &Statistics) {
+        let mut state = self.state.lock().unwrap();
+        state.finished += 1;
+        state.rays += statistics.rays();
+        if state.last_draw.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+        state.last_draw = Some(Instant::now());
+        eprint!("\r{}", state.line());
+        io::stderr().flush().ok();
+    }
+
+    fn finish(&self) {
+        let state = self.state.lock().unwrap();
+        eprintln!("\r{} in {}", state.line(), format_duration(state.start.elapsed()));
+    }
+
+    fn pass_finished(&self, samples: u32, elapsed: Duration, noise: f64) {
+        eprintln!("{} spp after {:.1?}, noise {:.5}", samples, elapsed, noise);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar_line() {
+        let state = BarState {
+            tiles: 4,
+            finished: 1,
+            rays: 0,
+            start: Instant::now() - Duration::from_secs(10),
+            last_draw: None,
+        };
+        let line = state.line();
+        assert!(line.starts_with(&format!("[{}{}]  25% 1/4 tiles", "#".repeat(8), " ".repeat(22))), "{}", line);
+        assert!(line.contains("ETA 00:00:30"), "{}", line);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 25 * 60 + 7)), "03:25:07");
+    }
+}
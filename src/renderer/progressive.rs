@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+use crate::renderer::framebuffer::Framebuffer;
+use crate::renderer::hit::Hittable;
+use crate::renderer::progress::Progress;
+use crate::renderer::{camera, new_framebuffer, render_pass, RenderSettings};
+
+// renders whole-image passes of pass_samples spp until samples_per_pixel is reached, the time
+// budget runs out or the mean pixel noise drops below the target, whichever comes first; a resumed
+// framebuffer continues from the samples it already holds
+#[derive(Copy, Clone, Debug)]
+pub struct Progressive {
+    pub pass_samples: u32,
+    // snapshot after every pass when unset
+    pub snapshot_interval: Option<Duration>,
+    pub time_budget: Option<Duration>,
+    pub noise_target: Option<f64>,
+}
+
+impl Progressive {
+    pub fn new(pass_samples: u32) -> Self {
+        Self {
+            pass_samples,
+            snapshot_interval: None,
+            time_budget: None,
+            noise_target: None,
+        }
+    }
+}
+
+pub fn render_progressive(
+    settings: &RenderSettings,
+    world: &dyn Hittable,
+    progressive: &Progressive,
+    resume: Option<Framebuffer>,
+    progress: &dyn Progress,
+    mut snapshot: impl FnMut(&Framebuffer),
+) -> Framebuffer {
+    let camera = camera(settings);
+    let mut framebuffer = resume.unwrap_or_else(|| new_framebuffer(settings));
+    let start = Instant::now();
+    let mut last_snapshot = start;
+    let mut samples = framebuffer.pixels.iter().map(|pixel| pixel.samples()).max().unwrap_or(0);
+    while samples < settings.samples_per_pixel {
+        let pass = progressive.pass_samples.max(1).min(settings.samples_per_pixel - samples);
+        render_pass(settings, &camera, world, &mut framebuffer, pass, progress);
+        samples += pass;
+
+        let noise = framebuffer.noise();
+        progress.pass_finished(samples, start.elapsed(), noise);
+        let finished = samples >= settings.samples_per_pixel
+            || progressive.time_budget.is_some_and(|budget| start.elapsed() >= budget)
+            || progressive.noise_target.is_some_and(|target| noise <= target);
+        let snapshot_due = progressive.snapshot_interval.is_none_or(|interval| last_snapshot.elapsed() >= interval);
+        if finished {
+            break;
+        }
+        if snapshot_due {
+            snapshot(&framebuffer);
+            last_snapshot = Instant::now();
+        }
+    }
+    framebuffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::renderer::progress::Silent;
+    use crate::renderer::render_image;
+    use crate::renderer::scene::spheres;
+    use crate::renderer::color_space::OutputTransform;
+
+    fn small() -> RenderSettings {
+        RenderSettings {
+            width: 24,
+            samples_per_pixel: 8,
+            depth: 6,
+            ..RenderSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_passes_add_up_to_a_single_render() {
+        let world = spheres(0);
+        let mut snapshots = 0;
+        let framebuffer = render_progressive(&small(), &world, &Progressive::new(3), None, &Silent, |_| snapshots += 1);
+        // passes of 3, 3 and 2 spp; the last one is the result rather than a snapshot
+        assert_eq!(snapshots, 2);
+        let transform = OutputTransform::default();
+        assert_eq!(framebuffer.to_image().to_ppm(&transform), render_image(&small(), &world, &Silent).to_ppm(&transform));
+    }
+
+    #[test]
+    fn test_passes_are_reported_to_the_observer() {
+        struct Passes(Mutex<Vec<u32>>);
+        impl Progress for Passes {
+            fn pass_finished(&self, samples: u32, _: Duration, _: f64) {
+                self.0.lock().unwrap().push(samples);
+            }
+        }
+        let passes = Passes(Mutex::new(Vec::new()));
+        render_progressive(&small(), &spheres(0), &Progressive::new(3), None, &passes, |_| {});
+        assert_eq!(passes.0.into_inner().unwrap(), [3, 6, 8]);
+    }
+
+    #[test]
+    fn test_time_budget_stops_after_a_pass() {
+        let progressive = Progressive {
+            time_budget: Some(Duration::ZERO),
+            ..Progressive::new(2)
+        };
+        let framebuffer = render_progressive(&small(), &spheres(0), &progressive, None, &Silent, |_| panic!("no snapshot expected"));
+        assert!(framebuffer.pixels.iter().all(|pixel| pixel.samples() == 2));
+    }
+
+    #[test]
+    fn test_noise_target_stops_early() {
+        let settings = RenderSettings { samples_per_pixel: 1000, ..small() };
+        let progressive = Progressive {
+            noise_target: Some(0.05),
+            snapshot_interval: Some(Duration::from_secs(3600)),
+            ..Progressive::new(4)
+        };
+        let framebuffer = render_progressive(&settings, &spheres(0), &progressive, None, &Silent, |_| panic!("no snapshot expected"));
+        assert!(framebuffer.pixels[0].samples() < 1000);
+    }
+}
@@ -1,8 +1,11 @@
 use crate::renderer::vector3d::{Point, Vector3D};
 
+#[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Point,
     pub direction: Vector3D,
+    // when within the shutter interval the ray was cast, which moving objects are seen at
+    pub time: f64,
 }
 
 impl Ray {
@@ -15,6 +18,14 @@ pub fn dot(first: Vector3D, second: Vector3D) -> f64 {
     first.x * second.x + first.y * second.y + first.z * second.z
 }
 
+pub fn cross(first: Vector3D, second: Vector3D) -> Vector3D {
+    Vector3D {
+        x: first.y * second.z - first.z * second.y,
+        y: first.z * second.x - first.x * second.z,
+        z: first.x * second.y - first.y * second.x,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -32,6 +43,7 @@ mod tests {
                 y: 2.0,
                 z: 6.0,
             },
+            time: 0.0,
         };
         let point = ray.at(5.0);
         assert_eq!(point.x, 6.0);
@@ -54,4 +66,22 @@ mod tests {
         let dot_product = dot(first, second);
         assert_eq!(dot_product, 32.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cross() {
+        let first = Vector3D {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let second = Vector3D {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        };
+        let cross_product = cross(first, second);
+        assert_eq!(cross_product.x, 0.0);
+        assert_eq!(cross_product.y, 0.0);
+        assert_eq!(cross_product.z, 1.0);
+    }
+}
@@ -0,0 +1,320 @@
+pub mod independent;
+pub mod stratified;
+pub mod halton;
+pub mod sobol;
+pub mod blue_noise;
+
+use std::fmt;
+use std::str::FromStr;
+use crate::renderer::sampler::blue_noise::BlueNoise;
+use crate::renderer::sampler::halton::Halton;
+use crate::renderer::sampler::independent::Independent;
+use crate::renderer::sampler::sobol::Sobol;
+use crate::renderer::sampler::stratified::Stratified;
+
+// stream separating scene generation from pixel sampling
+pub const SCENE_STREAM: u64 = 0x5ce7e;
+
+// low-discrepancy dimensions reserved for each bounce; anything a bounce draws past these
+// (e.g. long delta-tracking walks) comes from an independent stream instead
+pub const DIMENSIONS_PER_BOUNCE: u32 = 8;
+
+// source of the random numbers of one pixel sample. Bounce b owns dimensions
+// [b * DIMENSIONS_PER_BOUNCE, (b + 1) * DIMENSIONS_PER_BOUNCE), so the same decision at the same
+// depth always uses the same dimension, whatever the previous bounces consumed
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample: u32);
+    fn start_bounce(&mut self, bounce: u32);
+    fn next_1d(&mut self) -> f64;
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+pub const SAMPLER_KINDS: [SamplerKind; 4] = [
+    SamplerKind::Independent,
+    SamplerKind::Stratified,
+    SamplerKind::Halton,
+    SamplerKind::Sobol,
+];
+
+impl SamplerKind {
+    pub fn create(self, seed: u64, samples_per_pixel: u32, blue_noise: bool) -> Box<dyn Sampler> {
+        let pixel = PixelSample {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            count: samples_per_pixel.max(1),
+            blue_noise: if blue_noise { Some(BlueNoise::shared()) } else { None },
+        };
+        match self {
+            SamplerKind::Independent => Box::new(Independent::for_pixel(pixel)),
+            SamplerKind::Stratified => Box::new(LowDiscrepancy::new(Stratified, pixel)),
+            SamplerKind::Halton => Box::new(LowDiscrepancy::new(Halton::new(), pixel)),
+            SamplerKind::Sobol => Box::new(LowDiscrepancy::new(Sobol, pixel)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        SAMPLER_KINDS
+            .iter()
+            .find(|kind| kind.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown sampler '{}'", name))
+    }
+}
+
+pub(crate) fn mix(mut z: u64) -> u64 {
+    // SplitMix64 finalizer
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x2545f4914f6cdd1d, |hash, &value| mix(hash ^ value))
+}
+
+pub(crate) fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// Kensler's hashed permutation of 0..length, "Correlated Multi-Jittered Sampling" (2013)
+pub(crate) fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            return (i.wrapping_add(seed)) % length;
+        }
+    }
+}
+
+// which sample of which pixel is being drawn
+#[derive(Clone)]
+pub(crate) struct PixelSample {
+    pub seed: u64,
+    pub x: u32,
+    pub y: u32,
+    pub index: u32,
+    pub count: u32,
+    pub blue_noise: Option<&'static BlueNoise>,
+}
+
+impl PixelSample {
+    // per-dimension randomization; with blue noise every pixel shares it and the mask decorrelates pixels
+    pub fn scramble(&self, dimension: u32) -> u64 {
+        match self.blue_noise {
+            Some(_) => hash(&[self.seed, dimension as u64]),
+            None => hash(&[self.seed, self.x as u64, self.y as u64, dimension as u64]),
+        }
+    }
+
+    fn dither(&self, value: f64, dimension: u32) -> f64 {
+        match self.blue_noise {
+            Some(mask) => {
+                let shifted = value + mask.value(self.x, self.y, dimension);
+                shifted - shifted.floor()
+            }
+            None => value,
+        }
+    }
+}
+
+// a point set evaluated one dimension (or pair of dimensions) at a time
+pub(crate) trait Sequence {
+    fn sample_1d(&self, pixel: &PixelSample, dimension: u32) -> f64;
+    fn sample_2d(&self, pixel: &PixelSample, dimension: u32) -> (f64, f64);
+}
+
+// adapts a sequence to the Sampler interface, handing out dimensions per bounce
+pub(crate) struct LowDiscrepancy<S: Sequence> {
+    sequence: S,
+    pixel: PixelSample,
+    bounce: u32,
+    offset: u32,
+    fallback: Independent,
+}
+
+impl<S: Sequence> LowDiscrepancy<S> {
+    fn new(sequence: S, pixel: PixelSample) -> Self {
+        let fallback = Independent::for_pixel(pixel.clone());
+        Self { sequence, pixel, bounce: 0, offset: 0, fallback }
+    }
+
+    fn claim(&mut self, count: u32) -> Option<u32> {
+        if self.offset + count > DIMENSIONS_PER_BOUNCE {
+            return None;
+        }
+        let dimension = self.bounce * DIMENSIONS_PER_BOUNCE + self.offset;
+        self.offset += count;
+        Some(dimension)
+    }
+}
+
+impl<S: Sequence> Sampler for LowDiscrepancy<S> {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample: u32) {
+        self.pixel.x = x;
+        self.pixel.y = y;
+        self.pixel.index = sample;
+        self.fallback.start_pixel_sample(x, y, sample);
+        self.start_bounce(0);
+    }
+
+    fn start_bounce(&mut self, bounce: u32) {
+        self.bounce = bounce;
+        self.offset = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        match self.claim(1) {
+            Some(dimension) => {
+                let value = self.sequence.sample_1d(&self.pixel, dimension);
+                self.pixel.dither(value, dimension)
+            }
+            None => self.fallback.next_1d(),
+        }
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        // pairs start on even offsets so that 2D requests see a 2D-stratified sequence
+        self.offset += self.offset % 2;
+        match self.claim(2) {
+            Some(dimension) => {
+                let (u, v) = self.sequence.sample_2d(&self.pixel, dimension);
+                (self.pixel.dither(u, dimension), self.pixel.dither(v, dimension + 1))
+            }
+            None => self.fallback.next_2d(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mean squared error of estimating the area under a diagonal edge inside many pixels, i.e.
+    // the integrand behind anti-aliasing, plus a smooth integrand over a later bounce
+    fn estimator_error(kind: SamplerKind, samples_per_pixel: u32) -> (f64, f64) {
+        let mut sampler = kind.create(11, samples_per_pixel, false);
+        let pixels = 256;
+        let (mut edge_error, mut smooth_error) = (0.0, 0.0);
+        for pixel in 0..pixels {
+            let slope = 0.2 + 0.6 * pixel as f64 / pixels as f64;
+            let (mut edge, mut smooth) = (0.0, 0.0);
+            for sample in 0..samples_per_pixel {
+                sampler.start_pixel_sample(pixel, 0, sample);
+                let (u, v) = sampler.next_2d();
+                edge += if v < slope * u { 1.0 } else { 0.0 };
+                sampler.start_bounce(3);
+                let (a, b) = sampler.next_2d();
+                smooth += a * b;
+            }
+            edge_error += (edge / samples_per_pixel as f64 - slope / 2.0).powi(2);
+            smooth_error += (smooth / samples_per_pixel as f64 - 0.25).powi(2);
+        }
+        (edge_error / pixels as f64, smooth_error / pixels as f64)
+    }
+
+    #[test]
+    fn test_low_discrepancy_beats_independent() {
+        let (edge, smooth) = estimator_error(SamplerKind::Independent, 64);
+        for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let (kind_edge, kind_smooth) = estimator_error(kind, 64);
+            assert!(kind_edge < edge / 3.0, "{}: {} vs {}", kind, kind_edge, edge);
+            // Halton's large prime bases at deep bounces only stratify each axis on its own
+            assert!(kind_smooth < smooth / 1.5, "{}: {} vs {}", kind, kind_smooth, smooth);
+        }
+    }
+
+    #[test]
+    fn test_bounces_are_decorrelated() {
+        // the same dimension of consecutive bounces must not move together
+        for kind in SAMPLER_KINDS {
+            let mut sampler = kind.create(5, 64, false);
+            let samples = 4096;
+            let mut covariance = 0.0;
+            for sample in 0..samples {
+                sampler.start_pixel_sample(sample % 64, sample / 64, sample % 64);
+                sampler.start_bounce(1);
+                let first = sampler.next_1d();
+                sampler.start_bounce(2);
+                let second = sampler.next_1d();
+                covariance += (first - 0.5) * (second - 0.5);
+            }
+            assert!((covariance / samples as f64).abs() < 0.01, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_values_are_in_range() {
+        for kind in SAMPLER_KINDS {
+            for blue_noise in [false, true] {
+                let mut sampler = kind.create(1, 16, blue_noise);
+                for sample in 0..16 {
+                    sampler.start_pixel_sample(3, 9, sample);
+                    for bounce in 0..4 {
+                        sampler.start_bounce(bounce);
+                        for _ in 0..6 {
+                            let value = sampler.next_1d();
+                            assert!((0.0..1.0).contains(&value), "{} {}", kind, value);
+                            let (u, v) = sampler.next_2d();
+                            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_kind() {
+        assert_eq!("sobol".parse::<SamplerKind>(), Ok(SamplerKind::Sobol));
+        assert!("white".parse::<SamplerKind>().is_err());
+    }
+}
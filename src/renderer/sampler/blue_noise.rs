@@ -0,0 +1,156 @@
+use std::sync::OnceLock;
+use crate::renderer::sampler::hash;
+use crate::renderer::sampler::independent::Independent;
+use rand::Rng;
+
+const SIZE: usize = 64;
+const SIGMA: f64 = 1.5;
+
+// tileable blue-noise threshold mask built with Ulichney's void-and-cluster method; adding it to
+// every pixel's sample values (modulo 1) pushes the remaining error into high screen-space frequencies
+pub struct BlueNoise {
+    ranks: Vec<f64>,
+}
+
+struct Energy {
+    kernel: Vec<f64>,
+    values: Vec<f64>,
+}
+
+impl Energy {
+    fn new() -> Self {
+        let mut kernel = vec![0.0; SIZE * SIZE];
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                // toroidal distance, so the mask tiles
+                let dx = x.min(SIZE - x) as f64;
+                let dy = y.min(SIZE - y) as f64;
+                kernel[y * SIZE + x] = (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+        Self { kernel, values: vec![0.0; SIZE * SIZE] }
+    }
+
+    fn splat(&mut self, index: usize, sign: f64) {
+        let (px, py) = (index % SIZE, index / SIZE);
+        for y in 0..SIZE {
+            let ky = (y + SIZE - py) % SIZE;
+            for x in 0..SIZE {
+                let kx = (x + SIZE - px) % SIZE;
+                self.values[y * SIZE + x] += sign * self.kernel[ky * SIZE + kx];
+            }
+        }
+    }
+
+    fn tightest_cluster(&self, pattern: &[bool]) -> usize {
+        (0..pattern.len())
+            .filter(|&i| pattern[i])
+            .max_by(|&a, &b| self.values[a].total_cmp(&self.values[b]))
+            .unwrap()
+    }
+
+    fn largest_void(&self, pattern: &[bool]) -> usize {
+        (0..pattern.len())
+            .filter(|&i| !pattern[i])
+            .min_by(|&a, &b| self.values[a].total_cmp(&self.values[b]))
+            .unwrap()
+    }
+}
+
+impl BlueNoise {
+    pub fn shared() -> &'static BlueNoise {
+        static MASK: OnceLock<BlueNoise> = OnceLock::new();
+        MASK.get_or_init(BlueNoise::generate)
+    }
+
+    fn generate() -> Self {
+        let pixels = SIZE * SIZE;
+        let mut rng = Independent::new(0, 0xb1e);
+
+        // initial binary pattern: random minority pixels, relaxed until the tightest cluster
+        // and the largest void coincide
+        let mut pattern = vec![false; pixels];
+        let mut energy = Energy::new();
+        let mut ones = 0;
+        while ones < pixels / 10 {
+            let index = rng.gen_range(0..pixels);
+            if !pattern[index] {
+                pattern[index] = true;
+                energy.splat(index, 1.0);
+                ones += 1;
+            }
+        }
+        loop {
+            let cluster = energy.tightest_cluster(&pattern);
+            pattern[cluster] = false;
+            energy.splat(cluster, -1.0);
+            let void = energy.largest_void(&pattern);
+            pattern[void] = true;
+            energy.splat(void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0usize; pixels];
+
+        // phase 1: rank the initial pattern by repeatedly removing its tightest cluster
+        let mut removal = pattern.clone();
+        let mut removal_energy = Energy { kernel: energy.kernel.clone(), values: energy.values.clone() };
+        for rank in (0..ones).rev() {
+            let cluster = removal_energy.tightest_cluster(&removal);
+            removal[cluster] = false;
+            removal_energy.splat(cluster, -1.0);
+            ranks[cluster] = rank;
+        }
+
+        // phase 2: fill the largest voids until every pixel has a rank
+        for rank in ones..pixels {
+            let void = energy.largest_void(&pattern);
+            pattern[void] = true;
+            energy.splat(void, 1.0);
+            ranks[void] = rank;
+        }
+
+        Self {
+            ranks: ranks.iter().map(|&rank| (rank as f64 + 0.5) / pixels as f64).collect(),
+        }
+    }
+
+    // each dimension reads the mask at its own toroidal offset, so dimensions stay decorrelated
+    pub fn value(&self, x: u32, y: u32, dimension: u32) -> f64 {
+        let offset = hash(&[dimension as u64]);
+        let x = (x as usize + (offset as usize % SIZE)) % SIZE;
+        let y = (y as usize + ((offset >> 32) as usize % SIZE)) % SIZE;
+        self.ranks[y * SIZE + x]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_is_a_permutation() {
+        let mask = BlueNoise::shared();
+        let mut ranks: Vec<usize> = mask.ranks.iter().map(|value| (value * (SIZE * SIZE) as f64) as usize).collect();
+        ranks.sort();
+        assert!(ranks.iter().enumerate().all(|(i, &rank)| i == rank));
+    }
+
+    #[test]
+    fn test_mask_has_no_low_frequencies() {
+        // neighbouring thresholds of white noise differ by 1/3 on average, blue noise avoids
+        // putting similar values next to each other
+        let mask = BlueNoise::shared();
+        let mut difference = 0.0;
+        for y in 0..SIZE as u32 {
+            for x in 0..SIZE as u32 {
+                difference += (mask.value(x, y, 0) - mask.value(x + 1, y, 0)).abs();
+                difference += (mask.value(x, y, 0) - mask.value(x, y + 1, 0)).abs();
+            }
+        }
+        let mean = difference / (2 * SIZE * SIZE) as f64;
+        assert!(mean > 0.4, "{}", mean);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::renderer::sampler::{hash, permute, to_unit, PixelSample, Sequence};
+
+// dimensions past this many primes are drawn independently
+const MAX_DIMENSIONS: usize = 256;
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Halton sequence with random digit permutations: every digit of the radical inverse goes through its
+// own hashed permutation, which breaks up the correlation between the higher prime bases
+pub(crate) struct Halton {
+    primes: Vec<u64>,
+}
+
+impl Halton {
+    pub fn new() -> Self {
+        let mut primes = Vec::with_capacity(MAX_DIMENSIONS);
+        let mut candidate = 2;
+        while primes.len() < MAX_DIMENSIONS {
+            if primes.iter().take_while(|&&p| p * p <= candidate).all(|&p| candidate % p != 0) {
+                primes.push(candidate);
+            }
+            candidate += 1;
+        }
+        Self { primes }
+    }
+
+    fn radical_inverse(&self, pixel: &PixelSample, dimension: u32) -> f64 {
+        let scramble = pixel.scramble(dimension);
+        let Some(&base) = self.primes.get(dimension as usize) else {
+            return to_unit(hash(&[scramble, pixel.index as u64]));
+        };
+        let inverse_base = 1.0 / base as f64;
+        let mut index = pixel.index as u64;
+        let mut factor = inverse_base;
+        let mut result = 0.0;
+        let mut digit_position = 0;
+        // leading zero digits are permuted too, down to double precision, so that every sample
+        // of the pixel sees the same scramble
+        while index > 0 || factor > f64::EPSILON {
+            let digit = permute((index % base) as u32, base as u32, hash(&[scramble, digit_position]) as u32);
+            result += digit as f64 * factor;
+            index /= base;
+            factor *= inverse_base;
+            digit_position += 1;
+        }
+        result.min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sequence for Halton {
+    fn sample_1d(&self, pixel: &PixelSample, dimension: u32) -> f64 {
+        self.radical_inverse(pixel, dimension)
+    }
+
+    fn sample_2d(&self, pixel: &PixelSample, dimension: u32) -> (f64, f64) {
+        (self.radical_inverse(pixel, dimension), self.radical_inverse(pixel, dimension + 1))
+    }
+}
@@ -0,0 +1,102 @@
+use rand::{Error, RngCore};
+use crate::renderer::sampler::{mix, to_unit, PixelSample, Sampler};
+
+// small, splittable random number generator; every pixel sample gets its own stream, derived only from
+// the seed, the pixel and the sample index, so images don't depend on thread count or scheduling
+#[derive(Debug, Clone)]
+pub struct Independent {
+    seed: u64,
+    state: u64,
+}
+
+impl Independent {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        Self {
+            seed,
+            state: mix(mix(seed) ^ stream),
+        }
+    }
+
+    pub fn for_pixel_sample(seed: u64, x: u32, y: u32, sample: u32) -> Self {
+        let mut independent = Self { seed, state: 0 };
+        independent.start_pixel_sample(x, y, sample);
+        independent
+    }
+
+    pub(crate) fn for_pixel(pixel: PixelSample) -> Self {
+        Self::for_pixel_sample(pixel.seed, pixel.x, pixel.y, pixel.index)
+    }
+}
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample: u32) {
+        let pixel = (x as u64) << 32 | y as u64;
+        self.state = mix(mix(mix(self.seed) ^ pixel) ^ sample as u64);
+    }
+
+    fn start_bounce(&mut self, _: u32) {}
+
+    fn next_1d(&mut self) -> f64 {
+        to_unit(self.next_u64())
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+impl RngCore for Independent {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+
+    fn fill_bytes(&mut self, destination: &mut [u8]) {
+        for chunk in destination.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, destination: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(destination);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::renderer::sampler::SCENE_STREAM;
+
+    #[test]
+    fn test_streams_are_reproducible() {
+        let mut first = Independent::for_pixel_sample(7, 3, 4, 5);
+        let mut second = Independent::for_pixel_sample(7, 3, 4, 5);
+        for _ in 0..100 {
+            assert_eq!(first.next_u64(), second.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_streams_are_distinct() {
+        let draw = |mut sampler: Independent| sampler.gen::<f64>();
+        let reference = draw(Independent::for_pixel_sample(7, 3, 4, 5));
+        assert_ne!(reference, draw(Independent::for_pixel_sample(8, 3, 4, 5)));
+        assert_ne!(reference, draw(Independent::for_pixel_sample(7, 4, 3, 5)));
+        assert_ne!(reference, draw(Independent::for_pixel_sample(7, 3, 4, 6)));
+        assert_ne!(reference, draw(Independent::new(7, SCENE_STREAM)));
+    }
+
+    #[test]
+    fn test_uniform_mean() {
+        let mut sampler = Independent::new(1, 2);
+        let mean: f64 = (0..100000).map(|_| sampler.next_1d()).sum::<f64>() / 100000.0;
+        assert!((mean - 0.5).abs() < 0.005);
+    }
+}
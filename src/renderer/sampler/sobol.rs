@@ -0,0 +1,82 @@
+use crate::renderer::sampler::{hash, PixelSample, Sequence};
+
+// Owen-scrambled Sobol after Burley, "Practical Hash-based Owen Scrambling" (2020): every pair of
+// dimensions is the first two Sobol dimensions, padded with an independent shuffle of the sample index
+pub(crate) struct Sobol;
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn sobol(index: u32, dimension: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    for bit in 0..32 {
+        if (index >> bit) & 1 == 1 {
+            result ^= direction;
+        }
+        direction = match dimension {
+            0 => direction >> 1,
+            _ => direction ^ (direction >> 1),
+        };
+    }
+    result
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+impl Sobol {
+    fn sample(pixel: &PixelSample, dimension: u32, components: u32) -> [f64; 2] {
+        let scramble = pixel.scramble(dimension);
+        let index = nested_uniform_scramble(pixel.index, scramble as u32);
+        let mut values = [0.0; 2];
+        for (component, value) in values.iter_mut().enumerate().take(components as usize) {
+            let seed = hash(&[scramble, component as u64]) as u32;
+            *value = to_unit(nested_uniform_scramble(sobol(index, component as u32), seed));
+        }
+        values
+    }
+}
+
+impl Sequence for Sobol {
+    fn sample_1d(&self, pixel: &PixelSample, dimension: u32) -> f64 {
+        Self::sample(pixel, dimension, 1)[0]
+    }
+
+    fn sample_2d(&self, pixel: &PixelSample, dimension: u32) -> (f64, f64) {
+        let [u, v] = Self::sample(pixel, dimension, 2);
+        (u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sobol_is_stratified() {
+        // the first 2^k points fall into distinct elementary intervals of every shape 2^i x 2^(k-i)
+        let k = 6;
+        for i in 0..=k {
+            let mut seen = vec![false; 1 << k];
+            for index in 0..1u32 << k {
+                let x = sobol(index, 0).checked_shr(32 - i).unwrap_or(0);
+                let y = sobol(index, 1).checked_shr(32 - (k - i)).unwrap_or(0);
+                let cell = (x << (k - i) | y) as usize;
+                assert!(!seen[cell]);
+                seen[cell] = true;
+            }
+        }
+    }
+}
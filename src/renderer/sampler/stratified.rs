@@ -0,0 +1,34 @@
+use crate::renderer::sampler::{hash, permute, to_unit, PixelSample, Sequence};
+
+// jittered strata: the pixel's samples are spread over a shuffled grid of cells (n cells in 1D,
+// roughly sqrt(n) x sqrt(n) in 2D) and jittered inside them; once a pixel takes more samples than
+// it planned for, the next round gets a fresh shuffle
+pub(crate) struct Stratified;
+
+impl Stratified {
+    fn stratum(pixel: &PixelSample, dimension: u32, cells: u32) -> (u32, u64) {
+        let round = pixel.index / cells;
+        let scramble = hash(&[pixel.scramble(dimension), round as u64]);
+        (permute(pixel.index % cells, cells, scramble as u32), scramble)
+    }
+}
+
+impl Sequence for Stratified {
+    fn sample_1d(&self, pixel: &PixelSample, dimension: u32) -> f64 {
+        let (stratum, scramble) = Self::stratum(pixel, dimension, pixel.count);
+        let jitter = to_unit(hash(&[scramble, pixel.index as u64]));
+        (stratum as f64 + jitter) / pixel.count as f64
+    }
+
+    fn sample_2d(&self, pixel: &PixelSample, dimension: u32) -> (f64, f64) {
+        let columns = (pixel.count as f64).sqrt().ceil() as u32;
+        let rows = pixel.count.div_ceil(columns);
+        let (stratum, scramble) = Self::stratum(pixel, dimension, columns * rows);
+        let jitter_u = to_unit(hash(&[scramble, pixel.index as u64, 0]));
+        let jitter_v = to_unit(hash(&[scramble, pixel.index as u64, 1]));
+        (
+            ((stratum % columns) as f64 + jitter_u) / columns as f64,
+            ((stratum / columns) as f64 + jitter_v) / rows as f64,
+        )
+    }
+}
@@ -0,0 +1,602 @@
+use std::io;
+use std::path::Path;
+use std::fs;
+use std::sync::Arc;
+use rand::Rng;
+
+use crate::renderer::animation::Animation;
+use crate::renderer::camera::{CameraPose, Shutter};
+use crate::renderer::color_space::ImageTag;
+use crate::renderer::constant_medium::ConstantMedium;
+use crate::renderer::heterogeneous_medium::HeterogeneousMedium;
+use crate::renderer::material::{Material, metal::Metal};
+use crate::renderer::material::bump_map::BumpMap;
+use crate::renderer::material::diffuse_light::DiffuseLight;
+use crate::renderer::material::henyey_greenstein::{Blackbody, HenyeyGreenstein};
+use crate::renderer::material::conductor::Conductor;
+use crate::renderer::material::dispersion::Ior;
+use crate::renderer::material::lambert::Lambert;
+use crate::renderer::material::normal_map::NormalMap;
+use crate::renderer::material::principled::{GltfMaterial, Principled, Scalar, Tinted};
+use crate::renderer::material::rough_dielectric::RoughDielectric;
+use crate::renderer::motion::{Interpolate, Interpolation, Key, Keyframes, Transform, Transformed};
+use crate::renderer::polyhedron::ConvexPolyhedron;
+use crate::renderer::sampler::independent::Independent;
+use crate::renderer::sampler::SCENE_STREAM;
+use crate::renderer::sphere::{MovingSphere, Sphere};
+use crate::renderer::texture::image::ImageTexture;
+use crate::renderer::tone_mapping::ToneMapping;
+use crate::renderer::vector3d::{Color, Point, Vector3D};
+use crate::renderer::voxel_grid::VoxelGrid;
+use crate::renderer::world::HittableList;
+
+pub const SCENE_NAMES: [&str; 9] = ["spheres", "surface_detail", "materials", "principled_sweep", "volumes", "explosion", "prism", "motion", "turntable"];
+
+// albedo of the haze added by --atmosphere, and how far from the origin it reaches
+const HAZE_ALBEDO: Color = Color { x: 0.85, y: 0.88, z: 0.92 };
+const HAZE_EXTENT: f64 = 150.0;
+
+pub fn by_name(name: &str, seed: u64) -> io::Result<Animation> {
+    let world = match name {
+        "spheres" => spheres(seed),
+        "surface_detail" => surface_detail()?,
+        "materials" => materials(),
+        "principled_sweep" => principled_sweep()?,
+        "volumes" => volumes(),
+        "explosion" => explosion()?,
+        "prism" => prism(),
+        "motion" => motion(),
+        "turntable" => return Ok(turntable()),
+        _ => return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown scene '{}', expected one of {:?}", name, SCENE_NAMES),
+        )),
+    };
+    Ok(Animation::still(world))
+}
+
+// how each scene is meant to be viewed, from scenes/<name>.view where there is one;
+// --exposure, --tone-map and --white-point override it
+pub fn tone_mapping(name: &str) -> io::Result<ToneMapping> {
+    match fs::read_to_string(Path::new("scenes").join(format!("{}.view", name))) {
+        Ok(source) => ToneMapping::from_settings(&source),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(ToneMapping::default()),
+        Err(error) => Err(error),
+    }
+}
+
+// when each scene's shutter is open within a frame, in frames; --shutter overrides it
+pub fn shutter(name: &str) -> Shutter {
+    match name {
+        // half a frame, as a 180 degree film shutter
+        "motion" => Shutter { open: 0.0, close: 0.5 },
+        _ => Shutter::default(),
+    }
+}
+
+pub fn add_atmosphere(world: &mut HittableList, density: f64) {
+    world.add(Box::new(ConstantMedium::atmosphere(density, HAZE_ALBEDO, HAZE_EXTENT)));
+}
+
+// places a voxel grid in a box in front of the camera, glowing where it carries temperatures
+pub fn add_volume(world: &mut HittableList, grid: VoxelGrid, min: Point, max: Point, density_scale: f64) {
+    let grid = Arc::new(grid);
+    let emission = if grid.has_temperature() {
+        Some(Blackbody { grid: grid.clone(), min, extent: max - min, scale: 2000.0 })
+    } else {
+        None
+    };
+    let albedo = if emission.is_some() { 0.6 } else { 0.9 };
+    world.add(Box::new(HeterogeneousMedium {
+        grid,
+        min,
+        max,
+        density_scale,
+        phase_function: Arc::new(HenyeyGreenstein {
+            albedo: Color { x: albedo, y: albedo, z: albedo },
+            g: 0.4,
+            emission,
+        }),
+    }));
+}
+
+fn ground() -> Sphere {
+    Sphere {
+        center: Point { x: 0.0, y: -101.0, z: 0.0 },
+        radius: 100.0,
+        material: Arc::new(Lambert { albedo: Color { x: 0.0, y: 0.7, z: 0.0 } }),
+    }
+}
+
+pub fn spheres(seed: u64) -> HittableList {
+    let mut world = HittableList::new();
+
+    let mut rng = Independent::new(seed, SCENE_STREAM);
+    for x in 1..10 {
+        for z in 1..5 {
+            let choose_mat: Arc<dyn Material> = match rng.gen_range(1..4) {
+                1 => Arc::new(Metal { albedo: Color { x: 0.05, y: 0.05, z: 0.05 }, roughness: 0.0 }),
+                2 => Arc::new(Metal { albedo: Color { x: 0.7, y: 0.7, z: 0.7 }, roughness: 0.0 }),
+                _ => Arc::new(Metal { albedo: Color { x: 0.0, y: 0.0, z: 0.33 }, roughness: 0.0 }),
+            };
+            let coord_x = -3.0 + (0.6 * x as f64);
+            let coord_z = -1.2 - (0.5 * z as f64);
+            world.add(Box::new(Sphere {
+                center: Point { x: coord_x, y: -0.75, z: coord_z },
+                radius: 0.25,
+                material: choose_mat,
+            }));
+        }
+    }
+    world.add(Box::new(ground()));
+    world
+}
+
+// the same tile pattern as a plain, a normal mapped and a bump mapped sphere
+pub fn surface_detail() -> io::Result<HittableList> {
+    let normals = Arc::new(ImageTexture::from_ppm("textures/tiles_normal.ppm", ImageTag::Data)?);
+    let heights = Arc::new(ImageTexture::from_ppm("textures/tiles_height.ppm", ImageTag::Data)?);
+    let clay: Arc<dyn Material> = Arc::new(Lambert { albedo: Color { x: 0.7, y: 0.35, z: 0.2 } });
+    let steel: Arc<dyn Material> = Arc::new(Metal { albedo: Color { x: 0.8, y: 0.8, z: 0.8 }, roughness: 0.0 });
+
+    let mut world = HittableList::new();
+    world.add(Box::new(Sphere {
+        center: Point { x: -1.1, y: -0.5, z: -2.0 },
+        radius: 0.5,
+        material: clay.clone(),
+    }));
+    world.add(Box::new(Sphere {
+        center: Point { x: 0.0, y: -0.5, z: -2.0 },
+        radius: 0.5,
+        material: Arc::new(NormalMap { map: normals, strength: 1.0, material: clay }),
+    }));
+    world.add(Box::new(Sphere {
+        center: Point { x: 1.1, y: -0.5, z: -2.0 },
+        radius: 0.5,
+        material: Arc::new(BumpMap { height: heights, scale: 0.01, material: steel }),
+    }));
+    world.add(Box::new(ground()));
+    Ok(world)
+}
+
+// front row: gold, copper, aluminium and silver from smooth to rough; back row: glass from clear to frosted
+pub fn materials() -> HittableList {
+    let mut world = HittableList::new();
+    let roughness = [0.05, 0.25, 0.45, 0.7];
+    let conductors = [Conductor::gold, Conductor::copper, Conductor::aluminium, Conductor::silver];
+    for (i, conductor) in conductors.iter().enumerate() {
+        world.add(Box::new(Sphere {
+            center: Point { x: -1.5 + i as f64, y: -0.6, z: -2.0 },
+            radius: 0.4,
+            material: Arc::new(conductor(roughness[i])),
+        }));
+        world.add(Box::new(Sphere {
+            center: Point { x: -1.5 + i as f64, y: -0.6, z: -3.2 },
+            radius: 0.4,
+            material: Arc::new(RoughDielectric {
+                ior: Ior::Constant(1.5),
+                roughness: roughness[i],
+                tint: Color { x: 0.95, y: 0.97, z: 1.0 },
+            }),
+        }));
+    }
+    world.add(Box::new(ground()));
+    world
+}
+
+// one row per Principled parameter swept from 0 to 1 left to right, then a row of MTL and glTF materials
+pub fn principled_sweep() -> io::Result<HittableList> {
+    let columns = 5;
+    let sweep = |column: usize| column as f64 / (columns - 1) as f64;
+    let gold = Color { x: 1.0, y: 0.78, z: 0.34 };
+    let rows: Vec<Box<dyn Fn(f64) -> Principled>> = vec![
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(gold),
+            metallic: Scalar::constant(t),
+            roughness: Scalar::constant(0.3),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(gold),
+            metallic: Scalar::constant(1.0),
+            roughness: Scalar::constant(t),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.1, y: 0.25, z: 0.6 }),
+            roughness: Scalar::constant(t),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.6, y: 0.05, z: 0.05 }),
+            roughness: Scalar::constant(0.6),
+            clearcoat: Scalar::constant(t),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.05, y: 0.05, z: 0.3 }),
+            roughness: Scalar::constant(0.8),
+            sheen: Tinted::constant(Color { x: t, y: t, z: t }),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.9, y: 0.95, z: 1.0 }),
+            roughness: Scalar::constant(0.1),
+            transmission: Scalar::constant(t),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.8, y: 0.8, z: 0.8 }),
+            metallic: Scalar::constant(1.0),
+            roughness: Scalar::constant(0.4),
+            anisotropy: Scalar::constant(t),
+            ..Principled::default()
+        }),
+        Box::new(|t| Principled {
+            base_color: Tinted::constant(Color { x: 0.9, y: 0.6, z: 0.5 }),
+            roughness: Scalar::constant(0.5),
+            subsurface: Scalar::constant(t),
+            ..Principled::default()
+        }),
+    ];
+
+    let mut world = HittableList::new();
+    let mut place = |row: usize, column: usize, material: Arc<dyn Material>| {
+        world.add(Box::new(Sphere {
+            center: Point { x: -2.0 + column as f64, y: 4.0 - row as f64, z: -5.5 },
+            radius: 0.4,
+            material,
+        }));
+    };
+    for (row, material) in rows.iter().enumerate() {
+        for column in 0..columns {
+            place(row, column, Arc::new(material(sweep(column))));
+        }
+    }
+
+    let directory = Path::new("materials");
+    let mtl = Principled::from_mtl(&fs::read_to_string(directory.join("sweep.mtl"))?, directory)?;
+    let last = rows.len();
+    for (column, name) in ["chrome", "car_paint", "tiled_steel"].iter().enumerate() {
+        let material = mtl.get(*name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("sweep.mtl has no material '{}'", name))
+        })?;
+        place(last, column, Arc::new(material.clone()));
+    }
+    let gltf = GltfMaterial::parse(&fs::read_to_string(directory.join("sweep.gltf"))?, directory)?;
+    for (column, name) in [(3, "velvet"), (4, "default")] {
+        let material = gltf.get(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("sweep.gltf has no material '{}'", name))
+        })?;
+        place(last, column, Arc::new(Principled::from_gltf(material)));
+    }
+    Ok(world)
+}
+
+// an equilateral BK7 prism and balls of the other glass presets over a striped floor; with --spectral
+// the stripes seen through the glass split into rainbows
+pub fn prism() -> HittableList {
+    let mut world = HittableList::new();
+    let white: Arc<dyn Material> = Arc::new(Lambert { albedo: Color { x: 0.8, y: 0.8, z: 0.8 } });
+    let black: Arc<dyn Material> = Arc::new(Lambert { albedo: Color { x: 0.02, y: 0.02, z: 0.02 } });
+    for stripe in 0..60 {
+        let near = -0.5 - 0.15 * stripe as f64;
+        world.add(Box::new(ConvexPolyhedron::cuboid(
+            Point { x: -8.0, y: -1.1, z: near - 0.15 },
+            Point { x: 8.0, y: -1.0, z: near },
+            if stripe % 2 == 0 { white.clone() } else { black.clone() },
+        )));
+    }
+
+    // lying on its side with the ridge up, it bends what is behind it down onto the stripes
+    let glass = |ior| Arc::new(RoughDielectric { ior, roughness: 0.0, tint: Color { x: 1.0, y: 1.0, z: 1.0 } });
+    let side = 0.9;
+    let base = [
+        Point { x: -1.2, y: -0.35, z: -2.6 + side / 2.0 },
+        Point { x: -1.2, y: -0.35, z: -2.6 - side / 2.0 },
+        Point { x: -1.2, y: -0.35 + side * 3f64.sqrt() / 2.0, z: -2.6 },
+    ];
+    world.add(Box::new(ConvexPolyhedron::prism(base, Vector3D { x: 2.4, y: 0.0, z: 0.0 }, glass(Ior::BK7))));
+    for (i, ior) in [Ior::DIAMOND, Ior::FUSED_SILICA, Ior::WATER].into_iter().enumerate() {
+        world.add(Box::new(Sphere {
+            center: Point { x: -0.8 + 0.8 * i as f64, y: -0.75, z: -1.5 },
+            radius: 0.25,
+            material: glass(ior),
+        }));
+    }
+    world.add(Box::new(Sphere {
+        center: Point { x: 0.0, y: -1001.05, z: 0.0 },
+        radius: 1000.0,
+        material: Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } }),
+    }));
+    world
+}
+
+// a wheel of the given radius in the z = 0 plane around the origin: a tire of straight segments
+// and five spokes, so that its spin shows
+fn wheel(radius: f64) -> HittableList {
+    let mut wheel = HittableList::new();
+    let turned = |object: ConvexPolyhedron, degrees: f64| {
+        let rotation = Vector3D { x: 0.0, y: 0.0, z: degrees };
+        Box::new(Transformed::new(Box::new(object), Keyframes::constant(Transform { rotation, ..Transform::default() })))
+    };
+    let tire: Arc<dyn Material> = Arc::new(Lambert { albedo: Color { x: 0.03, y: 0.03, z: 0.03 } });
+    let segments = 16;
+    let half_width = radius * (std::f64::consts::PI / segments as f64).tan();
+    for segment in 0..segments {
+        let tread = ConvexPolyhedron::cuboid(
+            Point { x: -half_width, y: 0.75 * radius, z: -0.08 },
+            Point { x: half_width, y: radius, z: 0.08 },
+            tire.clone(),
+        );
+        wheel.add(turned(tread, 360.0 * segment as f64 / segments as f64));
+    }
+    let chrome: Arc<dyn Material> = Arc::new(Metal { albedo: Color { x: 0.8, y: 0.8, z: 0.8 }, roughness: 0.2 });
+    for spoke in 0..5 {
+        let bar = ConvexPolyhedron::cuboid(
+            Point { x: -0.025, y: 0.0, z: -0.03 },
+            Point { x: 0.025, y: 0.75 * radius, z: 0.03 },
+            chrome.clone(),
+        );
+        wheel.add(turned(bar, 72.0 * spoke as f64));
+    }
+    wheel.add(Box::new(Sphere { center: Point::default(), radius: 0.06, material: chrome }));
+    wheel
+}
+
+// a car seen from the side by a camera panning along with it: its wheels spin, the posts behind it
+// sweep by and a ball bounces in the distance. The car covers a metre a frame at 24 frames a second
+pub fn motion() -> HittableList {
+    let frame = 1.0 / 24.0;
+    let mut world = HittableList::new();
+    let paint: Arc<dyn Material> = Arc::new(Metal { albedo: Color { x: 0.7, y: 0.05, z: 0.05 }, roughness: 0.3 });
+    world.add(Box::new(ConvexPolyhedron::cuboid(
+        Point { x: -1.3, y: -0.75, z: -2.9 },
+        Point { x: 1.3, y: -0.35, z: -2.4 },
+        paint.clone(),
+    )));
+    world.add(Box::new(ConvexPolyhedron::cuboid(
+        Point { x: -0.7, y: -0.35, z: -2.85 },
+        Point { x: 0.6, y: -0.05, z: -2.45 },
+        paint,
+    )));
+
+    // a wheel of radius r rolling a metre turns by 1 / r radians
+    let radius: f64 = 0.28;
+    let roll = -(1.0 / radius).to_degrees();
+    for x in [-0.85, 0.85] {
+        let translation = Vector3D { x, y: -1.0 + radius, z: -2.3 };
+        let spin = Keyframes::linear(
+            (0.0, Transform { translation, ..Transform::default() }),
+            (frame, Transform { translation, rotation: Vector3D { x: 0.0, y: 0.0, z: roll }, scale: 1.0 }),
+        );
+        world.add(Box::new(Transformed::new(Box::new(wheel(radius)), spin)));
+    }
+
+    let concrete: Arc<dyn Material> = Arc::new(Lambert { albedo: Color { x: 0.6, y: 0.6, z: 0.55 } });
+    for post in -5..=5 {
+        let x = 1.5 * post as f64;
+        let slide = Keyframes::linear(
+            (0.0, Transform { translation: Vector3D { x, y: 0.0, z: -8.0 }, ..Transform::default() }),
+            (frame, Transform { translation: Vector3D { x: x - 1.0, y: 0.0, z: -8.0 }, ..Transform::default() }),
+        );
+        let pillar = ConvexPolyhedron::cuboid(
+            Point { x: -0.1, y: -1.0, z: -0.1 },
+            Point { x: 0.1, y: 1.5, z: 0.1 },
+            concrete.clone(),
+        );
+        world.add(Box::new(Transformed::new(Box::new(pillar), slide)));
+    }
+
+    // rolling back relative to the camera while it bounces
+    let bounce = (0..=4)
+        .map(|key| {
+            let fraction = 0.25 * key as f64;
+            let height = if key % 2 == 0 { -0.7 } else { 0.3 };
+            (fraction * frame, Point { x: 2.5 - 0.6 * fraction, y: height, z: -5.0 })
+        })
+        .collect();
+    world.add(Box::new(MovingSphere {
+        center: Keyframes::new(bounce),
+        radius: 0.3,
+        material: Arc::new(Lambert { albedo: Color { x: 0.9, y: 0.7, z: 0.1 } }),
+    }));
+
+    world.add(Box::new(Sphere {
+        center: Point { x: 0.0, y: -1001.0, z: 0.0 },
+        radius: 1000.0,
+        material: Arc::new(Lambert { albedo: Color { x: 0.35, y: 0.35, z: 0.35 } }),
+    }));
+    world
+}
+
+// eased in and out of every key
+fn eased<T: Interpolate>(keys: Vec<(f64, T)>) -> Keyframes<T> {
+    Keyframes::with_keys(keys.into_iter().map(|(time, value)| Key::new(time, value, Interpolation::EASE)).collect())
+}
+
+// four seconds of a turntable: the pieces on the plinth turn once while the camera swings round and
+// back, the sphere goes from polished to satin and back, the key light fades up and a beacon blinks
+pub fn turntable() -> Animation {
+    let mut still = HittableList::new();
+    still.add(Box::new(ConvexPolyhedron::cuboid(
+        Point { x: -0.9, y: -1.0, z: -0.9 },
+        Point { x: 0.9, y: -0.7, z: 0.9 },
+        Arc::new(Lambert { albedo: Color { x: 0.3, y: 0.3, z: 0.32 } }),
+    )));
+    still.add(Box::new(Sphere {
+        center: Point { x: 0.0, y: -1001.0, z: 0.0 },
+        radius: 1000.0,
+        material: Arc::new(Lambert { albedo: Color { x: 0.5, y: 0.5, z: 0.5 } }),
+    }));
+
+    let top = Vector3D { x: 0.0, y: -0.7, z: 0.0 };
+    let spin = Keyframes::linear(
+        (0.0, Transform { translation: top, ..Transform::default() }),
+        (4.0, Transform { translation: top, rotation: Vector3D { x: 0.0, y: 360.0, z: 0.0 }, scale: 1.0 }),
+    );
+    let roughness = eased(vec![(0.0, 0.05), (2.0, 0.6), (4.0, 0.05)]);
+    let key_light = eased(vec![(0.0, 0.0), (1.0, 6.0)]);
+    // on for the first half of every second
+    let beacon = Keyframes::with_keys(
+        (0..8).map(|half| Key::new(0.5 * half as f64, if half % 2 == 0 { 30.0 } else { 0.0 }, Interpolation::Step)).collect(),
+    );
+    let animated = move |time: f64| {
+        let mut pieces = HittableList::new();
+        pieces.add(Box::new(Sphere {
+            center: Point { x: 0.35, y: 0.35, z: 0.0 },
+            radius: 0.35,
+            material: Arc::new(Principled {
+                base_color: Tinted::constant(Color { x: 0.8, y: 0.15, z: 0.1 }),
+                roughness: Scalar::constant(roughness.at(time)),
+                ..Principled::default()
+            }),
+        }));
+        pieces.add(Box::new(ConvexPolyhedron::cuboid(
+            Point { x: -0.65, y: 0.0, z: -0.25 },
+            Point { x: -0.15, y: 0.5, z: 0.25 },
+            Arc::new(Metal { albedo: Color { x: 0.9, y: 0.7, z: 0.3 }, roughness: 0.1 }),
+        )));
+        let mut world = HittableList::new();
+        world.add(Box::new(Transformed::new(Box::new(pieces), spin.clone())));
+        world.add(Box::new(Sphere {
+            center: Point { x: 2.0, y: 2.5, z: 2.0 },
+            radius: 0.4,
+            material: Arc::new(DiffuseLight { color: Color { x: 1.0, y: 0.85, z: 0.7 }, intensity: key_light.at(time) }),
+        }));
+        world.add(Box::new(Sphere {
+            center: Point { x: -1.6, y: -0.85, z: -1.2 },
+            radius: 0.12,
+            material: Arc::new(DiffuseLight { color: Color { x: 1.0, y: 0.1, z: 0.05 }, intensity: beacon.at(time) }),
+        }));
+        world
+    };
+
+    let front = CameraPose {
+        look_from: Point { x: 0.0, y: 0.2, z: 4.0 },
+        look_at: Point { x: 0.0, y: -0.3, z: 0.0 },
+        vertical_fov: 40.0,
+        ..CameraPose::default()
+    };
+    let high = CameraPose { look_from: Point { x: 3.2, y: 1.4, z: 2.4 }, vertical_fov: 32.0, ..front };
+    Animation { still, animated: Some(Box::new(animated)), camera: eased(vec![(0.0, front), (2.0, high), (4.0, front)]) }
+}
+
+// dark smoke, white fog and a mirror ball standing in light haze
+pub fn volumes() -> HittableList {
+    let mut world = HittableList::new();
+    let ball = |x: f64| Sphere {
+        center: Point { x, y: -0.5, z: -2.5 },
+        radius: 0.5,
+        material: Arc::new(Lambert::default()),
+    };
+    world.add(Box::new(ConstantMedium::new(Box::new(ball(-1.1)), 4.0, Color { x: 0.2, y: 0.2, z: 0.2 })));
+    world.add(Box::new(ConstantMedium::new(Box::new(ball(0.0)), 2.0, Color { x: 0.95, y: 0.95, z: 0.95 })));
+    world.add(Box::new(Sphere {
+        material: Arc::new(Metal { albedo: Color { x: 0.8, y: 0.8, z: 0.8 }, roughness: 0.0 }),
+        ..ball(1.1)
+    }));
+    world.add(Box::new(ground()));
+    add_atmosphere(&mut world, 0.005);
+    world
+}
+
+// smooth pseudo-random noise in [-1, 1] from hashed lattice values
+fn value_noise(point: Point) -> f64 {
+    let hash = |x: i64, y: i64, z: i64| {
+        let mut h = (x.wrapping_mul(73856093) ^ y.wrapping_mul(19349663) ^ z.wrapping_mul(83492791)) as u64;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51afd7ed558ccd);
+        h ^= h >> 33;
+        (h & 0xffff) as f64 / 32767.5 - 1.0
+    };
+    let (x0, y0, z0) = (point.x.floor(), point.y.floor(), point.z.floor());
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let (tx, ty, tz) = (smooth(point.x - x0), smooth(point.y - y0), smooth(point.z - z0));
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let (x, y, z) = (x0 as i64, y0 as i64, z0 as i64);
+    lerp(
+        lerp(lerp(hash(x, y, z), hash(x + 1, y, z), tx), lerp(hash(x, y + 1, z), hash(x + 1, y + 1, z), tx), ty),
+        lerp(lerp(hash(x, y, z + 1), hash(x + 1, y, z + 1), tx), lerp(hash(x, y + 1, z + 1), hash(x + 1, y + 1, z + 1), tx), ty),
+        tz,
+    )
+}
+
+// a billowing fireball with a hot core, as a density and temperature grid
+fn fireball(resolution: usize) -> io::Result<VoxelGrid> {
+    let mut data = Vec::with_capacity(resolution * resolution * resolution * 2);
+    for z in 0..resolution {
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let coordinate = |i: usize| 2.0 * (i as f64 + 0.5) / resolution as f64 - 1.0;
+                let point = Point { x: coordinate(x), y: coordinate(y), z: coordinate(z) };
+                let noise = value_noise(4.0 * point) + 0.5 * value_noise(8.0 * point);
+                let radius = point.length() + 0.2 * noise;
+                let density = ((0.8 - radius) / 0.2).clamp(0.0, 1.0);
+                let heat = (1.0 - radius / 0.75).clamp(0.0, 1.0);
+                data.push(density);
+                data.push(800.0 + 2000.0 * heat);
+            }
+        }
+    }
+    VoxelGrid::new([resolution; 3], 2, data)
+}
+
+pub fn explosion() -> io::Result<HittableList> {
+    let mut world = HittableList::new();
+    add_volume(
+        &mut world,
+        fireball(48)?,
+        Point { x: -0.9, y: -1.0, z: -3.4 },
+        Point { x: 0.9, y: 0.8, z: -1.6 },
+        12.0,
+    );
+    world.add(Box::new(Sphere {
+        center: Point { x: 1.6, y: -0.6, z: -2.8 },
+        radius: 0.4,
+        material: Arc::new(Metal { albedo: Color { x: 0.8, y: 0.8, z: 0.8 }, roughness: 0.0 }),
+    }));
+    world.add(Box::new(ground()));
+    Ok(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::sampler::independent::Independent;
+    use crate::renderer::color::{ray_color, Rgb};
+    use crate::renderer::RenderSettings;
+    use crate::renderer::color_space::{ColorSpace, Conversion};
+    use crate::renderer::ray::Ray;
+    use crate::renderer::tone_mapping::ToneMapper;
+    use crate::renderer::vector3d::{unit_vector, Vector3D};
+
+    #[test]
+    fn test_scenes_read_their_view_settings() {
+        let explosion = tone_mapping("explosion").unwrap();
+        assert_eq!((explosion.mapper, explosion.exposure), (ToneMapper::Agx, 1.0));
+        assert_eq!(tone_mapping("spheres").unwrap(), ToneMapping::default());
+    }
+
+    #[test]
+    fn test_principled_sweep_is_finite() {
+        let world = principled_sweep().unwrap();
+        for row in 0..9 {
+            for column in 0..5 {
+                let center = Point { x: -2.0 + column as f64, y: 4.0 - row as f64, z: -5.5 };
+                // aim at the middle and at the rim of every sphere, where grazing angles stress the lobes
+                for offset in [0.0, 0.39] {
+                    let target = center + Vector3D { x: offset, y: 0.0, z: 0.0 };
+                    let ray = Ray { origin: Point::default(), direction: unit_vector(target), time: 0.0 };
+                    let mut sampler = Independent::new(0, 0);
+                    for _ in 0..200 {
+                        let color = ray_color(&ray, &world, &RenderSettings::default(), &mut Rgb { to_working: Conversion::new(ColorSpace::Srgb, ColorSpace::Srgb) }, &mut sampler, None);
+                        for channel in [color.x, color.y, color.z] {
+                            assert!(channel.is_finite() && channel >= 0.0, "row {} column {}: {}", row, column, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
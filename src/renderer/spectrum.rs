@@ -0,0 +1,423 @@
+use std::ops;
+use std::sync::OnceLock;
+use crate::renderer::color_space::{invert, multiply, transform, ColorSpace, Matrix};
+use crate::renderer::vector3d::Color;
+
+// wavelengths every path carries: a hero wavelength and companions spread evenly through the
+// visible range, so one path estimates four points of the spectrum at once
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// values at the path's wavelengths
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Spectrum {
+    pub values: [f64; SPECTRUM_SAMPLES],
+}
+
+impl ops::Add for Spectrum {
+    type Output = Spectrum;
+
+    fn add(self, other: Spectrum) -> Spectrum {
+        let mut values = self.values;
+        values.iter_mut().zip(other.values).for_each(|(value, other)| *value += other);
+        Spectrum { values }
+    }
+}
+
+impl ops::Mul for Spectrum {
+    type Output = Spectrum;
+
+    fn mul(self, other: Spectrum) -> Spectrum {
+        let mut values = self.values;
+        values.iter_mut().zip(other.values).for_each(|(value, other)| *value *= other);
+        Spectrum { values }
+    }
+}
+
+impl ops::Mul<f64> for Spectrum {
+    type Output = Spectrum;
+
+    fn mul(self, scale: f64) -> Spectrum {
+        Spectrum { values: self.values.map(|value| value * scale) }
+    }
+}
+
+// the wavelengths in nm a path carries and the densities they were sampled with
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wavelengths {
+    pub lambda: [f64; SPECTRUM_SAMPLES],
+    pub pdf: [f64; SPECTRUM_SAMPLES],
+}
+
+impl Wavelengths {
+    // the hero takes u, its companions u shifted by equal steps, each importance sampled towards
+    // where the eye is sensitive
+    pub fn sample(u: f64) -> Self {
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; SPECTRUM_SAMPLES];
+        for (index, (lambda, pdf)) in lambda.iter_mut().zip(&mut pdf).enumerate() {
+            *lambda = sample_visible((u + index as f64 / SPECTRUM_SAMPLES as f64).fract());
+            *pdf = visible_pdf(*lambda);
+        }
+        Self { lambda, pdf }
+    }
+
+    // after a wavelength-dependent scattering event the companions' paths would have gone elsewhere;
+    // only the hero carries on, standing in for all four
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1..].iter().all(|&pdf| pdf == 0.0) {
+            return;
+        }
+        self.pdf[1..].iter_mut().for_each(|pdf| *pdf = 0.0);
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    pub fn map(&self, value: impl Fn(f64) -> f64) -> Spectrum {
+        Spectrum { values: self.lambda.map(value) }
+    }
+
+    // the Monte Carlo estimate of the CIE XYZ color of radiance that was traced at these wavelengths
+    pub fn estimate_xyz(&self, radiance: &Spectrum) -> Color {
+        let mut xyz = Color::default();
+        for ((&lambda, &pdf), &value) in self.lambda.iter().zip(&self.pdf).zip(&radiance.values) {
+            if pdf > 0.0 {
+                xyz = xyz + cie_xyz(lambda) * (value / pdf);
+            }
+        }
+        xyz / (SPECTRUM_SAMPLES as f64 * tables().y_integral)
+    }
+}
+
+// the visible-range importance function of Radziszewski et al., which pbrt also uses
+fn visible_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let cosh = (0.0072 * (lambda - 538.0)).cosh();
+    0.0039398042 / (cosh * cosh)
+}
+
+fn sample_visible(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+// the CIE 1931 2° color matching functions as fitted with Gaussian lobes by Wyman, Sloan and
+// Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching Functions" (2013)
+pub fn cie_xyz(lambda: f64) -> Color {
+    let lobe = |mean: f64, below: f64, above: f64| {
+        let deviation = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * deviation * deviation).exp()
+    };
+    Color {
+        x: 1.056 * lobe(599.8, 37.9, 31.0) + 0.362 * lobe(442.0, 16.0, 26.7) - 0.065 * lobe(501.1, 20.4, 26.2),
+        y: 0.821 * lobe(568.8, 46.9, 40.5) + 0.286 * lobe(530.9, 16.3, 31.1),
+        z: 1.217 * lobe(437.0, 11.8, 36.0) + 0.681 * lobe(459.0, 26.0, 13.8),
+    }
+}
+
+// relative spectral power of CIE illuminant D65 from 360nm to 830nm in steps of 10nm
+const D65: [f64; 48] = [
+    46.6383, 52.0891, 49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0, 96.3342, 95.788, 88.6856,
+    90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842, 69.7213, 71.6091, 74.349,
+    61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054, 63.3828, 64.304, 59.4519, 51.959, 57.4406, 60.3125,
+];
+
+fn d65(lambda: f64) -> f64 {
+    let position = ((lambda - LAMBDA_MIN) / 10.0).clamp(0.0, (D65.len() - 1) as f64);
+    let index = (position as usize).min(D65.len() - 2);
+    let t = position - index as f64;
+    D65[index] * (1.0 - t) + D65[index + 1] * t
+}
+
+// Jakob and Hanika, "A Low-Dimensional Function Space for Efficient Spectral Upsampling" (2019): a
+// smooth spectrum bounded to 0..1, a sigmoid of a quadratic in the wavelength
+fn sigmoid_polynomial(coefficients: &[f64; 3], lambda: f64) -> f64 {
+    let t = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+    let x = (coefficients[0] * t + coefficients[1]) * t + coefficients[2];
+    if x.is_infinite() {
+        return (x > 0.0) as u32 as f64;
+    }
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+// linear sRGB upsampled to a spectrum: the color is scaled so that its largest channel is 0.5,
+// which leaves the fitted spectrum room on both sides of it, and the scale is multiplied back on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RgbSpectrum {
+    scale: f64,
+    coefficients: [f64; 3],
+    illuminant: bool,
+}
+
+impl RgbSpectrum {
+    // reflectances and other factors light is multiplied by
+    pub fn unbounded(color: Color) -> Self {
+        Self::fit(color, false)
+    }
+
+    // emitted light, whose white is the D65 white of sRGB
+    pub fn illuminant(color: Color) -> Self {
+        Self::fit(color, true)
+    }
+
+    fn fit(color: Color, illuminant: bool) -> Self {
+        let color = Color { x: color.x.max(0.0), y: color.y.max(0.0), z: color.z.max(0.0) };
+        let largest = color.x.max(color.y).max(color.z);
+        if largest <= 0.0 || !largest.is_finite() {
+            return Self { scale: 0.0, coefficients: [0.0; 3], illuminant };
+        }
+        Self { scale: 2.0 * largest, coefficients: tables().coefficients(color / (2.0 * largest)), illuminant }
+    }
+
+    pub fn evaluate(&self, lambda: f64) -> f64 {
+        let value = self.scale * sigmoid_polynomial(&self.coefficients, lambda);
+        if self.illuminant {
+            value * tables().d65_scale * d65(lambda)
+        } else {
+            value
+        }
+    }
+
+    pub fn sample(&self, wavelengths: &Wavelengths) -> Spectrum {
+        wavelengths.map(|lambda| self.evaluate(lambda))
+    }
+}
+
+// CIE XYZ of D65-lit colors to linear sRGB, balanced so that D65 comes out exactly white
+pub fn xyz_to_srgb(xyz: Color) -> Color {
+    transform(&tables().xyz_to_srgb, xyz)
+}
+
+// grid of the ratios of the two smaller channels to the largest
+const TABLE_RESOLUTION: usize = 32;
+// integration step in nm for fitting
+const FIT_STEP: f64 = 5.0;
+
+struct Tables {
+    // integral of the y matching function, the luminance of a constant unit spectrum
+    y_integral: f64,
+    // normalizes D65 to a luminance of 1
+    d65_scale: f64,
+    xyz_to_srgb: Matrix,
+    // [largest channel][first ratio][second ratio]
+    coefficients: Vec<[f64; 3]>,
+}
+
+impl Tables {
+    fn index(largest: usize, first: usize, second: usize) -> usize {
+        (largest * TABLE_RESOLUTION + first) * TABLE_RESOLUTION + second
+    }
+
+    // coefficients for a color whose largest channel is 0.5, interpolated bilinearly
+    fn coefficients(&self, color: Color) -> [f64; 3] {
+        let channels = [color.x, color.y, color.z];
+        let largest = (0..3).fold(0, |largest, channel| if channels[channel] > channels[largest] { channel } else { largest });
+        let scale = (TABLE_RESOLUTION - 1) as f64;
+        let position = |channel: usize| (channels[(largest + channel) % 3] / channels[largest] * scale).clamp(0.0, scale);
+        let (first, second) = (position(1), position(2));
+        let (first_index, second_index) = ((first as usize).min(TABLE_RESOLUTION - 2), (second as usize).min(TABLE_RESOLUTION - 2));
+        let (s, t) = (first - first_index as f64, second - second_index as f64);
+        let mut coefficients = [0.0; 3];
+        for (df, dt, weight) in [(0, 0, (1.0 - s) * (1.0 - t)), (1, 0, s * (1.0 - t)), (0, 1, (1.0 - s) * t), (1, 1, s * t)] {
+            let corner = self.coefficients[Self::index(largest, first_index + df, second_index + dt)];
+            for (coefficient, corner) in coefficients.iter_mut().zip(corner) {
+                *coefficient += weight * corner;
+            }
+        }
+        coefficients
+    }
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(build_tables)
+}
+
+fn build_tables() -> Tables {
+    let steps = ((LAMBDA_MAX - LAMBDA_MIN) / FIT_STEP) as usize;
+    let wavelengths: Vec<f64> = (0..steps).map(|step| LAMBDA_MIN + (step as f64 + 0.5) * FIT_STEP).collect();
+    let y_integral: f64 = wavelengths.iter().map(|&lambda| cie_xyz(lambda).y * FIT_STEP).sum();
+    let d65_luminance: f64 = wavelengths.iter().map(|&lambda| d65(lambda) * cie_xyz(lambda).y * FIT_STEP).sum();
+    let d65_scale = y_integral / d65_luminance;
+
+    // the matching functions and the D65 table don't land exactly on sRGB's white; balance it away
+    let white = wavelengths
+        .iter()
+        .fold(Color::default(), |white, &lambda| white + cie_xyz(lambda) * (d65_scale * d65(lambda) * FIT_STEP / y_integral));
+    let srgb = transform(&ColorSpace::Srgb.xyz_to_rgb(), white);
+    let balance = [[1.0 / srgb.x, 0.0, 0.0], [0.0, 1.0 / srgb.y, 0.0], [0.0, 0.0, 1.0 / srgb.z]];
+    let xyz_to_srgb = multiply(&balance, &ColorSpace::Srgb.xyz_to_rgb());
+
+    // what each wavelength of a reflectance contributes to its sRGB color under D65
+    let weights: Vec<(f64, Color)> = wavelengths
+        .iter()
+        .map(|&lambda| {
+            let xyz = cie_xyz(lambda) * (d65_scale * d65(lambda) * FIT_STEP / y_integral);
+            (lambda, transform(&xyz_to_srgb, xyz))
+        })
+        .collect();
+
+    let mut coefficients = vec![[0.0; 3]; 3 * TABLE_RESOLUTION * TABLE_RESOLUTION];
+    let scale = (TABLE_RESOLUTION - 1) as f64;
+    for largest in 0..3 {
+        // start from grey, whose spectrum is flat, and walk towards saturated colors, starting
+        // every fit from its neighbour's
+        let mut start = [0.0; 3];
+        for first in (0..TABLE_RESOLUTION).rev() {
+            let mut previous = start;
+            for second in (0..TABLE_RESOLUTION).rev() {
+                let mut target = [0.0; 3];
+                target[largest] = 0.5;
+                target[(largest + 1) % 3] = 0.5 * first as f64 / scale;
+                target[(largest + 2) % 3] = 0.5 * second as f64 / scale;
+                let fitted = fit(&weights, Color { x: target[0], y: target[1], z: target[2] }, previous);
+                coefficients[Tables::index(largest, first, second)] = fitted;
+                if second == TABLE_RESOLUTION - 1 {
+                    start = fitted;
+                }
+                previous = fitted;
+            }
+        }
+    }
+
+    Tables { y_integral, d65_scale, xyz_to_srgb, coefficients }
+}
+
+fn reflectance_color(weights: &[(f64, Color)], coefficients: &[f64; 3]) -> Color {
+    weights
+        .iter()
+        .fold(Color::default(), |color, &(lambda, weight)| color + weight * sigmoid_polynomial(coefficients, lambda))
+}
+
+// Levenberg-Marquardt on the difference between the spectrum's color and the target; colors outside
+// what a 0..1 spectrum can reach end at the closest one instead
+fn fit(weights: &[(f64, Color)], target: Color, start: [f64; 3]) -> [f64; 3] {
+    let residual_of = |coefficients: &[f64; 3]| reflectance_color(weights, coefficients) - target;
+    let mut coefficients = start;
+    let mut residual = residual_of(&coefficients);
+    let mut damping = 1e-3;
+    for _ in 0..50 {
+        if residual.length() < 1e-7 {
+            break;
+        }
+        let mut jacobian = [[0.0; 3]; 3];
+        for parameter in 0..3 {
+            let mut shifted = coefficients;
+            shifted[parameter] += 1e-5;
+            let derivative = (residual_of(&shifted) - residual) / 1e-5;
+            jacobian[0][parameter] = derivative.x;
+            jacobian[1][parameter] = derivative.y;
+            jacobian[2][parameter] = derivative.z;
+        }
+        let residuals = [residual.x, residual.y, residual.z];
+        let mut normal = [[0.0; 3]; 3];
+        let mut gradient = [0.0; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                normal[i][j] = (0..3).map(|k| jacobian[k][i] * jacobian[k][j]).sum();
+            }
+            normal[i][i] *= 1.0 + damping;
+            gradient[i] = (0..3).map(|k| jacobian[k][i] * residuals[k]).sum();
+        }
+        let step = transform(&invert(&normal), Color { x: gradient[0], y: gradient[1], z: gradient[2] });
+        let candidate = [coefficients[0] - step.x, coefficients[1] - step.y, coefficients[2] - step.z];
+        let candidate_residual = residual_of(&candidate);
+        if candidate.iter().all(|value| value.is_finite()) && candidate_residual.length() < residual.length() {
+            coefficients = candidate;
+            residual = candidate_residual;
+            damping = (damping * 0.1).max(1e-9);
+        } else {
+            damping *= 10.0;
+            if damping > 1e9 {
+                break;
+            }
+        }
+    }
+    coefficients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the sRGB color of a spectrum under D65, by numerical integration
+    fn color_of(spectrum: impl Fn(f64) -> f64, illuminant: bool) -> Color {
+        let tables = tables();
+        let mut xyz = Color::default();
+        for step in 0..470 {
+            let lambda = LAMBDA_MIN + step as f64 + 0.5;
+            let light = if illuminant { 1.0 } else { tables.d65_scale * d65(lambda) };
+            xyz = xyz + cie_xyz(lambda) * (spectrum(lambda) * light);
+        }
+        xyz_to_srgb(xyz / tables.y_integral)
+    }
+
+    #[test]
+    fn test_upsampled_colors_round_trip() {
+        let colors = [(0.5, 0.5, 0.5), (0.8, 0.2, 0.1), (0.1, 0.6, 0.3), (0.2, 0.3, 0.9), (0.05, 0.02, 0.01), (3.0, 2.0, 1.0)];
+        for (r, g, b) in colors {
+            let color = Color { x: r, y: g, z: b };
+            for illuminant in [false, true] {
+                let spectrum = if illuminant { RgbSpectrum::illuminant(color) } else { RgbSpectrum::unbounded(color) };
+                let round_trip = color_of(|lambda| spectrum.evaluate(lambda), illuminant);
+                assert!((round_trip - color).length() < 0.01 * r.max(g).max(b), "{:?} came back as {:?}", color, round_trip);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grey_is_flat() {
+        let spectrum = RgbSpectrum::unbounded(Color { x: 0.3, y: 0.3, z: 0.3 });
+        for lambda in [400.0, 550.0, 700.0] {
+            assert!((spectrum.evaluate(lambda) - 0.3).abs() < 1e-3, "{}", spectrum.evaluate(lambda));
+        }
+        assert_eq!(RgbSpectrum::unbounded(Color::default()).evaluate(500.0), 0.0);
+    }
+
+    #[test]
+    fn test_wavelengths_cover_the_visible_range() {
+        for step in 0..100 {
+            let wavelengths = Wavelengths::sample(step as f64 / 100.0);
+            for (lambda, pdf) in wavelengths.lambda.iter().zip(wavelengths.pdf) {
+                assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(lambda), "{}", lambda);
+                assert!(pdf > 0.0);
+            }
+        }
+        // the density integrates to 1 over the visible range
+        let integral: f64 = (0..4700).map(|step| visible_pdf(LAMBDA_MIN + (step as f64 + 0.5) * 0.1) * 0.1).sum();
+        assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+    }
+
+    #[test]
+    fn test_white_light_estimates_white() {
+        let white = RgbSpectrum::illuminant(Color { x: 1.0, y: 1.0, z: 1.0 });
+        let samples = 20000;
+        let mut total = Color::default();
+        for step in 0..samples {
+            let wavelengths = Wavelengths::sample((step as f64 + 0.5) / samples as f64);
+            total = total + xyz_to_srgb(wavelengths.estimate_xyz(&white.sample(&wavelengths)));
+        }
+        let mean = total / samples as f64;
+        for channel in [mean.x, mean.y, mean.z] {
+            assert!((channel - 1.0).abs() < 0.01, "{:?}", mean);
+        }
+    }
+
+    #[test]
+    fn test_terminated_companions_keep_the_estimate_unbiased() {
+        let white = RgbSpectrum::illuminant(Color { x: 1.0, y: 1.0, z: 1.0 });
+        let samples = 20000;
+        let mut total = Color::default();
+        for step in 0..samples {
+            let mut wavelengths = Wavelengths::sample((step as f64 + 0.5) / samples as f64);
+            wavelengths.terminate_secondary();
+            wavelengths.terminate_secondary();
+            total = total + xyz_to_srgb(wavelengths.estimate_xyz(&white.sample(&wavelengths)));
+        }
+        let mean = total / samples as f64;
+        assert!((mean.y - 1.0).abs() < 0.01, "{:?}", mean);
+    }
+}
@@ -1,44 +1,115 @@
-use std::rc::Rc;
-use crate::renderer::hit::{Hittable, HitRecord};
+use std::sync::Arc;
+use std::f64::consts::PI;
+use crate::renderer::aabb::Aabb;
+use crate::renderer::hit::{Hittable, HitRecord, ShadingFrame};
 use crate::renderer::material::Material;
+use crate::renderer::motion::{Interpolate, Keyframes};
 use crate::renderer::ray::{dot, Ray};
-use crate::renderer::vector3d::Point;
+use crate::renderer::sampler::Sampler;
+use crate::renderer::stats::{count, Counter};
+use crate::renderer::vector3d::{Point, Vector3D};
+
+fn sphere_uv(point: Point) -> (f64, f64) {
+    let theta = (-point.y).acos();
+    let phi = (-point.z).atan2(point.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
 
 pub(crate) struct Sphere {
     pub center: Point,
     pub radius: f64,
-    pub material: Rc<dyn Material>
+    pub material: Arc<dyn Material>
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let object_center = ray.origin - self.center;
-        let a: f64 = ray.direction.length_squared();
-        let half_b: f64 = dot(object_center, ray.direction);
-        let c = object_center.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-        if discriminant < 0.0 {
+fn sphere_bounds(center: Point, radius: f64) -> Aabb {
+    let radius = Vector3D { x: radius, y: radius, z: radius };
+    Aabb { min: center - radius, max: center + radius }
+}
+
+fn hit_sphere(center: Point, radius: f64, material: &Arc<dyn Material>, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    count(Counter::SphereTests);
+    let object_center = ray.origin - center;
+    let a: f64 = ray.direction.length_squared();
+    let half_b: f64 = dot(object_center, ray.direction);
+    let c = object_center.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let discriminant_sqrt = discriminant.sqrt();
+    let mut root = (-half_b - discriminant_sqrt) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + discriminant_sqrt) / a;
+        if root < t_min || t_max < root {
             return None;
         }
-        let discriminant_sqrt = discriminant.sqrt();
-        let mut root = (-half_b - discriminant_sqrt) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + discriminant_sqrt) / a;
-            if root < t_min || t_max < root {
-                return None;
+    }
+    let root_ray = ray.at(root);
+    let outward_normal = (root_ray - center) / radius;
+    let (u, v) = sphere_uv(outward_normal);
+
+    // direction of increasing u, i.e. dp/du
+    let tangent = Vector3D { x: outward_normal.z, y: 0.0, z: -outward_normal.x };
+
+    let mut hit_record = HitRecord {
+        point: root_ray,
+        normal: outward_normal,
+        shading: ShadingFrame::new(outward_normal, tangent),
+        material: material.clone(),
+        t: root,
+        u,
+        v,
+        front_face: false,
+        time: ray.time,
+        object_id: 0,
+        material_id: 0,
+    };
+    hit_record.set_face_normal(ray, outward_normal);
+    Some(hit_record)
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitRecord> {
+        hit_sphere(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(sphere_bounds(self.center, self.radius))
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.material)
+    }
+}
+
+// a sphere whose center moves through keyframes, e.g. over the shutter interval
+pub(crate) struct MovingSphere {
+    pub center: Keyframes<Point>,
+    pub radius: f64,
+    pub material: Arc<dyn Material>
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitRecord> {
+        hit_sphere(self.center.at(ray.time), self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds_during(f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    // the center moves along straight lines, so the spheres at their ends bound the sweep
+    fn bounds_during(&self, start: f64, end: f64) -> Option<Aabb> {
+        let mut swept = Aabb::empty();
+        for (from, to, lowest, highest) in self.center.reach(start, end) {
+            for p in [lowest, highest] {
+                swept = swept.union(&sphere_bounds(from.lerp(to, p), self.radius));
             }
         }
-        let root_ray = ray.at(root);
-        let outward_normal = (root_ray - self.center) / self.radius;
-
-        let mut hit_record = HitRecord {
-            point: root_ray,
-            normal: outward_normal,
-            material: self.material.clone(),
-            t: root,
-            front_face: false
-        };
-        hit_record.set_face_normal(ray, outward_normal);
-        Some(hit_record)
-    }
-}
\ No newline at end of file
+        Some(swept)
+    }
+
+    fn material(&self) -> Option<&Arc<dyn Material>> {
+        Some(&self.material)
+    }
+}
@@ -0,0 +1,172 @@
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::time::Duration;
+
+// work counters; each thread counts into its own copy, which the render loop collects per tile
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Counter {
+    CameraRays,
+    BounceRays,
+    // transmittance queries towards the sky from scattering events in media
+    ShadowRays,
+    SphereTests,
+    PolyhedronTests,
+    ConstantMediumTests,
+    HeterogeneousMediumTests,
+    BvhNodeVisits,
+    // camera samples firefly suppression scaled down
+    ClampedSamples,
+}
+
+const COUNTERS: [(Counter, &str); 9] = [
+    (Counter::CameraRays, "camera_rays"),
+    (Counter::BounceRays, "bounce_rays"),
+    (Counter::ShadowRays, "shadow_rays"),
+    (Counter::SphereTests, "sphere_tests"),
+    (Counter::PolyhedronTests, "polyhedron_tests"),
+    (Counter::ConstantMediumTests, "constant_medium_tests"),
+    (Counter::HeterogeneousMediumTests, "heterogeneous_medium_tests"),
+    (Counter::BvhNodeVisits, "bvh_node_visits"),
+    (Counter::ClampedSamples, "clamped_samples"),
+];
+
+thread_local! {
+    static COUNTS: [Cell<u64>; COUNTERS.len()] = const { [const { Cell::new(0) }; COUNTERS.len()] };
+    static PATH_LENGTHS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn count(counter: Counter) {
+    COUNTS.with(|counts| {
+        let cell = &counts[counter as usize];
+        cell.set(cell.get() + 1);
+    });
+}
+
+// a path that ended after `bounces` scattering events
+pub fn record_path_length(bounces: u32) {
+    PATH_LENGTHS.with(|lengths| {
+        let mut lengths = lengths.borrow_mut();
+        if lengths.len() <= bounces as usize {
+            lengths.resize(bounces as usize + 1, 0);
+        }
+        lengths[bounces as usize] += 1;
+    });
+}
+
+// what this thread counted since the last call
+pub fn take() -> Statistics {
+    Statistics {
+        counts: COUNTS.with(|counts| counts.each_ref().map(|cell| cell.replace(0))),
+        path_lengths: PATH_LENGTHS.with(|lengths| lengths.take()),
+        phases: Vec::new(),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Statistics {
+    counts: [u64; COUNTERS.len()],
+    // number of paths by how many times they scattered
+    path_lengths: Vec<u64>,
+    phases: Vec<(String, Duration)>,
+}
+
+impl Statistics {
+    pub fn counter(&self, counter: Counter) -> u64 {
+        self.counts[counter as usize]
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.counter(Counter::CameraRays) + self.counter(Counter::BounceRays) + self.counter(Counter::ShadowRays)
+    }
+
+    pub fn merge(&mut self, other: &Statistics) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+        if self.path_lengths.len() < other.path_lengths.len() {
+            self.path_lengths.resize(other.path_lengths.len(), 0);
+        }
+        for (count, other) in self.path_lengths.iter_mut().zip(&other.path_lengths) {
+            *count += other;
+        }
+        self.phases.extend(other.phases.iter().cloned());
+    }
+
+    pub fn add_phase(&mut self, name: &str, duration: Duration) {
+        self.phases.push((name.to_string(), duration));
+    }
+
+    pub fn summary(&self) -> String {
+        let mut summary = String::from("Render statistics\n");
+        for (counter, name) in COUNTERS {
+            writeln!(summary, "  {:<28}{:>16}", name.replace('_', " "), self.counter(counter)).unwrap();
+        }
+        let paths: u64 = self.path_lengths.iter().sum();
+        writeln!(summary, "  path length histogram ({} paths)", paths).unwrap();
+        for (bounces, &count) in self.path_lengths.iter().enumerate() {
+            let share = count as f64 / paths.max(1) as f64;
+            let bar = "#".repeat((share * 40.0).round() as usize);
+            writeln!(summary, "    {:>3} bounces {:>14} {:5.1}% {}", bounces, count, 100.0 * share, bar).unwrap();
+        }
+        writeln!(summary, "  phases").unwrap();
+        for (name, duration) in &self.phases {
+            writeln!(summary, "    {:<26}{:>12.3} s", name, duration.as_secs_f64()).unwrap();
+        }
+        summary
+    }
+
+    pub fn to_json(&self) -> String {
+        let counters: Vec<String> = COUNTERS
+            .iter()
+            .map(|&(counter, name)| format!("    \"{}\": {}", name, self.counter(counter)))
+            .collect();
+        let lengths: Vec<String> = self.path_lengths.iter().map(u64::to_string).collect();
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| format!("    \"{}\": {}", name.replace('"', "\\\""), duration.as_secs_f64()))
+            .collect();
+        format!(
+            "{{\n  \"counters\": {{\n{}\n  }},\n  \"path_lengths\": [{}],\n  \"phase_seconds\": {{\n{}\n  }}\n}}\n",
+            counters.join(",\n"),
+            lengths.join(", "),
+            phases.join(",\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_are_taken_per_thread() {
+        take();
+        count(Counter::CameraRays);
+        count(Counter::SphereTests);
+        count(Counter::SphereTests);
+        record_path_length(3);
+        std::thread::spawn(|| count(Counter::SphereTests)).join().unwrap();
+        let taken = take();
+        assert_eq!(taken.counter(Counter::CameraRays), 1);
+        assert_eq!(taken.counter(Counter::SphereTests), 2);
+        assert_eq!(taken.path_lengths, vec![0, 0, 0, 1]);
+        assert_eq!(take().counter(Counter::SphereTests), 0);
+    }
+
+    #[test]
+    fn test_merge_and_json() {
+        let mut total = Statistics::default();
+        let mut part = Statistics::default();
+        part.counts[Counter::BounceRays as usize] = 5;
+        part.path_lengths = vec![1, 2];
+        total.merge(&part);
+        total.merge(&part);
+        total.add_phase("render", Duration::from_millis(1500));
+        assert_eq!(total.counter(Counter::BounceRays), 10);
+        let json = total.to_json();
+        assert!(json.contains("\"bounce_rays\": 10"), "{}", json);
+        assert!(json.contains("\"path_lengths\": [2, 4]"), "{}", json);
+        assert!(json.contains("\"render\": 1.5"), "{}", json);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod image;
+
+use crate::renderer::vector3d::{Color, Point};
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: &Point) -> Color;
+}
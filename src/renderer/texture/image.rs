@@ -0,0 +1,162 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use crate::renderer::color_space::{ColorSpace, ImageTag};
+use crate::renderer::texture::Texture;
+use crate::renderer::util::clamp;
+use crate::renderer::vector3d::{Color, Point};
+
+pub(crate) struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+// reads the next whitespace separated header token, skipping '#' comments
+fn next_token(bytes: &[u8], position: &mut usize) -> Result<String> {
+    loop {
+        while *position < bytes.len() && bytes[*position].is_ascii_whitespace() {
+            *position += 1;
+        }
+        if *position < bytes.len() && bytes[*position] == b'#' {
+            while *position < bytes.len() && bytes[*position] != b'\n' {
+                *position += 1;
+            }
+            continue;
+        }
+        break;
+    }
+    let start = *position;
+    while *position < bytes.len() && !bytes[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    if start == *position {
+        return Err(invalid("unexpected end of PPM data"));
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*position]).into_owned())
+}
+
+fn next_number(bytes: &[u8], position: &mut usize) -> Result<usize> {
+    next_token(bytes, position)?
+        .parse()
+        .map_err(|_| invalid("malformed number in PPM data"))
+}
+
+impl ImageTexture {
+    // color images are decoded to linear sRGB, the space scene colors are authored in
+    pub fn from_ppm<P: AsRef<Path>>(path: P, tag: ImageTag) -> Result<Self> {
+        Self::parse_ppm(&fs::read(path)?, tag)
+    }
+
+    pub fn parse_ppm(bytes: &[u8], tag: ImageTag) -> Result<Self> {
+        let mut position = 0;
+        let magic = next_token(bytes, &mut position)?;
+        let width = next_number(bytes, &mut position)?;
+        let height = next_number(bytes, &mut position)?;
+        let max_value = next_number(bytes, &mut position)?;
+        if width == 0 || height == 0 || max_value == 0 || max_value > 255 {
+            return Err(invalid("unsupported PPM dimensions or depth"));
+        }
+
+        let scale = 1.0 / max_value as f64;
+        let count = width * height;
+        let samples: Vec<u8> = match magic.as_str() {
+            "P3" => {
+                let mut samples = Vec::with_capacity(count * 3);
+                for _ in 0..count * 3 {
+                    let value = next_number(bytes, &mut position)?;
+                    samples.push(value.min(max_value) as u8);
+                }
+                samples
+            }
+            "P6" => {
+                // exactly one whitespace byte separates the header from the raster
+                let start = position + 1;
+                if bytes.len() < start + count * 3 {
+                    return Err(invalid("truncated PPM raster"));
+                }
+                bytes[start..start + count * 3].to_vec()
+            }
+            _ => return Err(invalid("not a P3 or P6 PPM image")),
+        };
+
+        let pixels = samples
+            .chunks(3)
+            .map(|rgb| {
+                let value = Color {
+                    x: rgb[0] as f64 * scale,
+                    y: rgb[1] as f64 * scale,
+                    z: rgb[2] as f64 * scale,
+                };
+                tag.decode(value, ColorSpace::Srgb)
+            })
+            .collect();
+
+        Ok(Self { width, height, pixels })
+    }
+
+    fn texel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _: &Point) -> Color {
+        // wrap u, clamp v, and flip v so that v = 1 is the top row of the image
+        let u = u - u.floor();
+        let v = 1.0 - clamp(v, 0.0, 1.0);
+
+        // bilinear filtering keeps finite differences (bump mapping) smooth
+        let fx = u * self.width as f64 - 0.5;
+        let fy = clamp(v * self.height as f64 - 0.5, 0.0, (self.height - 1) as f64);
+        let x0 = fx.floor();
+        let y0 = fy.floor();
+        let tx = fx - x0;
+        let ty = fy - y0;
+
+        let wrap_x = |x: f64| (x as i64).rem_euclid(self.width as i64) as usize;
+        let x0i = wrap_x(x0);
+        let x1i = wrap_x(x0 + 1.0);
+        let y0i = y0 as usize;
+        let y1i = (y0i + 1).min(self.height - 1);
+
+        let top = (1.0 - tx) * self.texel(x0i, y0i) + tx * self.texel(x1i, y0i);
+        let bottom = (1.0 - tx) * self.texel(x0i, y1i) + tx * self.texel(x1i, y1i);
+        (1.0 - ty) * top + ty * bottom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ascii_ppm() {
+        let texture = ImageTexture::parse_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n", ImageTag::Data).unwrap();
+        assert_eq!(texture.width, 2);
+        assert_eq!(texture.height, 1);
+        assert_eq!(texture.texel(0, 0).x, 1.0);
+        assert_eq!(texture.texel(1, 0).z, 1.0);
+    }
+
+    #[test]
+    fn test_parse_binary_ppm() {
+        let mut bytes = b"P6 1 1 255\n".to_vec();
+        bytes.extend_from_slice(&[0, 255, 51]);
+        let texture = ImageTexture::parse_ppm(&bytes, ImageTag::Data).unwrap();
+        let color = texture.value(0.5, 0.5, &Point::default());
+        assert_eq!(color.x, 0.0);
+        assert_eq!(color.y, 1.0);
+        assert_eq!(color.z, 0.2);
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(ImageTexture::parse_ppm(b"P5 1 1 255\n\0", ImageTag::Data).is_err());
+        assert!(ImageTexture::parse_ppm(b"P6 4 4 255\n\0\0\0", ImageTag::Data).is_err());
+    }
+}
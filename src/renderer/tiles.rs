@@ -0,0 +1,144 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use crate::renderer::framebuffer::Window;
+
+pub const TILE_SIZE: u32 = 32;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TileOrder {
+    Scanline,
+    // rings around the centre of the window, so the middle of the frame shows up first
+    Spiral,
+    // consecutive tiles are neighbours, which keeps the caches warm
+    Hilbert,
+}
+
+pub const TILE_ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        TILE_ORDERS
+            .iter()
+            .find(|order| order.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown tile order '{}'", name))
+    }
+}
+
+// distance along a Hilbert curve filling a size x size grid, size a power of two
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // rotate the quadrant so the curve stays continuous
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+// splits the window into tiles of at most tile_size x tile_size pixels, in rendering order
+pub fn schedule(window: Window, tile_size: u32, order: TileOrder) -> Vec<Window> {
+    let tile_size = tile_size.max(1);
+    let columns = window.width.div_ceil(tile_size);
+    let rows = window.height.div_ceil(tile_size);
+    let mut cells: Vec<(u32, u32)> = (0..rows).flat_map(|row| (0..columns).map(move |column| (column, row))).collect();
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center_x = (columns as f64 - 1.0) / 2.0;
+            let center_y = (rows as f64 - 1.0) / 2.0;
+            let key = |&(column, row): &(u32, u32)| {
+                let dx = column as f64 - center_x;
+                let dy = row as f64 - center_y;
+                let ring = dx.abs().max(dy.abs()).round() as u32;
+                // clockwise from the top within each ring
+                let angle = (dx.atan2(-dy) + 2.0 * PI) % (2.0 * PI);
+                (ring, angle)
+            };
+            cells.sort_by(|a, b| {
+                let (ring_a, angle_a) = key(a);
+                let (ring_b, angle_b) = key(b);
+                ring_a.cmp(&ring_b).then(angle_a.total_cmp(&angle_b))
+            });
+        }
+        TileOrder::Hilbert => {
+            let size = columns.max(rows).next_power_of_two();
+            cells.sort_by_key(|&(column, row)| hilbert_index(size, column, row));
+        }
+    }
+    cells
+        .into_iter()
+        .map(|(column, row)| {
+            let x = column * tile_size;
+            let y = row * tile_size;
+            Window {
+                x: window.x + x,
+                y: window.y + y,
+                width: tile_size.min(window.width - x),
+                height: tile_size.min(window.height - y),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles_cover_the_window_once() {
+        let window = Window { x: 5, y: 7, width: 100, height: 70 };
+        for order in TILE_ORDERS {
+            let mut covered = vec![0; (window.width * window.height) as usize];
+            for tile in schedule(window, 32, order) {
+                for y in tile.y..tile.y + tile.height {
+                    for x in tile.x..tile.x + tile.width {
+                        covered[((y - window.y) * window.width + x - window.x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{}", order);
+        }
+    }
+
+    #[test]
+    fn test_hilbert_steps_to_neighbours() {
+        let tiles = schedule(Window { x: 0, y: 0, width: 256, height: 256 }, 32, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let distance = pair[0].x.abs_diff(pair[1].x) + pair[0].y.abs_diff(pair[1].y);
+            assert_eq!(distance, 32);
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_the_middle() {
+        let tiles = schedule(Window { x: 0, y: 0, width: 160, height: 96 }, 32, TileOrder::Spiral);
+        assert_eq!((tiles[0].x, tiles[0].y), (64, 32));
+        let last = tiles.last().unwrap();
+        assert!(last.x == 0 || last.x == 128);
+    }
+}
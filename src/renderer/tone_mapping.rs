@@ -0,0 +1,254 @@
+use std::{fmt, io};
+use std::str::FromStr;
+use crate::renderer::color_space::{transform, Matrix};
+use crate::renderer::vector3d::Color;
+
+// maps scene-referred radiance to display-referred values in 0..1, before the output encoding
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapper {
+    // no curve, highlights clip
+    Clamp,
+    Reinhard,
+    // Reinhard with a white point that maps to 1 instead of only approaching it
+    ReinhardExtended,
+    AcesFitted,
+    Agx,
+    Hable,
+}
+
+pub const TONE_MAPPERS: [ToneMapper; 6] = [
+    ToneMapper::Clamp,
+    ToneMapper::Reinhard,
+    ToneMapper::ReinhardExtended,
+    ToneMapper::AcesFitted,
+    ToneMapper::Agx,
+    ToneMapper::Hable,
+];
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::ReinhardExtended => "reinhard-extended",
+            ToneMapper::AcesFitted => "aces",
+            ToneMapper::Agx => "agx",
+            ToneMapper::Hable => "hable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        TONE_MAPPERS
+            .iter()
+            .find(|mapper| mapper.to_string() == name)
+            .copied()
+            .ok_or_else(|| format!("unknown tone mapper '{}'", name))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    // exposure compensation in stops
+    pub exposure: f64,
+    pub mapper: ToneMapper,
+    // the exposed luminance that reinhard-extended maps to white
+    pub white: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            mapper: ToneMapper::Clamp,
+            white: 4.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    // reads `exposure`, `tone-map` and `white-point` statements, named like the options that
+    // override them, over the defaults
+    pub fn from_settings(source: &str) -> io::Result<Self> {
+        let mut tone_mapping = Self::default();
+        for line in source.lines() {
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue,
+            };
+            let malformed = || io::Error::new(io::ErrorKind::InvalidData, format!("malformed '{}' statement", line.trim()));
+            let value = tokens.next().ok_or_else(malformed)?;
+            match keyword {
+                "exposure" => tone_mapping.exposure = value.parse().map_err(|_| malformed())?,
+                "tone-map" => {
+                    tone_mapping.mapper = value.parse().map_err(|error: String| io::Error::new(io::ErrorKind::InvalidData, error))?
+                }
+                "white-point" => tone_mapping.white = value.parse().map_err(|_| malformed())?,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown setting '{}'", keyword))),
+            }
+        }
+        Ok(tone_mapping)
+    }
+
+    pub fn apply(&self, radiance: Color) -> Color {
+        let exposed = radiance * 2f64.powf(self.exposure);
+        let mapped = match self.mapper {
+            ToneMapper::Clamp => exposed,
+            ToneMapper::Reinhard => scale_luminance(exposed, |luminance| luminance / (1.0 + luminance)),
+            ToneMapper::ReinhardExtended => {
+                let white = self.white * self.white;
+                scale_luminance(exposed, |luminance| luminance * (1.0 + luminance / white) / (1.0 + luminance))
+            }
+            ToneMapper::AcesFitted => aces_fitted(exposed),
+            ToneMapper::Agx => agx(exposed),
+            ToneMapper::Hable => {
+                let white_scale = 1.0 / hable(HABLE_WHITE);
+                // the usual exposure bias of 2 keeps mid grey near where the other curves put it
+                map_channels(exposed, |value| hable(2.0 * value) * white_scale)
+            }
+        };
+        map_channels(mapped, |value| value.clamp(0.0, 1.0))
+    }
+}
+
+fn map_channels(color: Color, map: impl Fn(f64) -> f64) -> Color {
+    Color { x: map(color.x), y: map(color.y), z: map(color.z) }
+}
+
+// Reinhard et al. compress luminance, which keeps the hue of saturated highlights
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let luminance = color.luminance();
+    if luminance <= 0.0 {
+        return Color::default();
+    }
+    color * (curve(luminance) / luminance)
+}
+
+// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms
+const ACES_INPUT: Matrix = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+
+const ACES_OUTPUT: Matrix = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+fn aces_fitted(color: Color) -> Color {
+    let fit = |v: f64| (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081);
+    transform(&ACES_OUTPUT, map_channels(transform(&ACES_INPUT, color), fit))
+}
+
+// Troy Sobotka's AgX with the polynomial fit of its base contrast curve by Benjamin Wrensch: an
+// inset into a smaller gamut so that bright saturated colors desaturate towards white, a log2
+// encoding, the sigmoid, then the inset undone
+const AGX_INSET: Matrix = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+
+const AGX_OUTSET: Matrix = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+fn agx(color: Color) -> Color {
+    let contrast = |value: f64| {
+        let encoded = (value.max(0.0).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+        let x2 = encoded * encoded;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * encoded + 31.96 * x4 - 6.868 * x2 * encoded + 0.4298 * x2 + 0.1191 * encoded - 0.00232
+    };
+    // the curve produces display-encoded values; undo a 2.2 gamma to hand on linear ones
+    let outset = transform(&AGX_OUTSET, map_channels(transform(&AGX_INSET, color), contrast));
+    map_channels(outset, |value| value.max(0.0).powf(2.2))
+}
+
+// John Hable's filmic curve from Uncharted 2
+const HABLE_WHITE: f64 = 11.2;
+
+fn hable(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(value: f64) -> Color {
+        Color { x: value, y: value, z: value }
+    }
+
+    #[test]
+    fn test_settings_override_the_defaults() {
+        let settings = ToneMapping::from_settings("# comment\n\ntone-map reinhard-extended\nwhite-point 8\n").unwrap();
+        assert_eq!(settings, ToneMapping { mapper: ToneMapper::ReinhardExtended, white: 8.0, ..ToneMapping::default() });
+        assert!(ToneMapping::from_settings("tone-map sepia").is_err());
+        assert!(ToneMapping::from_settings("exposure").is_err());
+        assert!(ToneMapping::from_settings("gamma 2.2").is_err());
+    }
+
+    #[test]
+    fn test_curves_are_monotonic_and_stay_in_range() {
+        for mapper in TONE_MAPPERS {
+            let tone_mapping = ToneMapping { mapper, ..ToneMapping::default() };
+            let mut previous = -1.0;
+            for step in 0..200 {
+                let mapped = tone_mapping.apply(grey(0.001 * 1.07f64.powi(step)));
+                assert!((0.0..=1.0).contains(&mapped.x), "{}", mapper);
+                assert!(mapped.x >= previous - 1e-9, "{} at step {}", mapper, step);
+                previous = mapped.x;
+            }
+        }
+    }
+
+    #[test]
+    fn test_curves_compress_highlights_instead_of_clipping() {
+        for mapper in [ToneMapper::Reinhard, ToneMapper::AcesFitted, ToneMapper::Agx, ToneMapper::Hable] {
+            let tone_mapping = ToneMapping { mapper, ..ToneMapping::default() };
+            assert!(tone_mapping.apply(grey(2.0)).x < tone_mapping.apply(grey(4.0)).x, "{}", mapper);
+            assert!(tone_mapping.apply(grey(4.0)).x < 1.0, "{}", mapper);
+        }
+        let clamp = ToneMapping::default();
+        assert_eq!(clamp.apply(grey(2.0)).x, clamp.apply(grey(8.0)).x);
+    }
+
+    #[test]
+    fn test_exposure_and_white_point() {
+        let brighter = ToneMapping { exposure: 1.0, ..ToneMapping::default() };
+        assert_eq!(brighter.apply(grey(0.2)).x, 0.4);
+        let extended = ToneMapping { mapper: ToneMapper::ReinhardExtended, white: 3.0, ..ToneMapping::default() };
+        assert!((extended.apply(grey(3.0)).x - 1.0).abs() < 1e-12);
+        assert!(extended.apply(grey(1.0)).x < 1.0);
+    }
+
+    #[test]
+    fn test_reinhard_keeps_the_hue() {
+        let tone_mapping = ToneMapping { mapper: ToneMapper::Reinhard, ..ToneMapping::default() };
+        let mapped = tone_mapping.apply(Color { x: 1.6, y: 0.8, z: 0.4 });
+        assert!((mapped.x / mapped.y - 2.0).abs() < 1e-12);
+        assert!((mapped.y / mapped.z - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_parse() {
+        for mapper in TONE_MAPPERS {
+            assert_eq!(mapper.to_string().parse::<ToneMapper>(), Ok(mapper));
+        }
+        assert!("drago".parse::<ToneMapper>().is_err());
+    }
+}
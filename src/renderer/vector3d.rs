@@ -1,7 +1,8 @@
-use std::ops;
-use rand::Rng;
+use std::{fmt, ops};
+use std::f64::consts::PI;
+use crate::renderer::sampler::Sampler;
 
-#[derive(Debug, Copy, Clone, Default)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub struct Vector3D {
     pub x: f64,
     pub y: f64,
@@ -52,6 +53,18 @@ impl ops::Sub<Vector3D> for f64 {
     }
 }
 
+impl ops::Sub<f64> for Vector3D {
+    type Output = Vector3D;
+
+    fn sub(self, other: f64) -> Vector3D {
+        Vector3D {
+            x: self.x - other,
+            y: self.y - other,
+            z: self.z - other,
+        }
+    }
+}
+
 impl ops::Mul<Vector3D> for Vector3D {
     type Output = Vector3D;
 
@@ -104,9 +117,9 @@ impl ops::Div<f64> for Vector3D {
     }
 }
 
-impl ToString for Vector3D {
-    fn to_string(&self) -> String {
-        format!("{} {} {}", self.x, self.y, self.z)
+impl fmt::Display for Vector3D {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.x, self.y, self.z)
     }
 }
 
@@ -116,8 +129,7 @@ impl Vector3D {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
-    #[allow(dead_code)]
-    fn length(&self) -> f64 {
+    pub(crate) fn length(&self) -> f64 {
         self.length_squared().sqrt()
     }
 
@@ -127,31 +139,17 @@ impl Vector3D {
     }
 }
 
-fn random(min: f64, max: f64) -> Vector3D {
-    let mut rng = rand::thread_rng();
-    Vector3D {
-        x: rng.gen_range(min..max+1.0),
-        y: rng.gen_range(min..max+1.0),
-        z: rng.gen_range(min..max+1.0),
-    }
-}
-
-pub fn random_in_unit_sphere() -> Vector3D {
-    loop {
-        let p = random(-1.0, 1.0);
-        if p.length_squared() >= 1.0 {
-            continue;
-        }
-        return p;
-    }
-}
-
 pub fn unit_vector(v: Vector3D) -> Vector3D {
     v / v.length()
 }
 
-pub fn random_unit_vector() -> Vector3D {
-    unit_vector(random_in_unit_sphere())
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vector3D {
+    // area-preserving map from the square to the sphere, so stratified samples stay stratified
+    let (u1, u2) = sampler.next_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vector3D { x: r * phi.cos(), y: r * phi.sin(), z }
 }
 
 pub use Vector3D as Color;
@@ -164,6 +162,10 @@ impl Color {
                 (255.999 * self.z) as u32
         )
     }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
 }
 
 pub use Vector3D as Point;
@@ -223,7 +225,7 @@ mod tests {
 
     #[test]
     fn test_f64_subtract_vector3d() {
-        let first = 1.0 as f64;
+        let first = 1.0_f64;
         let second = Vector3D {
             x: 4.0,
             y: 5.0,
@@ -235,6 +237,19 @@ mod tests {
         assert_eq!(difference.z, -5.0);
     }
 
+    #[test]
+    fn test_vector3d_subtract_f64() {
+        let first = Vector3D {
+            x: 4.0,
+            y: 5.0,
+            z: 6.0,
+        };
+        let difference = first - 1.0;
+        assert_eq!(difference.x, 3.0);
+        assert_eq!(difference.y, 4.0);
+        assert_eq!(difference.z, 5.0);
+    }
+
     #[test]
     fn test_vector3d_multiply_vector3d() {
         let first = Vector3D {
@@ -0,0 +1,221 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use crate::renderer::vector3d::Point;
+
+// dense grid of voxel-centered samples; channel 0 is density, an optional channel 1 is temperature in kelvin
+pub(crate) struct VoxelGrid {
+    pub size: [usize; 3],
+    pub channels: usize,
+    data: Vec<f64>,
+    max_density: f64,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// values in a grid of the given size, unless there are too many to address
+fn value_count(size: [usize; 3], channels: usize) -> Option<usize> {
+    size.iter().try_fold(channels, |count, &length| count.checked_mul(length))
+}
+
+impl VoxelGrid {
+    pub fn new(size: [usize; 3], channels: usize, data: Vec<f64>) -> Result<Self> {
+        if size.contains(&0) || !(1..=2).contains(&channels) {
+            return Err(invalid(format!("unsupported grid of {:?} voxels with {} channels", size, channels)));
+        }
+        let count = value_count(size, channels).ok_or_else(|| invalid(format!("a {:?} grid is too large", size)))?;
+        if data.len() != count {
+            return Err(invalid(format!("expected {} values for a {:?} grid, found {}", count, size, data.len())));
+        }
+        let max_density = data.iter().step_by(channels).fold(0.0_f64, |max, &value| max.max(value));
+        Ok(Self { size, channels, data, max_density })
+    }
+
+    pub fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    pub fn has_temperature(&self) -> bool {
+        self.channels > 1
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize, channel: usize) -> f64 {
+        self.data[((z * self.size[1] + y) * self.size[0] + x) * self.channels + channel]
+    }
+
+    // trilinear lookup at a position given in [0, 1]^3 grid coordinates
+    pub fn sample(&self, position: Point, channel: usize) -> f64 {
+        let coordinates = [position.x, position.y, position.z];
+        let mut base = [0; 3];
+        let mut next = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let last = (self.size[axis] - 1) as f64;
+            let continuous = (coordinates[axis] * self.size[axis] as f64 - 0.5).clamp(0.0, last);
+            base[axis] = continuous.floor() as usize;
+            next[axis] = (base[axis] + 1).min(self.size[axis] - 1);
+            fraction[axis] = continuous - base[axis] as f64;
+        }
+
+        let mut result = 0.0;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                index[axis] = if pick(axis) { next[axis] } else { base[axis] };
+                weight *= if pick(axis) { fraction[axis] } else { 1.0 - fraction[axis] };
+            }
+            if weight > 0.0 {
+                result += weight * self.value(index[0], index[1], index[2], channel);
+            }
+        }
+        result
+    }
+
+    pub fn from_nrrd<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        Self::parse_nrrd(&fs::read(path)?, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    // reads raw-encoded NRRD volumes: 3D density or 4D with a leading density/temperature axis,
+    // with the samples attached after the header or in a detached `data file`
+    pub fn parse_nrrd(bytes: &[u8], directory: &Path) -> Result<Self> {
+        if !bytes.starts_with(b"NRRD000") {
+            return Err(invalid("missing NRRD magic".to_string()));
+        }
+
+        let mut position = 0;
+        let mut fields: Vec<(String, String)> = Vec::new();
+        loop {
+            let end = bytes[position..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map(|offset| position + offset)
+                .ok_or_else(|| invalid("unterminated NRRD header".to_string()))?;
+            let line = String::from_utf8_lossy(&bytes[position..end]).trim_end_matches('\r').to_string();
+            position = end + 1;
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with('#') || line.starts_with("NRRD") {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                fields.push((key.trim().to_lowercase(), value.trim_start_matches('=').trim().to_string()));
+            }
+        }
+        let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+
+        let encoding = field("encoding").unwrap_or("raw");
+        if encoding != "raw" {
+            return Err(invalid(format!("unsupported NRRD encoding '{}'", encoding)));
+        }
+        let (width, float) = match field("type").ok_or_else(|| invalid("NRRD header has no type".to_string()))? {
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => (1, false),
+            "float" => (4, true),
+            "double" => (8, true),
+            other => return Err(invalid(format!("unsupported NRRD type '{}'", other))),
+        };
+        let big_endian = field("endian") == Some("big");
+
+        let sizes: Vec<usize> = field("sizes")
+            .ok_or_else(|| invalid("NRRD header has no sizes".to_string()))?
+            .split_whitespace()
+            .map(|size| size.parse().map_err(|_| invalid(format!("malformed NRRD size '{}'", size))))
+            .collect::<Result<_>>()?;
+        let (channels, size) = match sizes.as_slice() {
+            [x, y, z] => (1, [*x, *y, *z]),
+            [c, x, y, z] => (*c, [*x, *y, *z]),
+            _ => return Err(invalid(format!("expected a 3D or 4D NRRD, found sizes {:?}", sizes))),
+        };
+
+        let detached;
+        let raw = match field("data file").or_else(|| field("datafile")) {
+            Some(file) => {
+                detached = fs::read(directory.join(file))?;
+                &detached[..]
+            }
+            None => &bytes[position..],
+        };
+        let length = value_count(size, channels)
+            .and_then(|count| count.checked_mul(width))
+            .ok_or_else(|| invalid(format!("NRRD sizes {:?} are too large", sizes)))?;
+        if raw.len() < length {
+            return Err(invalid(format!("NRRD data holds {} bytes, expected {}", raw.len(), length)));
+        }
+
+        let data = raw[..length]
+            .chunks(width)
+            .map(|chunk| {
+                let mut sample = [0u8; 8];
+                sample[..width].copy_from_slice(chunk);
+                if big_endian {
+                    sample[..width].reverse();
+                }
+                match (width, float) {
+                    (4, true) => f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f64,
+                    (8, true) => f64::from_le_bytes(sample),
+                    // bytes are normalized densities
+                    _ => sample[0] as f64 / 255.0,
+                }
+            })
+            .collect();
+        Self::new(size, channels, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trilinear_sample() {
+        let grid = VoxelGrid::new([2, 1, 1], 1, vec![0.0, 1.0]).unwrap();
+        assert_eq!(grid.sample(Point { x: 0.25, y: 0.5, z: 0.5 }, 0), 0.0);
+        assert_eq!(grid.sample(Point { x: 0.5, y: 0.5, z: 0.5 }, 0), 0.5);
+        assert_eq!(grid.sample(Point { x: 1.0, y: 0.5, z: 0.5 }, 0), 1.0);
+        assert_eq!(grid.max_density(), 1.0);
+    }
+
+    #[test]
+    fn test_parse_float_nrrd_with_temperature() {
+        let mut bytes = b"NRRD0004\n# fluid cache\ntype: float\ndimension: 4\nsizes: 2 1 1 2\nencoding: raw\nendian: little\n\n".to_vec();
+        for value in [0.5_f32, 1500.0, 2.0, 900.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let grid = VoxelGrid::parse_nrrd(&bytes, Path::new(".")).unwrap();
+        assert!(grid.has_temperature());
+        assert_eq!(grid.size, [1, 1, 2]);
+        assert_eq!(grid.max_density(), 2.0);
+        assert_eq!(grid.sample(Point { x: 0.5, y: 0.5, z: 0.0 }, 1), 1500.0);
+    }
+
+    #[test]
+    fn test_parse_big_endian_and_bytes() {
+        let mut bytes = b"NRRD0004\ntype: double\nsizes: 1 1 1\nendian: big\n\n".to_vec();
+        bytes.extend_from_slice(&3.5_f64.to_be_bytes());
+        let grid = VoxelGrid::parse_nrrd(&bytes, Path::new(".")).unwrap();
+        assert_eq!(grid.sample(Point::default(), 0), 3.5);
+
+        let mut bytes = b"NRRD0004\ntype: uchar\nsizes: 1 1 1\nencoding: raw\n\n".to_vec();
+        bytes.push(255);
+        let grid = VoxelGrid::parse_nrrd(&bytes, Path::new(".")).unwrap();
+        assert_eq!(grid.max_density(), 1.0);
+    }
+
+    #[test]
+    fn test_parse_rejects_unsupported() {
+        assert!(VoxelGrid::parse_nrrd(b"P6 1 1 255\n", Path::new(".")).is_err());
+        assert!(VoxelGrid::parse_nrrd(b"NRRD0004\ntype: float\nsizes: 1 1 1\nencoding: gzip\n\n", Path::new(".")).is_err());
+        assert!(VoxelGrid::parse_nrrd(b"NRRD0004\ntype: float\nsizes: 2 2 2\n\n\0\0\0\0", Path::new(".")).is_err());
+        // sizes whose product overflows
+        let huge = format!("NRRD0004\ntype: double\nsizes: 2 {0} {0} {0}\n\n\0", usize::MAX / 2 + 1);
+        assert!(VoxelGrid::parse_nrrd(huge.as_bytes(), Path::new(".")).is_err());
+        // and sizes whose values fit but whose bytes don't
+        let wide = format!("NRRD0004\ntype: double\nsizes: 1 1 {}\n\n\0", usize::MAX / 4);
+        assert!(VoxelGrid::parse_nrrd(wide.as_bytes(), Path::new(".")).is_err());
+    }
+}
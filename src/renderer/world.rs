@@ -1,37 +1,100 @@
+use std::sync::Arc;
+use crate::renderer::aabb::Aabb;
 use crate::renderer::hit::{Hittable, HitRecord};
+use crate::renderer::material::Material;
 use crate::renderer::ray::Ray;
+use crate::renderer::sampler::Sampler;
+
+// numbers materials from 1 in the order the objects using them come in, telling them apart by
+// identity; objects without a material get 0
+#[derive(Default)]
+pub(crate) struct MaterialIds {
+    materials: Vec<Arc<dyn Material>>,
+}
+
+impl MaterialIds {
+    pub fn id(&mut self, object: &dyn Hittable) -> u32 {
+        let Some(material) = object.material() else {
+            return 0;
+        };
+        let index = match self.materials.iter().position(|known| Arc::ptr_eq(known, material)) {
+            Some(index) => index,
+            None => {
+                self.materials.push(material.clone());
+                self.materials.len() - 1
+            }
+        };
+        index as u32 + 1
+    }
+
+    pub fn count(&self) -> u32 {
+        self.materials.len() as u32
+    }
+}
 
 pub(crate) struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
+    material_ids: Vec<u32>,
+    materials: MaterialIds,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         Self {
             objects: Vec::new(),
+            material_ids: Vec::new(),
+            materials: MaterialIds::default(),
         }
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        self.material_ids.push(self.materials.id(object.as_ref()));
         self.objects.push(object);
     }
 
+    pub fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
+
     #[allow(dead_code)]
     pub fn clear(&mut self) {
-        self.objects = Vec::new();
+        *self = Self::new();
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitRecord> {
         let mut temp_record = None;
         let mut closest_so_far = t_max;
-        for object in &self.objects {
-            if let Some(record) = object.hit(ray, t_min, closest_so_far) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(record) = object.hit(ray, t_min, closest_so_far, sampler) {
                 closest_so_far = record.t;
-                temp_record = Some(record);
+                temp_record = Some((record, index));
+            }
+        }
+        temp_record.map(|(mut record, index)| {
+            record.object_id = index as u32 + 1;
+            record.material_id = self.material_ids[index];
+            record
+        })
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let mut transmittance = 1.0;
+        for object in &self.objects {
+            transmittance *= object.transmittance(ray, t_min, t_max, sampler);
+            if transmittance == 0.0 {
+                break;
             }
         }
-        temp_record
+        transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.objects.iter().try_fold(Aabb::empty(), |bounds, object| Some(bounds.union(&object.bounding_box()?)))
+    }
+
+    fn bounds_during(&self, start: f64, end: f64) -> Option<Aabb> {
+        self.objects.iter().try_fold(Aabb::empty(), |bounds, object| Some(bounds.union(&object.bounds_during(start, end)?)))
     }
 }
\ No newline at end of file